@@ -0,0 +1,146 @@
+use regex::Regex;
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::OnceLock,
+};
+
+use crate::error::{CompiError, Result};
+use crate::logging;
+use crate::task::Task;
+use crate::util::{normalize_path_lexical, patterns_match};
+
+static ENABLED: OnceLock<()> = OnceLock::new();
+static OPEN_CALL: OnceLock<Regex> = OnceLock::new();
+
+const MAX_LISTED: usize = 10;
+
+/// Turns on input auditing, which runs commands under `strace` and reports
+/// files they read that are not declared as inputs.
+pub fn enable() -> Result<()> {
+    if !cfg!(target_os = "linux") {
+        return Err(CompiError::Task(
+            "--audit-inputs is only supported on Linux".to_string(),
+        ));
+    }
+
+    let available = Command::new("strace")
+        .arg("-V")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if !available {
+        return Err(CompiError::Task(
+            "--audit-inputs requires strace to be installed and on PATH".to_string(),
+        ));
+    }
+
+    let _ = ENABLED.set(());
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.get().is_some()
+}
+
+/// Where the trace of `task` is written, if it should be audited. Only shell
+/// commands of tasks that declare inputs are traced.
+pub fn log_path(task: &Task) -> Option<PathBuf> {
+    if !is_enabled() || task.inputs.is_empty() || task.executor.is_some() {
+        return None;
+    }
+
+    let id: String = task
+        .id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    Some(env::temp_dir().join(format!("compi-audit-{}-{}.log", process::id(), id)))
+}
+
+/// Puts `strace` in front of the task's own wrapper, so every process the
+/// command starts is traced.
+pub fn wrap(wrapper: Option<&str>, log: &Path) -> String {
+    let strace = format!(
+        "strace -f -qq -e trace=open,openat -o {}",
+        shell_words::quote(&log.to_string_lossy())
+    );
+    match wrapper {
+        Some(wrapper) => format!("{} {}", strace, wrapper),
+        None => strace,
+    }
+}
+
+/// Reads the trace written to `log` and warns about project files the task
+/// read that none of its inputs or outputs cover. The log is removed.
+pub fn report(task: &Task, log: &Path) {
+    let contents = fs::read_to_string(log);
+    let _ = fs::remove_file(log);
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(e) => {
+            logging::warn(format!(
+                "Could not read input audit for task '{}': {}",
+                task.id, e
+            ));
+            return;
+        }
+    };
+
+    let Ok(root) = env::current_dir() else {
+        return;
+    };
+    let base = task.dir.clone().unwrap_or_else(|| root.clone());
+
+    let undeclared: BTreeSet<PathBuf> = opened_files(&contents)
+        .into_iter()
+        .filter_map(|path| {
+            let path = normalize_path_lexical(&base.join(path));
+            let relative = path.strip_prefix(&root).ok()?.to_path_buf();
+            path.is_file().then_some(relative)
+        })
+        .filter(|path| !path.starts_with(".git"))
+        .filter(|path| !patterns_match(&task.inputs, path) && !patterns_match(&task.outputs, path))
+        .collect();
+
+    if undeclared.is_empty() {
+        logging::debug(format!("Task '{}': no undeclared inputs read", task.id));
+        return;
+    }
+
+    let mut listed: Vec<String> = undeclared
+        .iter()
+        .take(MAX_LISTED)
+        .map(|p| p.display().to_string())
+        .collect();
+    if undeclared.len() > MAX_LISTED {
+        listed.push(format!("and {} more", undeclared.len() - MAX_LISTED));
+    }
+    logging::warn(format!(
+        "Task '{}' read {} files not declared as inputs: {}",
+        task.id,
+        undeclared.len(),
+        listed.join(", ")
+    ));
+}
+
+/// Paths opened for reading, skipping failed calls and directories.
+fn opened_files(trace: &str) -> Vec<PathBuf> {
+    let open_call = OPEN_CALL.get_or_init(|| {
+        Regex::new(r#"\bopen(?:at2?)?\((?:[^,]+, )?"((?:[^"\\]|\\.)*)", ([A-Z_|]+)"#).unwrap()
+    });
+
+    trace
+        .lines()
+        .filter(|line| !line.contains("= -1 "))
+        .filter_map(|line| open_call.captures(line))
+        .filter(|caps| {
+            let flags = &caps[2];
+            !flags.contains("O_WRONLY") && !flags.contains("O_DIRECTORY")
+        })
+        .map(|caps| PathBuf::from(&caps[1]))
+        .collect()
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs::File, io::BufReader, time::Duration};
+
+use crate::cache::{cache_file_path, write_json};
+use crate::logging;
+
+const BASELINE_FILENAME: &str = "compi_bench.json";
+
+/// Durations of the runs of a benchmarked task.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BenchStats {
+    pub iterations: usize,
+    pub min_ms: u64,
+    pub mean_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+impl BenchStats {
+    pub fn from_durations(durations: &[Duration]) -> Option<Self> {
+        let mut millis: Vec<u64> = durations.iter().map(|d| d.as_millis() as u64).collect();
+        millis.sort_unstable();
+
+        let (min, max) = (*millis.first()?, *millis.last()?);
+        let mean = millis.iter().sum::<u64>() / millis.len() as u64;
+        // Nearest-rank percentile: the smallest value at least 95% of runs do not exceed.
+        let rank = (millis.len() * 95).div_ceil(100).max(1);
+
+        Some(Self {
+            iterations: millis.len(),
+            min_ms: min,
+            mean_ms: mean,
+            p95_ms: millis[rank - 1],
+            max_ms: max,
+        })
+    }
+}
+
+/// Saved results per task that later benchmarks are compared against.
+pub fn load_baselines(cache_dir: Option<&str>, config_path: &str) -> HashMap<String, BenchStats> {
+    let path = cache_file_path(cache_dir, config_path, BASELINE_FILENAME);
+    File::open(&path)
+        .ok()
+        .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_baselines(
+    baselines: &HashMap<String, BenchStats>,
+    cache_dir: Option<&str>,
+    config_path: &str,
+) {
+    let path = cache_file_path(cache_dir, config_path, BASELINE_FILENAME);
+    write_json(&path, baselines, "benchmark baseline");
+}
+
+pub fn show_bench(task_id: &str, stats: &BenchStats, baseline: Option<&BenchStats>) {
+    logging::info(format!(
+        "Benchmark '{}': {} iterations",
+        task_id, stats.iterations
+    ));
+
+    let rows = [
+        ("min", stats.min_ms, baseline.map(|b| b.min_ms)),
+        ("mean", stats.mean_ms, baseline.map(|b| b.mean_ms)),
+        ("p95", stats.p95_ms, baseline.map(|b| b.p95_ms)),
+        ("max", stats.max_ms, baseline.map(|b| b.max_ms)),
+    ];
+    for (label, value, base) in rows {
+        let comparison = match base {
+            Some(base) if base > 0 => format!(
+                "  {:+.1}% vs baseline {}",
+                (value as f64 - base as f64) / base as f64 * 100.0,
+                seconds(base)
+            ),
+            Some(base) => format!("  baseline {}", seconds(base)),
+            None => String::new(),
+        };
+        logging::info(format!("  {:<5} {}{}", label, seconds(value), comparison));
+    }
+}
+
+fn seconds(millis: u64) -> String {
+    format!("{:.2}s", millis as f64 / 1000.0)
+}
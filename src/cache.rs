@@ -1,3 +1,4 @@
+use serde::Serialize;
 use std::{
     collections::HashSet,
     fs::{self, File},
@@ -5,13 +6,17 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use crate::logging;
+use crate::util::resolve_path;
+
 const DEFAULT_CACHE_DIR: &str = ".";
 const CACHE_FILENAME: &str = "compi_cache.json";
 
+/// Hashes of task inputs that completed successfully.
 pub type Cache = HashSet<String>;
 
 pub fn load_cache(cache_dir: Option<&str>, config_path: &str) -> Cache {
-    let cache_path = get_cache_path(cache_dir, config_path);
+    let cache_path = cache_path(cache_dir, config_path);
 
     let file = match File::open(&cache_path) {
         Ok(file) => file,
@@ -23,40 +28,65 @@ pub fn load_cache(cache_dir: Option<&str>, config_path: &str) -> Cache {
 }
 
 pub fn save_cache(cache: &Cache, cache_dir: Option<&str>, config_path: &str) {
-    let cache_path = get_cache_path(cache_dir, config_path);
+    let cache_path = cache_path(cache_dir, config_path);
 
-    if let Some(parent) = cache_path.parent() {
-        if let Err(e) = fs::create_dir_all(parent) {
-            eprintln!("Warning: Failed to create cache directory: {}", e);
-            return;
-        }
+    if let Some(parent) = cache_path.parent()
+        && let Err(e) = fs::create_dir_all(parent)
+    {
+        logging::warn(format!("Failed to create cache directory: {}", e));
+        return;
     }
 
     match File::create(&cache_path) {
         Ok(file) => {
             let writer = BufWriter::new(file);
             if let Err(e) = serde_json::to_writer_pretty(writer, cache) {
-                eprintln!("Warning: Failed to write cache file: {}", e);
+                logging::warn(format!("Failed to write cache file: {}", e));
+            }
+        }
+        Err(e) => {
+            logging::warn(format!("Failed to open cache file for writing: {}", e));
+        }
+    }
+}
+
+pub(crate) fn cache_path(cache_dir: Option<&str>, config_path: &str) -> PathBuf {
+    cache_file_path(cache_dir, config_path, CACHE_FILENAME)
+}
+
+/// Writes `value` to a JSON file in the cache directory, warning on failure.
+pub(crate) fn write_json(path: &Path, value: &impl Serialize, name: &str) {
+    if let Some(parent) = path.parent()
+        && let Err(e) = fs::create_dir_all(parent)
+    {
+        logging::warn(format!("Failed to create cache directory: {}", e));
+        return;
+    }
+
+    match File::create(path) {
+        Ok(file) => {
+            let writer = BufWriter::new(file);
+            if let Err(e) = serde_json::to_writer(writer, value) {
+                logging::warn(format!("Failed to write {} file: {}", name, e));
             }
         }
         Err(e) => {
-            eprintln!("Warning: Failed to open cache file for writing: {}", e);
+            logging::warn(format!("Failed to open {} file for writing: {}", name, e));
         }
     }
 }
 
-fn get_cache_path(cache_dir: Option<&str>, config_path: &str) -> PathBuf {
+/// Location of a file in the cache directory, which is relative to the config
+/// file unless it is absolute or starts with `~`.
+pub(crate) fn cache_file_path(
+    cache_dir: Option<&str>,
+    config_path: &str,
+    filename: &str,
+) -> PathBuf {
     let config_parent = Path::new(config_path)
         .parent()
         .unwrap_or_else(|| Path::new("."));
 
     let cache_dir = cache_dir.unwrap_or(DEFAULT_CACHE_DIR);
-
-    let cache_dir_path = if Path::new(cache_dir).is_absolute() {
-        PathBuf::from(cache_dir)
-    } else {
-        config_parent.join(cache_dir)
-    };
-
-    cache_dir_path.join(CACHE_FILENAME)
+    resolve_path(Path::new(cache_dir), config_parent).join(filename)
 }
@@ -0,0 +1,110 @@
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A CI system compi is running in, detected from the variables it sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ci {
+    GithubActions,
+    GitlabCi,
+    Buildkite,
+    /// Any other system that sets `CI`.
+    Other,
+}
+
+impl Ci {
+    /// Checks the platform-specific variables first, then the generic `CI`,
+    /// which most systems set. `CI=false` or `CI=0` counts as not in CI.
+    pub fn detect() -> Option<Self> {
+        if is_true("GITHUB_ACTIONS") {
+            Some(Ci::GithubActions)
+        } else if is_true("GITLAB_CI") {
+            Some(Ci::GitlabCi)
+        } else if is_true("BUILDKITE") {
+            Some(Ci::Buildkite)
+        } else if env::var("CI").is_ok_and(|v| !v.is_empty() && v != "false" && v != "0") {
+            Some(Ci::Other)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Ci::GithubActions => "GitHub Actions",
+            Ci::GitlabCi => "GitLab CI",
+            Ci::Buildkite => "Buildkite",
+            Ci::Other => "CI",
+        }
+    }
+
+    /// The line that starts a collapsible section of the job log titled
+    /// `title`, on systems that fold logs.
+    pub fn section_start(self, title: &str) -> Option<String> {
+        match self {
+            Ci::GithubActions => Some(format!("::group::{}", escape_workflow_data(title))),
+            Ci::GitlabCi => Some(format!(
+                "\x1b[0Ksection_start:{}:{}[collapsed=true]\r\x1b[0K{}",
+                unix_time(),
+                section_name(title),
+                title
+            )),
+            // Buildkite folds everything up to the next `---` line.
+            Ci::Buildkite => Some(format!("--- {}", title)),
+            Ci::Other => None,
+        }
+    }
+
+    /// The line that ends the section started by [`Ci::section_start`].
+    pub fn section_end(self, title: &str) -> Option<String> {
+        match self {
+            Ci::GithubActions => Some("::endgroup::".to_string()),
+            Ci::GitlabCi => Some(format!(
+                "\x1b[0Ksection_end:{}:{}\r\x1b[0K",
+                unix_time(),
+                section_name(title)
+            )),
+            Ci::Buildkite | Ci::Other => None,
+        }
+    }
+}
+
+/// The CI system compi is running in, if any.
+pub fn detect() -> Option<Ci> {
+    Ci::detect()
+}
+
+fn is_true(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| value == "true")
+}
+
+/// GitLab section names may only contain letters, digits, `_`, `.` and `-`.
+fn section_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("compi_{}", name)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Escapes a value for a GitHub Actions workflow command.
+pub(crate) fn escape_workflow_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
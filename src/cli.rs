@@ -1,16 +1,39 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
-use crate::output::OutputMode;
+use compi::export::{CiFormat, JobGrouping};
+use compi::lint::{LintFormat, Rule};
+use compi::logging::LogFormat;
+use compi::output::{ColorChoice, OutputMode};
+use compi::report::ReportSpec;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Configuration file to use
-    #[arg(short = 'f', long = "file", default_value = "compi.toml")]
-    pub file: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Configuration file to use. Repeat it, or separate files with commas,
+    /// to lay overlays such as compi.local.toml over the first
+    #[arg(
+        short = 'f',
+        long = "file",
+        default_value = "compi.toml",
+        value_delimiter = ',',
+        global = true
+    )]
+    pub file: Vec<String>,
+
+    /// Profile whose env files to load, defaults to $COMPI_PROFILE
+    #[arg(short = 'p', long = "profile", global = true)]
+    pub profile: Option<String>,
+
+    /// Fail when a ${VAR} reference has no definition
+    #[arg(long = "strict", global = true)]
+    pub strict: bool,
 
     /// Enable verbose output
-    #[arg(short = 'v', long = "verbose")]
+    #[arg(short = 'v', long = "verbose", global = true)]
     pub verbose: bool,
 
     /// Remove outputs after successful task execution
@@ -18,7 +41,7 @@ pub struct Cli {
     pub rm: bool,
 
     /// Override number of worker threads for parallel execution
-    #[arg(short = 'j', long = "workers")]
+    #[arg(short = 'j', long = "workers", global = true)]
     pub workers: Option<usize>,
 
     /// Override default timeout (e.g., "5m", "30s", "1h30m")
@@ -37,6 +60,227 @@ pub struct Cli {
     #[arg(long = "output", value_enum)]
     pub output: Option<OutputMode>,
 
-    /// Task to run, runs default task or all tasks if not specified
+    /// How to format compi's own log messages and events
+    #[arg(long = "log-format", value_enum, global = true)]
+    pub log_format: Option<LogFormat>,
+
+    /// When to use colored output
+    #[arg(long = "color", value_enum, global = true)]
+    pub color: Option<ColorChoice>,
+
+    /// Write a report of the run, e.g. "junit=report.xml" or "json=report.json" (can be repeated)
+    #[arg(long = "report", value_name = "FORMAT=PATH")]
+    pub report: Vec<ReportSpec>,
+
+    /// Mirror everything compi prints, including all task output, to this file
+    #[arg(long = "log-file", value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Only run tasks whose inputs changed since this git ref, plus their dependents
+    #[arg(long = "since", value_name = "REF")]
+    pub since: Option<String>,
+
+    /// Only run the tasks that did not complete in the last run
+    #[arg(long = "resume")]
+    pub resume: bool,
+
+    /// Trace the files each task reads and warn about those not declared as inputs (Linux, needs strace)
+    #[arg(long = "audit-inputs")]
+    pub audit_inputs: bool,
+
+    /// Write a Chrome/Perfetto trace of the run to this file
+    #[arg(long = "trace", value_name = "PATH")]
+    pub trace: Option<PathBuf>,
+
+    /// Run tasks that ask for confirmation without asking
+    #[arg(short = 'y', long = "yes")]
+    pub yes: bool,
+
+    /// Run every task, even when [config] sets a default
+    #[arg(long = "all", global = true)]
+    pub all: bool,
+
+    /// Task to run, runs the default tasks if not specified
     pub task: Option<String>,
 }
+
+impl Cli {
+    /// The first config file, which the cache, history and relative paths
+    /// belong to.
+    pub fn config_path(&self) -> &str {
+        self.file.first().map_or("compi.toml", String::as_str)
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a command that is not declared in the config, skipping it like a
+    /// task when its inputs are unchanged
+    Exec {
+        /// Files the command reads, as paths or globs (can be repeated)
+        #[arg(long = "inputs", value_delimiter = ',')]
+        inputs: Vec<PathBuf>,
+
+        /// Files the command writes (can be repeated)
+        #[arg(long = "outputs", value_delimiter = ',')]
+        outputs: Vec<PathBuf>,
+
+        /// Name the command is shown and recorded in the history under
+        #[arg(long = "id", default_value = "exec")]
+        id: String,
+
+        /// Stop the command after this long (e.g., "5m", "30s")
+        #[arg(short = 't', long = "timeout")]
+        timeout: Option<String>,
+
+        /// Command to run, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// List every task with whether it is cached or stale and its last result
+    List,
+    /// Show recorded durations and statuses of previous runs
+    History {
+        /// Task to show the full history for
+        task: Option<String>,
+    },
+    /// Show the longest dependency chain by recorded durations
+    CriticalPath {
+        /// Task to analyze, defaults to the default task or all tasks
+        task: Option<String>,
+    },
+    /// Show a task's dependencies as a tree, with whether each is cached or
+    /// stale and its last result
+    Tree {
+        /// Task to show, defaults to the default task or all tasks
+        task: Option<String>,
+
+        /// Draw the tree with ASCII characters only
+        #[arg(long = "ascii")]
+        ascii: bool,
+    },
+    /// Generate a CI pipeline from the task graph
+    Export {
+        /// CI system to generate configuration for
+        #[arg(value_enum)]
+        format: CiFormat,
+
+        /// Task to export, defaults to the default task or all tasks
+        task: Option<String>,
+
+        /// Create one job per task or one per dependency level
+        #[arg(long = "per", value_enum, default_value_t)]
+        per: JobGrouping,
+
+        /// Write the pipeline to this file instead of stdout
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Run a task repeatedly without caching and report its durations
+    Bench {
+        /// Task to benchmark, after running its dependencies once
+        task: String,
+
+        /// Number of times to run the task
+        #[arg(short = 'n', long = "iterations", default_value_t = 10)]
+        iterations: usize,
+
+        /// Store the results as the baseline later benchmarks are compared against
+        #[arg(long = "save-baseline")]
+        save_baseline: bool,
+    },
+    /// Write the tasks a run would execute, and why each one runs or is skipped, as JSON
+    Plan {
+        /// Task to plan, defaults to the default task or all tasks
+        task: Option<String>,
+
+        /// Write the plan to this file instead of stdout
+        #[arg(short = 'o', long = "out")]
+        out: Option<PathBuf>,
+    },
+    /// Run exactly the tasks of a plan written by `compi plan`
+    Apply {
+        /// Plan file to run
+        plan: PathBuf,
+    },
+    /// Show the variables loaded from env files and which file set each one
+    Env,
+    /// Check the project for common problems, such as inputs that match no
+    /// files or tasks that rerun every time, and suggest fixes
+    Doctor,
+    /// Report unused variables, unreachable tasks, redundant dependencies and
+    /// subsumed inputs, failing if there are any
+    Lint {
+        /// How to print the findings
+        #[arg(long, value_enum, default_value_t)]
+        format: LintFormat,
+
+        /// Rule not to report (can be repeated)
+        #[arg(long, value_enum)]
+        allow: Vec<Rule>,
+    },
+    /// Show which tasks produce and read a file, and which would rerun if it changed
+    Why {
+        /// File to look up, relative to the current directory
+        path: PathBuf,
+    },
+    /// Keep running and start each task with a `schedule` whenever it is due
+    Daemon,
+    /// Update the config file to the current config version
+    Migrate,
+    /// Replace this binary with the latest release from GitHub, checked against
+    /// its SHA-256 checksum (releases are not signed)
+    SelfUpdate {
+        /// Only check whether a newer release exists, failing if one does
+        #[arg(long)]
+        check: bool,
+    },
+    /// Generate compi tasks from another build tool's configuration
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ImportSource {
+    /// Convert the explicit rules of a Makefile into tasks
+    Make {
+        /// Makefile to read
+        #[arg(default_value = "Makefile")]
+        makefile: PathBuf,
+
+        /// Write the generated configuration to this file instead of stdout
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Convert a file with the `import` hook of a configured plugin
+    Plugin {
+        /// Name of the plugin in `[config] plugins`
+        name: String,
+
+        /// File to convert
+        path: PathBuf,
+
+        /// Write the generated configuration to this file instead of stdout
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn task_names_cannot_shadow_subcommands() {
+        let mut names: Vec<String> = Cli::command()
+            .get_subcommands()
+            .map(|command| command.get_name().to_string())
+            .chain(["help".to_string()])
+            .collect();
+        names.sort();
+        assert_eq!(names, compi::task::SUBCOMMANDS);
+    }
+}
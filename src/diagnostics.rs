@@ -0,0 +1,209 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, sync::OnceLock};
+
+use crate::{logging, mask, spool::Captured};
+
+/// Matchers that exist without being declared in `[config] problem_matchers`.
+pub const BUILTIN_MATCHERS: [&str; 3] = ["rustc", "tsc", "gcc"];
+
+static MATCHERS: OnceLock<Vec<ProblemMatcher>> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+
+    fn parse(text: &str) -> Self {
+        let text = text.to_ascii_lowercase();
+        if text.starts_with("err") || text.starts_with("fatal") {
+            Severity::Error
+        } else if text.starts_with("warn") {
+            Severity::Warning
+        } else {
+            Severity::Note
+        }
+    }
+}
+
+/// A problem found in a task's output, such as a compiler error.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub task: String,
+    pub file: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// `file:line:column`, leaving out what the matcher did not capture.
+    pub fn location(&self) -> String {
+        let mut location = self.file.clone();
+        for number in [self.line, self.column].into_iter().map_while(|n| n) {
+            location.push_str(&format!(":{}", number));
+        }
+        location
+    }
+}
+
+/// A problem matcher as declared in `[config] problem_matchers`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProblemMatcherConfig {
+    pub name: String,
+    pub pattern: String,
+    /// Severity of matches without a `severity` group.
+    #[serde(default)]
+    pub severity: Option<Severity>,
+}
+
+/// A regular expression run over a task's output. Its named groups `file`
+/// and `message` are required, `line`, `column` and `severity` optional.
+#[derive(Clone, Debug)]
+pub struct ProblemMatcher {
+    pub name: String,
+    regex: Regex,
+    severity: Severity,
+}
+
+impl ProblemMatcher {
+    pub fn new(config: &ProblemMatcherConfig) -> Result<Self, String> {
+        // Multi-line mode, so that `^` and `$` match at every line of the output.
+        let regex = Regex::new(&format!("(?m){}", config.pattern))
+            .map_err(|e| format!("invalid pattern: {}", e))?;
+
+        let groups: Vec<&str> = regex.capture_names().flatten().collect();
+        for required in ["file", "message"] {
+            if !groups.contains(&required) {
+                return Err(format!("pattern has no '{}' group", required));
+            }
+        }
+
+        Ok(Self {
+            name: config.name.clone(),
+            regex,
+            severity: config.severity.unwrap_or(Severity::Error),
+        })
+    }
+
+    fn find(&self, task_id: &str, output: &str, dir: Option<&Path>) -> Vec<Diagnostic> {
+        self.regex
+            .captures_iter(output)
+            .filter_map(|captures| {
+                let file = captures.name("file")?.as_str().trim();
+                let file = match dir {
+                    Some(dir) if Path::new(file).is_relative() => {
+                        dir.join(file).display().to_string()
+                    }
+                    _ => file.to_string(),
+                };
+                let number = |group| captures.name(group)?.as_str().parse().ok();
+
+                Some(Diagnostic {
+                    task: task_id.to_string(),
+                    file,
+                    line: number("line"),
+                    column: number("column"),
+                    severity: captures
+                        .name("severity")
+                        .map(|s| Severity::parse(s.as_str()))
+                        .unwrap_or(self.severity),
+                    message: captures.name("message")?.as_str().trim().to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Makes the matchers from `[config] problem_matchers` available to tasks,
+/// replacing built-in matchers of the same name.
+pub fn init(custom: Vec<ProblemMatcher>) {
+    let _ = MATCHERS.set(custom.into_iter().chain(builtin_matchers()).collect());
+}
+
+fn matchers() -> &'static [ProblemMatcher] {
+    MATCHERS.get_or_init(builtin_matchers)
+}
+
+fn builtin_matchers() -> Vec<ProblemMatcher> {
+    let builtin = |name: &str, pattern: &str| {
+        ProblemMatcher::new(&ProblemMatcherConfig {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            severity: None,
+        })
+        .expect("built-in problem matchers are valid")
+    };
+
+    vec![
+        // error[E0425]: cannot find value `x` in this scope
+        //  --> src/main.rs:2:5
+        builtin(
+            "rustc",
+            r"^(?P<severity>error|warning)(?:\[\w+\])?: (?P<message>.+)\r?\n[ \t]*--> (?P<file>.+?):(?P<line>\d+):(?P<column>\d+)\r?$",
+        ),
+        // src/app.ts(3,7): error TS2322: Type 'string' is not assignable to type 'number'.
+        builtin(
+            "tsc",
+            r"^(?P<file>[^\s(][^(\r\n]*)\((?P<line>\d+),(?P<column>\d+)\): (?P<severity>error|warning) (?P<message>TS\d+: .+)$",
+        ),
+        // src/main.c:4:5: error: 'x' undeclared (first use in this function)
+        builtin(
+            "gcc",
+            r"^(?P<file>[^\s:][^:\r\n]*):(?P<line>\d+):(?:(?P<column>\d+):)? (?:fatal )?(?P<severity>error|warning|note): (?P<message>.+)$",
+        ),
+    ]
+}
+
+/// Runs the named matchers over a task's output. `dir` is prepended to
+/// relative paths, so that they are relative to the config file.
+pub fn extract(
+    task_id: &str,
+    names: &[String],
+    dir: Option<&Path>,
+    stdout: &Captured,
+    stderr: &Captured,
+) -> Vec<Diagnostic> {
+    if names.is_empty() {
+        return Vec::new();
+    }
+
+    let mut diagnostics = Vec::new();
+    for output in [stdout, stderr] {
+        // The whole stream, as errors are often at the end of a long build.
+        let output = output.to_vec().unwrap_or_else(|e| {
+            logging::warn(format!(
+                "Could not read the output of task '{}' for diagnostics: {}",
+                task_id, e
+            ));
+            output.head().to_vec()
+        });
+        // Diagnostics are only shown, so they come from the masked output.
+        let output = String::from_utf8_lossy(&output);
+        let output = mask::text(&output);
+        for name in names {
+            let Some(matcher) = matchers().iter().find(|m| &m.name == name) else {
+                continue;
+            };
+            for diagnostic in matcher.find(task_id, &output, dir) {
+                if !diagnostics.contains(&diagnostic) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+    }
+    diagnostics
+}
@@ -0,0 +1,356 @@
+use glob::glob;
+use serde::de::DeserializeOwned;
+use std::{
+    env,
+    fs::File,
+    io::{BufReader, ErrorKind},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::cache::{self, Cache};
+use crate::execution::TaskStatus;
+use crate::history::{self, History};
+use crate::plan::{PlannedTask, RunReason};
+use crate::task::Task;
+use crate::util::{exclusion, expand_globs_any, files_in_dir, is_glob_pattern};
+
+/// Recent runs a task with inputs must all have executed in to be reported
+/// as rerunning every time.
+const RERUN_RUNS: usize = 3;
+/// How far in the future a modification time may be before it counts as skew,
+/// allowing for filesystems with coarse timestamps.
+const CLOCK_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Something found by `compi doctor`, with what to do about it.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub message: String,
+    pub fix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindingKind {
+    Problem,
+    /// Behavior that may well be intended, such as a task without inputs.
+    Note,
+}
+
+impl Finding {
+    fn new(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            kind: FindingKind::Problem,
+            message: message.into(),
+            fix: fix.into(),
+        }
+    }
+
+    fn note(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            kind: FindingKind::Note,
+            ..Self::new(message, fix)
+        }
+    }
+}
+
+/// What `compi doctor` looks at.
+pub struct Checkup<'a> {
+    pub tasks: &'a [Task],
+    /// Whether each task would run now, and why.
+    pub planned: &'a [PlannedTask],
+    pub history: &'a History,
+    /// `cache_dir` and config path of the project and of each workspace member.
+    pub state: Vec<(Option<&'a str>, &'a str)>,
+    /// `[config] wrapper`, which run-level hooks use.
+    pub wrapper: Option<&'a str>,
+}
+
+pub fn examine(checkup: &Checkup) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    findings.extend(check_shell(checkup.tasks, checkup.wrapper));
+    for &(cache_dir, config_path) in &checkup.state {
+        findings.extend(check_state_file::<Cache>(
+            &cache::cache_path(cache_dir, config_path),
+            "Cache",
+            "every task with inputs reruns",
+        ));
+        findings.extend(check_state_file::<History>(
+            &history::history_path(cache_dir, config_path),
+            "History",
+            "recorded durations and statuses are ignored",
+        ));
+    }
+    for task in checkup.tasks {
+        findings.extend(check_inputs(task));
+        findings.extend(check_outputs(task, checkup.history));
+        findings.extend(check_reruns(task, checkup.planned, checkup.history));
+    }
+    findings.extend(check_clock(checkup.tasks));
+    findings
+}
+
+fn check_shell(tasks: &[Task], wrapper: Option<&str>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let shell = if cfg!(target_os = "windows") {
+        "cmd"
+    } else {
+        "sh"
+    };
+    if find_program(shell).is_none() {
+        findings.push(Finding::new(
+            format!(
+                "The shell '{}' was not found on PATH, so no command can run",
+                shell
+            ),
+            format!(
+                "Install it or add its directory to PATH (PATH is '{}')",
+                path_var()
+            ),
+        ));
+    }
+
+    let mut wrappers: Vec<(&str, String)> = Vec::new();
+    if let Some(wrapper) = wrapper.filter(|w| !w.is_empty()) {
+        wrappers.push((wrapper, "[config] wrapper".to_string()));
+    }
+    for task in tasks {
+        if let Some(wrapper) = task.wrapper.as_deref().filter(|w| !w.is_empty())
+            && !wrappers.iter().any(|(w, _)| *w == wrapper)
+        {
+            wrappers.push((wrapper, format!("the wrapper of task '{}'", task.id)));
+        }
+    }
+    for (wrapper, owner) in wrappers {
+        let program = match shell_words::split(wrapper) {
+            Ok(words) => words.into_iter().next(),
+            Err(e) => {
+                findings.push(Finding::new(
+                    format!(
+                        "{} '{}' cannot be parsed: {}",
+                        capitalize(&owner),
+                        wrapper,
+                        e
+                    ),
+                    "Fix the quoting in the wrapper command",
+                ));
+                continue;
+            }
+        };
+        if let Some(program) = program
+            && find_program(&program).is_none()
+        {
+            findings.push(Finding::new(
+                format!("'{}', used by {}, was not found on PATH", program, owner),
+                format!("Install '{}' or change the wrapper", program),
+            ));
+        }
+    }
+    findings
+}
+
+fn check_state_file<T: DeserializeOwned>(path: &Path, name: &str, impact: &str) -> Option<Finding> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return None,
+        Err(e) => {
+            return Some(Finding::new(
+                format!("{} file '{}' cannot be read: {}", name, path.display(), e),
+                format!("Fix its permissions or delete it; until then {}", impact),
+            ));
+        }
+    };
+
+    match serde_json::from_reader::<_, T>(BufReader::new(file)) {
+        Ok(_) => None,
+        Err(e) => Some(Finding::new(
+            format!("{} file '{}' is corrupt: {}", name, path.display(), e),
+            format!(
+                "Delete it, compi writes a new one on the next run; until then {}",
+                impact
+            ),
+        )),
+    }
+}
+
+fn check_inputs(task: &Task) -> Vec<Finding> {
+    task.inputs
+        .iter()
+        .filter(|input| exclusion(input).is_none() && !matches_anything(input))
+        .map(|input| {
+            Finding::new(
+                format!(
+                    "Input '{}' of task '{}' matches no files",
+                    input.display(),
+                    task.id
+                ),
+                "Correct the path or glob, or remove it; changes to files it was meant to cover do not rerun the task",
+            )
+        })
+        .collect()
+}
+
+/// Outputs still missing although the task succeeded when it last ran.
+fn check_outputs(task: &Task, history: &History) -> Vec<Finding> {
+    let last_run = history
+        .entries(&task.id)
+        .iter()
+        .rev()
+        .find(|entry| entry.status != TaskStatus::Skipped);
+    if !last_run.is_some_and(|entry| entry.status == TaskStatus::Success) {
+        return Vec::new();
+    }
+
+    task.outputs
+        .iter()
+        .filter(|output| exclusion(output).is_none() && !matches_anything(output))
+        .map(|output| {
+            Finding::new(
+                format!(
+                    "Output '{}' of task '{}' does not exist, although the task succeeded when it last ran",
+                    output.display(),
+                    task.id
+                ),
+                "Make the command write it or correct the path; set strict_outputs = true to fail the task when an output is missing",
+            )
+        })
+        .collect()
+}
+
+fn check_reruns(task: &Task, planned: &[PlannedTask], history: &History) -> Option<Finding> {
+    let reason = planned
+        .iter()
+        .find(|p| p.id == task.id)
+        .filter(|p| p.run)
+        .map(|p| &p.reason);
+
+    if task.always_run {
+        return Some(Finding::note(
+            format!("Task '{}' always reruns because always_run = true", task.id),
+            "Remove always_run and declare inputs if the task can be skipped when nothing changed",
+        ));
+    }
+    // Generators, captures and piped tasks run every time by design.
+    if task.generator
+        || task.capture.is_some()
+        || task.pipe_from.is_some()
+        || reason == Some(&RunReason::OutputPiped)
+    {
+        return None;
+    }
+    if task.inputs.is_empty() {
+        return Some(Finding::note(
+            format!("Task '{}' always reruns because it has no inputs", task.id),
+            "List the files the command reads in inputs, so the task is skipped when they are unchanged",
+        ));
+    }
+
+    let recent: Vec<_> = history
+        .entries(&task.id)
+        .iter()
+        .rev()
+        .take(RERUN_RUNS)
+        .collect();
+    if recent.len() < RERUN_RUNS || recent.iter().any(|e| e.status == TaskStatus::Skipped) {
+        return None;
+    }
+
+    let reason = reason?;
+    let fix = match reason {
+        RunReason::OutputsMissing => {
+            "Make the command write every declared output, or correct outputs".to_string()
+        }
+        RunReason::OutputsOutdated => "Make sure the command rewrites its outputs, e.g. by touching them at the end, and that no input is written after them".to_string(),
+        RunReason::InputsChanged => "Check for an input the command itself writes, such as a generated or log file, and exclude it with a '!' entry".to_string(),
+        RunReason::DependencyRuns(dependency) => {
+            format!("Fix task '{}' first, which it depends on", dependency)
+        }
+        _ => "Run with --verbose to see why it reruns".to_string(),
+    };
+    Some(Finding::new(
+        format!(
+            "Task '{}' ran in each of its last {} runs although it has inputs; it would run now because: {}",
+            task.id, RERUN_RUNS, reason
+        ),
+        fix,
+    ))
+}
+
+/// Inputs and outputs with modification times in the future, which make
+/// outputs look newer than inputs changed after them.
+fn check_clock(tasks: &[Task]) -> Option<Finding> {
+    let limit = SystemTime::now() + CLOCK_TOLERANCE;
+    let mut files: Vec<PathBuf> = Vec::new();
+    for task in tasks {
+        let paths: Vec<PathBuf> = task.inputs.iter().chain(&task.outputs).cloned().collect();
+        for path in expand_globs_any(&paths).unwrap_or_default() {
+            if path.is_dir() {
+                files.extend(files_in_dir(&path));
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    let skewed: Vec<(PathBuf, Duration)> = files
+        .into_iter()
+        .filter_map(|file| {
+            let modified = file.metadata().ok()?.modified().ok()?;
+            let ahead = modified.duration_since(limit).ok()?;
+            Some((file, ahead + CLOCK_TOLERANCE))
+        })
+        .collect();
+    let (furthest, ahead) = skewed.iter().max_by_key(|(_, ahead)| *ahead)?;
+
+    Some(Finding::new(
+        format!(
+            "{} input or output file{} modified in the future, '{}' by {}",
+            skewed.len(),
+            if skewed.len() == 1 { " was" } else { "s were" },
+            furthest.display(),
+            humantime::format_duration(Duration::from_secs(ahead.as_secs()))
+        ),
+        "Check the system clock, and the clock of the machine the files came from; 'touch' them to reset their times",
+    ))
+}
+
+fn matches_anything(path: &Path) -> bool {
+    let path_str = path.to_string_lossy();
+    if is_glob_pattern(&path_str) {
+        return glob(&path_str).is_ok_and(|mut paths| paths.any(|p| p.is_ok()));
+    }
+    path.exists()
+}
+
+fn find_program(program: &str) -> Option<PathBuf> {
+    if Path::new(program).components().count() > 1 {
+        return Some(PathBuf::from(program)).filter(|p| p.is_file());
+    }
+
+    let extensions: &[&str] = if cfg!(target_os = "windows") {
+        &["", ".exe", ".cmd", ".bat"]
+    } else {
+        &[""]
+    };
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+        extensions
+            .iter()
+            .map(|ext| dir.join(format!("{}{}", program, ext)))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+fn path_var() -> String {
+    env::var("PATH").unwrap_or_default()
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
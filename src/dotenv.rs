@@ -0,0 +1,132 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::error::{CompiError, Result};
+
+static VARS: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// A variable read from an env file.
+#[derive(Debug, Clone)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+    pub source: PathBuf,
+}
+
+impl EnvVar {
+    /// Variables already set in compi's own environment take precedence over env files.
+    pub fn overridden(&self) -> bool {
+        env::var_os(&self.name).is_some()
+    }
+}
+
+/// Sets the variables every command compi runs receives, skipping those
+/// already set in the environment.
+pub fn init(vars: &[EnvVar]) {
+    let vars = vars
+        .iter()
+        .filter(|var| !var.overridden())
+        .map(|var| (var.name.clone(), var.value.clone()))
+        .collect();
+    let _ = VARS.set(vars);
+}
+
+pub fn vars() -> &'static [(String, String)] {
+    VARS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Reads `files` in order, later files overriding earlier ones.
+pub fn load(files: &[PathBuf]) -> Result<Vec<EnvVar>> {
+    let mut vars: Vec<EnvVar> = Vec::new();
+    for file in files {
+        let contents = fs::read_to_string(file).map_err(|e| {
+            CompiError::Parse(format!(
+                "failed to read env file '{}': {}",
+                file.display(),
+                e
+            ))
+        })?;
+
+        for (name, value) in parse(&contents, file)? {
+            vars.retain(|var| var.name != name);
+            vars.push(EnvVar {
+                name,
+                value,
+                source: file.clone(),
+            });
+        }
+    }
+    Ok(vars)
+}
+
+/// Parses `KEY=value` lines. Blank lines, `#` comments and an `export` prefix
+/// are allowed. Single-quoted values are literal, double-quoted values support
+/// `\n`, `\t`, `\"` and `\\`, and unquoted values end at ` #`.
+fn parse(contents: &str, file: &Path) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let invalid = |reason: &str| {
+            CompiError::Parse(format!("{}:{}: {}", file.display(), index + 1, reason))
+        };
+
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid("expected KEY=value"))?;
+        let name = name.trim();
+        if name.is_empty()
+            || name.starts_with(|c: char| c.is_ascii_digit())
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(invalid(&format!("invalid variable name '{}'", name)));
+        }
+
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('\'') {
+            quoted
+                .strip_suffix('\'')
+                .ok_or_else(|| invalid("unterminated single quote"))?
+                .to_string()
+        } else if let Some(quoted) = value.strip_prefix('"') {
+            let quoted = quoted
+                .strip_suffix('"')
+                .ok_or_else(|| invalid("unterminated double quote"))?;
+            unescape(quoted)
+        } else {
+            match value.find(" #") {
+                Some(comment) => value[..comment].trim_end().to_string(),
+                None => value.to_string(),
+            }
+        };
+
+        vars.push((name.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
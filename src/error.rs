@@ -1,6 +1,6 @@
 use std::fmt;
 
-use crate::util::{CommandError, FileError};
+pub use crate::util::{CommandError, FileError};
 
 #[derive(Debug)]
 pub enum CompiError {
@@ -10,6 +10,7 @@ pub enum CompiError {
     File(FileError),
     Command(CommandError),
     Parse(String),
+    Http(String),
 }
 
 impl fmt::Display for CompiError {
@@ -21,6 +22,7 @@ impl fmt::Display for CompiError {
             CompiError::File(err) => write!(f, "File error: {}", err),
             CompiError::Command(err) => write!(f, "Command error: {}", err),
             CompiError::Parse(msg) => write!(f, "Parse error: {}", msg),
+            CompiError::Http(msg) => write!(f, "HTTP error: {}", msg),
         }
     }
 }
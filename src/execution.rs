@@ -1,198 +1,434 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::{
     collections::{HashMap, HashSet},
-    path::PathBuf,
-    sync::Arc,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
     thread,
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::sync::Semaphore;
 
 use crate::{
-    cache,
+    audit, cache,
+    diagnostics::{self, Diagnostic},
     error::CompiError,
+    history::{History, Slowdown},
+    hooks, jobserver, logging,
     output::OutputMode,
-    task::Task,
+    plan::{PlannedTask, RunReason},
+    plugin,
+    process::DEFAULT_GRACE_PERIOD,
+    sandbox::Sandbox,
+    spool::{CommandOutput, Pipe},
+    task::{
+        CriticalPath, Task, TaskSettings,
+        dependency::{
+            ExecutionLevel, calculate_dependency_levels, resolve_file_dependencies, validate_tasks,
+        },
+        find_critical_path,
+        template::substitute_variables,
+    },
+    trace,
     util::{
-        CommandError, cleanup_outputs, expand_globs, hash_files, output_print_lock, parse_timeout,
-        run_command_with_timeout,
+        CommandError, FileError, cleanup_outputs, exclusion, expand_globs, expand_globs_any,
+        files_in_dir, hash_files, is_glob_pattern, output_print_lock, parse_timeout, path_matches,
+        patterns_match, replay_output, run_command_with_timeout, strip_cur_dir,
     },
 };
 
-fn default_workers() -> usize {
+pub fn default_workers() -> usize {
     thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(1)
 }
 
-#[derive(Debug)]
-pub struct ExecutionLevel {
-    pub level: usize,
-    pub task_ids: Vec<String>,
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Success,
+    Failed,
+    TimedOut,
+    Skipped,
 }
 
-pub fn calculate_dependency_levels(tasks: &[Task]) -> Result<Vec<ExecutionLevel>, CompiError> {
-    let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
-    let mut levels: HashMap<String, usize> = HashMap::new();
-
-    for task in tasks {
-        let mut visited = HashSet::new();
-        calculate_task_level(&task.id, &task_map, &mut levels, &mut visited)?;
-    }
-
-    let mut level_groups: HashMap<usize, Vec<String>> = HashMap::new();
-    for (task_id, level) in levels {
-        level_groups.entry(level).or_default().push(task_id);
-    }
-
-    let mut execution_levels: Vec<ExecutionLevel> = level_groups
-        .into_iter()
-        .map(|(level, task_ids)| ExecutionLevel { level, task_ids })
-        .collect();
-
-    execution_levels.sort_by_key(|el| el.level);
-    Ok(execution_levels)
+#[derive(Serialize, Clone, Debug, Default)]
+/// Counts and timings for a whole run, available once [`TaskRunner::run_tasks`] returns.
+pub struct RunSummary {
+    pub executed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Tasks that track inputs and can therefore be skipped when up-to-date.
+    pub cacheable: usize,
+    /// Estimated time saved by skipped tasks, based on their recorded durations.
+    pub time_saved_ms: u64,
+    pub duration_ms: u128,
+    pub slowdowns: Vec<Slowdown>,
+    pub critical_path: Option<CriticalPath>,
 }
 
-fn calculate_task_level(
-    task_id: &str,
-    task_map: &HashMap<&str, &Task>,
-    levels: &mut HashMap<String, usize>,
-    visited: &mut HashSet<String>,
-) -> Result<usize, CompiError> {
-    if let Some(&level) = levels.get(task_id) {
-        return Ok(level);
-    }
-
-    if visited.contains(task_id) {
-        return Err(CompiError::Dependency(format!(
-            "Circular dependency detected involving task '{}'",
-            task_id
-        )));
-    }
-
-    let task = match task_map.get(task_id) {
-        Some(task) => task,
-        None => {
-            levels.insert(task_id.to_string(), 0);
-            return Ok(0);
+impl RunSummary {
+    pub fn hit_rate(&self) -> f64 {
+        if self.cacheable == 0 {
+            return 0.0;
         }
-    };
-
-    if task.dependencies.is_empty() {
-        levels.insert(task_id.to_string(), 0);
-        return Ok(0);
+        self.skipped as f64 / self.cacheable as f64
     }
+}
 
-    visited.insert(task_id.to_string());
+#[derive(Debug, Clone)]
+/// The outcome of a single task, including its captured output.
+pub struct TaskResult {
+    pub task_id: String,
+    pub status: TaskStatus,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+    pub exit_code: Option<i32>,
+    /// Output kept in memory, only the start of it for output beyond the
+    /// memory limit, except for the stdout of captures and generators. Tasks
+    /// that are piped from write their stdout to a [`Pipe`] instead.
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Declared outputs that did not exist after the command succeeded.
+    pub missing_outputs: Vec<PathBuf>,
+    /// Problems the task's matchers found in its output.
+    pub diagnostics: Vec<Diagnostic>,
+}
 
-    let mut max_dep_level = 0;
-    for dep in &task.dependencies {
-        let dep_level = calculate_task_level(dep, task_map, levels, visited)?;
-        max_dep_level = max_dep_level.max(dep_level);
+impl TaskResult {
+    fn new(task_id: &str, status: TaskStatus, duration: Duration) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            status,
+            started_at: SystemTime::now()
+                .checked_sub(duration)
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+            duration,
+            exit_code: None,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            missing_outputs: Vec::new(),
+            diagnostics: Vec::new(),
+        }
     }
+}
 
-    visited.remove(task_id);
-
-    let level = max_dep_level + 1;
-    levels.insert(task_id.to_string(), level);
-    Ok(level)
+/// Settings that change how a [`TaskRunner`] runs tasks.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Remove each task's outputs after it succeeds, as `--rm` does.
+    pub rm: bool,
+    /// Timeout for tasks without their own, such as "5m".
+    pub default_timeout: Option<String>,
+    /// How many tasks run at once, one per CPU by default.
+    pub workers: Option<usize>,
+    /// Keep running tasks that do not depend on a failed one.
+    pub continue_on_failure: bool,
+    /// How task output is shown.
+    pub output_mode: OutputMode,
 }
 
+/// Runs tasks level by level, up to `workers` at a time, skipping tasks whose
+/// inputs match a hash in the cache.
 pub struct TaskRunner<'a> {
-    tasks: &'a [Task],
+    tasks: Vec<Task>,
     cache: &'a mut cache::Cache,
+    history: &'a mut History,
     rm: bool,
-    verbose: bool,
     default_timeout: Option<String>,
     workers: usize,
     continue_on_failure: bool,
     output_mode: OutputMode,
+    summary: RunSummary,
+    results: Vec<TaskResult>,
+    /// Tasks emitted by generators in the current level, keyed by generator.
+    generated: Vec<(String, Vec<Task>)>,
+    /// How generated tasks are prepared, as the config's tasks were.
+    settings: TaskSettings,
+    /// Variables set from the stdout of tasks with `capture`.
+    captured: HashMap<String, String>,
+    /// Stdout of tasks that other tasks pipe from.
+    piped: HashMap<String, Pipe>,
+    /// Whether each task runs, decided by a plan instead of the cache.
+    planned: HashMap<String, bool>,
+    /// Limits on how many tasks with each tag run at once.
+    tag_limits: HashMap<String, Arc<Semaphore>>,
+    /// Input hashes added to the cache, with the task that added them.
+    cache_additions: Vec<(String, String)>,
+    /// Directory of the config file, which input paths are hashed relative to.
+    root: PathBuf,
 }
 
 impl<'a> TaskRunner<'a> {
-    #[allow(clippy::too_many_arguments)]
+    /// Runs `tasks`, whose input paths are relative to `root`, recording
+    /// results in `cache` and `history`.
     pub fn new(
         tasks: &'a [Task],
         cache: &'a mut cache::Cache,
-        rm: bool,
-        verbose: bool,
-        default_timeout: Option<String>,
-        workers: Option<usize>,
-        continue_on_failure: bool,
-        output_mode: OutputMode,
+        history: &'a mut History,
+        root: &Path,
+        options: RunOptions,
     ) -> Self {
-        let workers = workers.unwrap_or_else(default_workers);
         Self {
-            tasks,
+            tasks: tasks.to_vec(),
             cache,
-            rm,
-            verbose,
-            default_timeout,
-            workers,
-            continue_on_failure,
-            output_mode,
+            history,
+            rm: options.rm,
+            default_timeout: options.default_timeout,
+            workers: options.workers.unwrap_or_else(default_workers),
+            continue_on_failure: options.continue_on_failure,
+            output_mode: options.output_mode,
+            summary: RunSummary::default(),
+            results: Vec::new(),
+            generated: Vec::new(),
+            settings: TaskSettings::default(),
+            captured: HashMap::new(),
+            piped: HashMap::new(),
+            planned: HashMap::new(),
+            tag_limits: HashMap::new(),
+            cache_additions: Vec::new(),
+            root: root.to_path_buf(),
         }
     }
 
-    pub async fn run_tasks(&mut self, task_ids: &[String]) -> bool {
-        let tasks_to_run: Vec<Task> = task_ids
+    /// Decides which of `task_ids` would run, without running anything. A
+    /// task whose dependency runs is planned to run as well, since its inputs
+    /// may change.
+    pub fn plan(&self, task_ids: &[String]) -> Vec<PlannedTask> {
+        let mut running: HashSet<String> = HashSet::new();
+        let mut planned = Vec::new();
+
+        for mut task in task_ids
             .iter()
-            .filter_map(|task_id| self.tasks.iter().find(|t| &t.id == task_id))
+            .filter_map(|id| self.tasks.iter().find(|t| &t.id == id))
             .cloned()
+        {
+            // Lists that a task in the plan writes are read as they are now.
+            if let Err(e) = task.expand_path_lists() {
+                logging::warn(e.to_string());
+            }
+            let task = &task;
+            let reason = self.run_reason(task).or_else(|| {
+                task.dependencies
+                    .iter()
+                    .find(|dep| running.contains(dep.as_str()))
+                    .map(|dep| RunReason::DependencyRuns(dep.clone()))
+            });
+            if reason.is_some() {
+                running.insert(task.id.clone());
+            }
+
+            planned.push(PlannedTask {
+                id: task.id.clone(),
+                command: task.command.clone(),
+                dependencies: task.dependencies.clone(),
+                run: reason.is_some(),
+                reason: reason.unwrap_or(RunReason::UpToDate),
+            });
+        }
+        planned
+    }
+
+    /// Substitutes variables into generated tasks and fills in defaults from
+    /// `[config]`, as [`crate::load_tasks`] did for the config's own tasks.
+    pub fn prepare_generated(&mut self, settings: &TaskSettings) {
+        self.settings = settings.clone();
+    }
+
+    /// Runs or skips planned tasks as the plan says, rather than checking
+    /// whether they are up-to-date.
+    pub fn follow_plan(&mut self, plan: &[PlannedTask]) {
+        self.planned = plan.iter().map(|t| (t.id.clone(), t.run)).collect();
+    }
+
+    /// Runs at most `limit` tasks with a tag at once, in addition to the
+    /// overall `workers` limit.
+    pub fn limit_tags(&mut self, limits: &HashMap<String, usize>) {
+        self.tag_limits = limits
+            .iter()
+            .map(|(tag, &limit)| (tag.clone(), Arc::new(Semaphore::new(limit))))
+            .collect();
+    }
+
+    pub fn results(&self) -> &[TaskResult] {
+        &self.results
+    }
+
+    pub fn summary(&self) -> &RunSummary {
+        &self.summary
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Input hashes added to the cache during the run, with the task that added
+    /// each one, so that workspace members can keep their own cache.
+    pub fn cache_additions(&self) -> &[(String, String)] {
+        &self.cache_additions
+    }
+
+    /// Runs `task_ids`, which must already be in dependency order. Returns
+    /// whether the cache changed and should be saved.
+    pub async fn run_tasks(&mut self, task_ids: &[String]) -> bool {
+        let started = Instant::now();
+        let cache_updated = self.run_levels(task_ids).await;
+
+        self.summary.duration_ms = started.elapsed().as_millis();
+        self.summary.critical_path = self.run_critical_path();
+        let diagnostics: Vec<Diagnostic> = self
+            .results
+            .iter()
+            .flat_map(|r| r.diagnostics.iter().cloned())
             .collect();
+        logging::diagnostics(&diagnostics);
+        logging::run_summary(&self.summary);
 
-        if tasks_to_run.is_empty() {
-            return false;
+        cache_updated
+    }
+
+    /// Runs `task_ids` as usual, except for the last, which must be the task
+    /// they lead up to. That task then runs `iterations` times in a row without
+    /// consulting the cache. Stops at the first run that fails.
+    pub async fn bench(
+        &mut self,
+        task_ids: &[String],
+        iterations: usize,
+    ) -> Result<Vec<TaskResult>, CompiError> {
+        let Some((task_id, dependencies)) = task_ids.split_last() else {
+            return Ok(Vec::new());
+        };
+        let task = self
+            .tasks
+            .iter()
+            .find(|t| &t.id == task_id)
+            .cloned()
+            .ok_or_else(|| CompiError::Task(format!("Task '{}' not found", task_id)))?;
+
+        self.run_levels(dependencies).await;
+        if let Some(failed) = self
+            .results
+            .iter()
+            .find(|r| matches!(r.status, TaskStatus::Failed | TaskStatus::TimedOut))
+        {
+            return Err(CompiError::Task(format!(
+                "dependency '{}' of '{}' failed",
+                failed.task_id, task.id
+            )));
         }
 
-        let execution_levels = match calculate_dependency_levels(&tasks_to_run) {
-            Ok(levels) => levels,
-            Err(e) => {
-                eprintln!("Error calculating dependency levels: {}", e);
-                return false;
+        let mut bench_task = task.clone();
+        substitute_captures(&mut bench_task, &self.captured);
+        bench_task.expand_path_lists()?;
+        let mut env = self.dependency_outputs_env(&task);
+        env.extend(self.captured.clone());
+
+        let mut results = Vec::new();
+        for _ in 0..iterations {
+            logging::task_started(&bench_task.id);
+            let result = Self::execute_single_task(
+                &bench_task,
+                self.default_timeout.clone(),
+                false,
+                self.output_mode.clone(),
+                &env,
+                self.pipe_input(&bench_task),
+                None,
+            )
+            .await;
+            let succeeded = result.status == TaskStatus::Success;
+            results.push(result);
+            if !succeeded {
+                break;
             }
-        };
+        }
+        Ok(results)
+    }
+
+    async fn run_levels(&mut self, task_ids: &[String]) -> bool {
+        let mut remaining: Vec<Task> = task_ids
+            .iter()
+            .filter_map(|task_id| self.tasks.iter().find(|t| &t.id == task_id))
+            .cloned()
+            .collect();
+
+        let mut any_cache_updated = false;
+        let mut level_number = 0;
+
+        // Levels are recalculated whenever generators add tasks to the graph.
+        'schedule: while !remaining.is_empty() {
+            let execution_levels = match pending_levels(&remaining) {
+                Ok(levels) => levels,
+                Err(e) => {
+                    logging::error(format!("Failed to calculate dependency levels: {}", e));
+                    return false;
+                }
+            };
 
-        if self.verbose {
-            println!(
+            logging::debug(format!(
                 "Executing {} levels with up to {} workers:",
                 execution_levels.len(),
                 self.workers
-            );
-            for level in &execution_levels {
-                println!("  Level {}: {} tasks", level.level, level.task_ids.len());
+            ));
+            for (offset, level) in execution_levels.iter().enumerate() {
+                logging::debug(format!(
+                    "  Level {}: {} tasks",
+                    level_number + offset,
+                    level.task_ids.len()
+                ));
             }
-        }
 
-        let mut any_cache_updated = false;
-
-        for level in execution_levels {
-            if self.verbose {
-                println!(
+            for level in execution_levels {
+                logging::debug(format!(
                     "Level {}: Running {} tasks in parallel",
-                    level.level,
+                    level_number,
                     level.task_ids.len()
-                );
-            }
+                ));
 
-            let level_result = self.execute_level_parallel(&level.task_ids).await;
+                let level_started = Instant::now();
+                let level_result = self.execute_level_parallel(&level.task_ids).await;
+                trace::span(
+                    &format!("level {}", level_number),
+                    "level",
+                    trace::SCHEDULER_LANE,
+                    level_started,
+                    Instant::now(),
+                    json!({ "tasks": level.task_ids }),
+                );
 
-            match level_result {
-                Ok(cache_updated) => {
-                    if cache_updated {
-                        any_cache_updated = true;
+                match level_result {
+                    Ok(cache_updated) => {
+                        if cache_updated {
+                            any_cache_updated = true;
+                        }
+                    }
+                    Err(_) => {
+                        if self.continue_on_failure {
+                            logging::warn(format!(
+                                "Level {} had failures, but continuing due to --continue-on-failure",
+                                level_number
+                            ));
+                        } else {
+                            logging::error(format!(
+                                "Level {} failed, stopping execution",
+                                level_number
+                            ));
+                            return false;
+                        }
                     }
                 }
-                Err(_) => {
-                    if self.continue_on_failure {
-                        eprintln!(
-                            "Level {} had failures, but continuing due to --continue-on-failure",
-                            level.level
-                        );
-                    } else {
-                        eprintln!("Level {} failed, stopping execution", level.level);
+
+                level_number += 1;
+                remaining.retain(|task| !level.task_ids.contains(&task.id));
+
+                if !self.generated.is_empty() {
+                    if let Err(e) = self.merge_generated(&mut remaining) {
+                        logging::error(e);
                         return false;
                     }
+                    continue 'schedule;
                 }
             }
         }
@@ -200,47 +436,163 @@ impl<'a> TaskRunner<'a> {
         any_cache_updated
     }
 
+    /// Adds generated tasks to the graph. Tasks that depended on a generator
+    /// also wait for everything it generated.
+    fn merge_generated(&mut self, remaining: &mut Vec<Task>) -> Result<(), CompiError> {
+        for (generator, generated) in std::mem::take(&mut self.generated) {
+            let generated_ids: Vec<String> = generated.iter().map(|t| t.id.clone()).collect();
+            if let Some(duplicate) = generated_ids
+                .iter()
+                .find(|id| self.tasks.iter().any(|t| &t.id == *id))
+            {
+                return Err(CompiError::Task(format!(
+                    "task '{}' generated by '{}' already exists",
+                    duplicate, generator
+                )));
+            }
+
+            let mut combined = self.tasks.clone();
+            combined.extend(generated);
+            resolve_file_dependencies(&mut combined)
+                .and_then(|()| validate_tasks(&combined))
+                .map_err(|e| {
+                    CompiError::Task(format!(
+                        "tasks generated by '{}' are invalid: {}",
+                        generator, e
+                    ))
+                })?;
+            let generated = combined.split_off(self.tasks.len());
+
+            for task in remaining.iter_mut().chain(self.tasks.iter_mut()) {
+                if task.dependencies.contains(&generator) {
+                    task.dependencies.extend(generated_ids.iter().cloned());
+                }
+            }
+
+            logging::debug(format!(
+                "Task '{}' generated {} tasks: {}",
+                generator,
+                generated_ids.len(),
+                generated_ids.join(", ")
+            ));
+            self.tasks.extend(generated.iter().cloned());
+            remaining.extend(generated);
+        }
+        Ok(())
+    }
+
     async fn execute_level_parallel(&mut self, task_ids: &[String]) -> Result<bool, ()> {
         if task_ids.is_empty() {
             return Ok(false);
         }
 
         let semaphore = Arc::new(Semaphore::new(self.workers));
+        let lanes = Arc::new(Mutex::new((1..=self.workers).rev().collect::<Vec<_>>()));
         let mut handles = Vec::new();
         let mut any_cache_updated = false;
+        let mut pipes = HashMap::new();
 
         for task_id in task_ids {
-            let task = match self.tasks.iter().find(|t| &t.id == task_id) {
-                Some(task) => task,
-                None => {
-                    eprintln!("Error: task {} not found", task_id);
+            let Some(index) = self.tasks.iter().position(|t| &t.id == task_id) else {
+                logging::error(format!("Task '{}' not found", task_id));
+                return Err(());
+            };
+            // Before the cache check, so captured values in inputs and outputs
+            // count. Each task is scheduled once, so `$${NAME}` is only
+            // unescaped once.
+            substitute_captures(&mut self.tasks[index], &self.captured);
+            // Lists are read now, so that one written by a dependency counts.
+            if let Err(e) = self.tasks[index].expand_path_lists() {
+                let task_id = self.tasks[index].id.clone();
+                logging::task_error(&task_id, e);
+                let result = TaskResult::new(&task_id, TaskStatus::Failed, Duration::ZERO);
+                logging::task_finished(&result);
+                self.record_result(result);
+                if !self.continue_on_failure {
                     return Err(());
                 }
-            };
+                continue;
+            }
+            let task = &self.tasks[index];
 
             if !self.should_run_task(task) {
-                if self.verbose {
-                    println!("Task '{}': outputs up-to-date, skipping", task.id);
-                }
+                let result = TaskResult::new(&task.id, TaskStatus::Skipped, Duration::ZERO);
+                logging::task_finished(&result);
+                self.record_result(result);
                 continue;
             }
 
             let task_clone = task.clone();
+            let mut env = self.dependency_outputs_env(task);
+            env.extend(self.captured.clone());
+            let stdin = self.pipe_input(task);
+            // A generator's stdout is tasks to add, not output to show.
+            let stdout = match task.generator || self.is_piped_from(&task.id) {
+                true => match Pipe::create().and_then(|pipe| Ok((pipe.writer()?, pipe))) {
+                    Ok((writer, pipe)) => {
+                        pipes.insert(task.id.clone(), pipe);
+                        Some(writer)
+                    }
+                    Err(e) => {
+                        logging::error(format!(
+                            "Could not create a file for the output of task '{}': {}",
+                            task.id, e
+                        ));
+                        return Err(());
+                    }
+                },
+                false => None,
+            };
             let semaphore_clone = Arc::clone(&semaphore);
+            // Sorted, so that tasks sharing several tags take them in the same
+            // order and cannot wait on each other.
+            let mut tags: Vec<&String> = task.tags.iter().collect();
+            tags.sort();
+            tags.dedup();
+            let tag_limits: Vec<Arc<Semaphore>> = tags
+                .into_iter()
+                .filter_map(|tag| self.tag_limits.get(tag).cloned())
+                .collect();
             let default_timeout = self.default_timeout.clone();
             let rm = self.rm;
-            let verbose = self.verbose;
             let output_mode = self.output_mode.clone();
+            let lanes = Arc::clone(&lanes);
+            let queued_at = Instant::now();
 
             let handle = tokio::spawn(async move {
+                let mut _tag_permits = Vec::new();
+                for limit in &tag_limits {
+                    _tag_permits.push(limit.acquire().await.unwrap());
+                }
                 let _permit = semaphore_clone.acquire().await.unwrap();
+                let _token = jobserver::acquire().await;
+                let lane = lanes.lock().unwrap().pop().unwrap_or(trace::SCHEDULER_LANE);
+                let started_at = Instant::now();
+                trace::queue_span(&task_clone.id, queued_at, started_at);
 
-                if verbose {
-                    println!("Running task: {}", task_clone.id);
-                }
+                logging::task_started(&task_clone.id);
+
+                let result = Self::execute_single_task(
+                    &task_clone,
+                    default_timeout,
+                    rm,
+                    output_mode,
+                    &env,
+                    stdin,
+                    stdout,
+                )
+                .await;
 
-                Self::execute_single_task(&task_clone, default_timeout, rm, verbose, output_mode)
-                    .await
+                trace::span(
+                    &task_clone.id,
+                    "task",
+                    lane,
+                    started_at,
+                    Instant::now(),
+                    json!({ "status": result.status, "exit_code": result.exit_code }),
+                );
+                lanes.lock().unwrap().push(lane);
+                result
             });
 
             handles.push((task.id.clone(), handle));
@@ -248,26 +600,75 @@ impl<'a> TaskRunner<'a> {
 
         for (task_id, handle) in handles {
             match handle.await {
-                Ok(Ok(cache_updated)) => {
-                    if cache_updated {
-                        any_cache_updated = true;
-                        if let Some(task) = self.tasks.iter().find(|t| t.id == task_id) {
-                            if !task.inputs.is_empty() {
-                                if let Ok(hash) = hash_files(task.inputs.clone()) {
-                                    self.cache.insert(hash.to_hex().to_string());
+                Ok(mut result) if result.status == TaskStatus::Success => {
+                    if let Some(pipe) = pipes.remove(&task_id) {
+                        let task = self.tasks.iter().find(|t| t.id == task_id);
+                        if task.is_some_and(|t| t.capture.is_some() || t.generator) {
+                            result.stdout = pipe.to_vec().unwrap_or_else(|e| {
+                                logging::warn(format!(
+                                    "Could not read the output of task '{}': {}",
+                                    task_id, e
+                                ));
+                                Vec::new()
+                            });
+                        }
+                        self.piped.insert(task_id.clone(), pipe);
+                    }
+                    let generator = self
+                        .tasks
+                        .iter()
+                        .find(|t| t.id == task_id)
+                        .filter(|t| t.generator);
+                    if let Some(generator) = generator {
+                        match parse_generated_tasks(generator, &result.stdout, &self.settings) {
+                            Ok(generated) => {
+                                self.generated.push((task_id.clone(), generated));
+                            }
+                            Err(e) => {
+                                logging::task_error(&task_id, e);
+                                result.status = TaskStatus::Failed;
+                                self.record_result(result);
+                                if !self.continue_on_failure {
+                                    return Err(());
                                 }
+                                continue;
                             }
                         }
                     }
+
+                    if let Some(name) = self
+                        .tasks
+                        .iter()
+                        .find(|t| t.id == task_id)
+                        .and_then(|t| t.capture.clone())
+                    {
+                        let value = String::from_utf8_lossy(&result.stdout).trim().to_string();
+                        self.captured.insert(name, value);
+                    }
+
+                    // A task that skipped its outputs must not be cached as up-to-date.
+                    if let Some(task) = self.tasks.iter().find(|t| t.id == task_id)
+                        && !task.inputs.is_empty()
+                        && result.missing_outputs.is_empty()
+                    {
+                        any_cache_updated = true;
+                        if let Ok(hash) = traced_hash(task, &self.root) {
+                            let hash = hash.to_hex().to_string();
+                            self.cache.insert(hash.clone());
+                            self.cache_additions.push((task_id.clone(), hash));
+                        }
+                    }
+                    self.record_result(result);
                 }
-                Ok(Err(_)) => {
-                    eprintln!("Task '{}' failed", task_id);
+                Ok(result) => {
+                    self.record_result(result);
                     if !self.continue_on_failure {
                         return Err(());
                     }
                 }
                 Err(e) => {
-                    eprintln!("Task '{}' panicked: {}", task_id, e);
+                    self.summary.failed += 1;
+                    logging::error(format!("Task '{}' panicked: {}", task_id, e));
                     if !self.continue_on_failure {
                         return Err(());
                     }
@@ -278,155 +679,522 @@ impl<'a> TaskRunner<'a> {
         Ok(any_cache_updated)
     }
 
+    fn is_piped_from(&self, task_id: &str) -> bool {
+        self.tasks
+            .iter()
+            .any(|t| t.pipe_from.as_deref() == Some(task_id))
+    }
+
+    /// The stdout of the task's `pipe_from` dependency, read from the start,
+    /// or `None` for empty input if it did not run.
+    fn pipe_input(&self, task: &Task) -> Option<File> {
+        let producer = task.pipe_from.as_ref()?;
+        let Some(pipe) = self.piped.get(producer) else {
+            logging::warn(format!(
+                "Task '{}' pipes from '{}', which produced no output in this run",
+                task.id, producer
+            ));
+            return None;
+        };
+        pipe.reader()
+            .inspect_err(|e| {
+                logging::warn(format!(
+                    "Could not read the output of '{}' for task '{}': {}",
+                    producer, task.id, e
+                ))
+            })
+            .ok()
+    }
+
+    /// `DEP_<id>_OUTPUTS` for each dependency: its outputs, space-separated, with
+    /// glob patterns expanded to what exists now that the dependency has run.
+    /// Paths are absolute for tasks that run in another directory.
+    fn dependency_outputs_env(&self, task: &Task) -> Vec<(String, String)> {
+        task.dependencies
+            .iter()
+            .filter_map(|dep| self.tasks.iter().find(|t| &t.id == dep))
+            .map(|dep| {
+                let mut outputs: Vec<PathBuf> = Vec::new();
+                for output in &dep.outputs {
+                    if let Some(excluded) = exclusion(output) {
+                        outputs.retain(|p| !path_matches(&excluded, &strip_cur_dir(p)));
+                    } else if is_glob_pattern(&output.to_string_lossy()) {
+                        let expanded = expand_globs_any(std::slice::from_ref(output));
+                        outputs.extend(expanded.unwrap_or_default());
+                    } else {
+                        outputs.push(output.clone());
+                    }
+                }
+
+                let paths: Vec<String> = outputs
+                    .iter()
+                    .map(|p| match task.dir {
+                        Some(_) => std::path::absolute(p).unwrap_or_else(|_| p.clone()),
+                        None => p.clone(),
+                    })
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect();
+                (dependency_env_name(&dep.id), paths.join(" "))
+            })
+            .collect()
+    }
+
+    fn run_critical_path(&self) -> Option<CriticalPath> {
+        let durations: HashMap<String, Duration> = self
+            .results
+            .iter()
+            .map(|r| (r.task_id.clone(), r.duration))
+            .collect();
+        let path = find_critical_path(&self.tasks, &durations);
+
+        (path.tasks.len() > 1 && path.duration_ms > 0).then_some(path)
+    }
+
+    fn record_result(&mut self, result: TaskResult) {
+        match result.status {
+            TaskStatus::Success => self.summary.executed += 1,
+            TaskStatus::Skipped => self.summary.skipped += 1,
+            TaskStatus::Failed | TaskStatus::TimedOut => self.summary.failed += 1,
+        }
+
+        if let Some(task) = self.tasks.iter().find(|t| t.id == result.task_id)
+            && !task.inputs.is_empty()
+            && !task.always_run
+        {
+            self.summary.cacheable += 1;
+        }
+
+        if result.status == TaskStatus::Skipped
+            && let Some(average) = self.history.rolling_average(&result.task_id)
+        {
+            self.summary.time_saved_ms += average.as_millis() as u64;
+        }
+
+        if let Some(slowdown) = self.history.check_slowdown(&result) {
+            self.summary.slowdowns.push(slowdown);
+        }
+        self.history.record(&result);
+        self.results.push(result);
+    }
+
     async fn execute_single_task(
         task: &Task,
         default_timeout: Option<String>,
         rm: bool,
-        verbose: bool,
         output_mode: OutputMode,
-    ) -> Result<bool, ()> {
+        env: &[(String, String)],
+        stdin: Option<File>,
+        stdout: Option<File>,
+    ) -> TaskResult {
         let timeout = parse_timeout(task.timeout.as_deref(), default_timeout.as_deref());
         let stream_output = matches!(output_mode, OutputMode::Stream);
+        let started = Instant::now();
 
-        match run_command_with_timeout(&task.command, timeout, stream_output).await {
-            Ok(output) if output.status.success() => {
-                let cache_updated = !task.inputs.is_empty();
+        let finally = hooks::arm_task(task);
+        if !hooks::before_task(task, &output_mode).await {
+            logging::task_error(&task.id, format!("Task '{}' before hook failed", task.id));
+            let result = TaskResult::new(&task.id, TaskStatus::Failed, started.elapsed());
+            hooks::after_task(task, &result, finally, &output_mode).await;
+            logging::task_finished(&result);
+            return result;
+        }
 
-                if matches!(output_mode, OutputMode::Group)
-                    && (!output.stdout.is_empty() || !output.stderr.is_empty())
-                {
+        // Only the command is timed, so that hooks do not count towards the
+        // task's duration, its history or a benchmark.
+        let command_started = Instant::now();
+        let output = run_task(task, timeout, stdin, stdout, stream_output, env).await;
+        let duration = command_started.elapsed();
+        let result = match output {
+            Ok(output) => {
+                let mut status = if output.status.success() {
+                    TaskStatus::Success
+                } else {
+                    logging::task_error(
+                        &task.id,
+                        format!("Task '{}' failed with status: {}", task.id, output.status),
+                    );
+                    TaskStatus::Failed
+                };
+
+                let missing_outputs = match status {
+                    TaskStatus::Success => missing_outputs(task),
+                    _ => Vec::new(),
+                };
+                if !missing_outputs.is_empty() {
+                    let missing: Vec<String> = missing_outputs
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect();
+                    let message = format!(
+                        "Task '{}' did not produce declared outputs: {}",
+                        task.id,
+                        missing.join(", ")
+                    );
+                    if task.strict_outputs.unwrap_or(false) {
+                        logging::task_error(&task.id, message);
+                        status = TaskStatus::Failed;
+                    } else {
+                        logging::warn(message);
+                    }
+                }
+
+                let show_output = match output_mode {
+                    OutputMode::Stream => false,
+                    OutputMode::Group => true,
+                    OutputMode::Failures => status != TaskStatus::Success,
+                };
+                if show_output && (!output.stdout.is_empty() || !output.stderr.is_empty()) {
                     let _guard = output_print_lock().lock().await;
-                    Self::print_group_output(&task.id, &output);
+                    logging::task_output_group(&task.id, &output);
                 }
 
-                if (rm || task.auto_remove) && !task.outputs.is_empty() {
-                    if let Err(e) = cleanup_outputs(&task.outputs, verbose) {
-                        eprintln!("Warning: Cleanup failed for task '{}': {}", task.id, e);
-                    }
+                if status == TaskStatus::Success
+                    && (rm || task.auto_remove)
+                    && !task.outputs.is_empty()
+                    && let Err(e) = cleanup_outputs(&task.outputs)
+                {
+                    logging::warn(format!("Cleanup failed for task '{}': {}", task.id, e));
                 }
 
-                Ok(cache_updated)
-            }
-            Ok(output) => {
-                eprintln!(
-                    "Error: Task '{}' failed with status: {}",
-                    task.id, output.status
+                let diagnostics = diagnostics::extract(
+                    &task.id,
+                    &task.problem_matchers,
+                    task.dir.as_deref(),
+                    &output.stdout,
+                    &output.stderr,
                 );
-                if matches!(output_mode, OutputMode::Group)
-                    && (!output.stdout.is_empty() || !output.stderr.is_empty())
-                {
-                    let _guard = output_print_lock().lock().await;
-                    Self::print_group_output(&task.id, &output);
+
+                // Captures and generators need all of stdout, other results
+                // keep what fit in memory.
+                let stdout = if task.capture.is_some() || task.generator {
+                    output.stdout.to_vec().unwrap_or_else(|e| {
+                        logging::warn(format!(
+                            "Could not read the output of task '{}': {}",
+                            task.id, e
+                        ));
+                        output.stdout.head().to_vec()
+                    })
+                } else {
+                    output.stdout.into_head()
+                };
+
+                let stderr = output.stderr.into_head();
+
+                TaskResult {
+                    exit_code: output.status.code(),
+                    stdout,
+                    stderr,
+                    missing_outputs,
+                    diagnostics,
+                    ..TaskResult::new(&task.id, status, duration)
                 }
-                Err(())
             }
-            Err(CommandError::Timeout) => {
-                eprintln!("Error: Task '{}' timed out", task.id);
-                Err(())
+            Err(CommandError::Timeout(output)) => {
+                logging::task_error(&task.id, format!("Task '{}' timed out", task.id));
+                let mut result = TaskResult::new(&task.id, TaskStatus::TimedOut, duration);
+                // Output up to the timeout often shows where the task got stuck.
+                if let Some(output) = output {
+                    if !stream_output && (!output.stdout.is_empty() || !output.stderr.is_empty()) {
+                        let _guard = output_print_lock().lock().await;
+                        logging::task_output_group(&task.id, &output);
+                    }
+                    result.diagnostics = diagnostics::extract(
+                        &task.id,
+                        &task.problem_matchers,
+                        task.dir.as_deref(),
+                        &output.stdout,
+                        &output.stderr,
+                    );
+                    result.stdout = output.stdout.into_head();
+                    result.stderr = output.stderr.into_head();
+                }
+                result
             }
             Err(CommandError::Io(e)) => {
-                eprintln!("Error: Task '{}' failed to execute: {}", task.id, e);
-                Err(())
+                logging::task_error(
+                    &task.id,
+                    format!("Task '{}' failed to execute: {}", task.id, e),
+                );
+                TaskResult::new(&task.id, TaskStatus::Failed, duration)
             }
-        }
-    }
+        };
 
-    fn print_group_output(task_id: &str, output: &std::process::Output) {
-        use std::io::Write;
+        hooks::after_task(task, &result, finally, &output_mode).await;
+        logging::task_finished(&result);
+        result
+    }
 
-        if !output.stdout.is_empty() {
-            let mut out = std::io::stdout();
-            let _ = writeln!(out, "---- {} (stdout) ----", task_id);
-            let _ = out.write_all(&output.stdout);
-            if output.stdout.last() != Some(&b'\n') {
-                let _ = writeln!(out);
-            }
-            let _ = out.flush();
+    fn should_run_task(&self, task: &Task) -> bool {
+        if let Some(&run) = self.planned.get(&task.id) {
+            logging::debug(format!(
+                "Task '{}': {} as planned",
+                task.id,
+                if run { "running" } else { "skipping" }
+            ));
+            return run;
         }
 
-        if !output.stderr.is_empty() {
-            let mut err = std::io::stderr();
-            let _ = writeln!(err, "---- {} (stderr) ----", task_id);
-            let _ = err.write_all(&output.stderr);
-            if output.stderr.last() != Some(&b'\n') {
-                let _ = writeln!(err);
+        match self.run_reason(task) {
+            Some(reason) => {
+                logging::debug(format!("Task '{}': {}, must run", task.id, reason));
+                true
+            }
+            None => {
+                logging::debug(format!("Task '{}': outputs up-to-date, skipping", task.id));
+                false
             }
-            let _ = err.flush();
         }
     }
 
-    fn should_run_task(&self, task: &Task) -> bool {
+    /// Why the task has to run, or `None` when it is up-to-date.
+    fn run_reason(&self, task: &Task) -> Option<RunReason> {
+        if task.generator {
+            return Some(RunReason::Generator);
+        }
+
+        if task.capture.is_some() {
+            return Some(RunReason::CapturesOutput);
+        }
+
+        if self.is_piped_from(&task.id) {
+            return Some(RunReason::OutputPiped);
+        }
+
+        if let Some(producer) = &task.pipe_from {
+            return Some(RunReason::ReadsPipe(producer.clone()));
+        }
+
         if task.always_run {
-            if self.verbose {
-                println!("Task '{}': always_run=true, must run", task.id);
-            }
-            return true;
+            return Some(RunReason::AlwaysRun);
         }
 
         if task.inputs.is_empty() {
-            if self.verbose {
-                println!("Task '{}': no inputs, always run", task.id);
-            }
-            return true;
+            return Some(RunReason::NoInputs);
         }
 
         if !outputs_exist(task) {
-            if self.verbose {
-                println!("Task '{}': outputs missing, must run", task.id);
-            }
-            return true;
+            return Some(RunReason::OutputsMissing);
         }
 
         if outputs_outdated(task) {
-            if self.verbose {
-                println!("Task '{}': outputs older than inputs, must run", task.id);
-            }
-            return true;
+            return Some(RunReason::OutputsOutdated);
         }
 
-        match hash_files(task.inputs.clone()) {
+        match traced_hash(task, &self.root) {
             Ok(hash) => {
                 let hash_key = hash.to_hex().to_string();
                 if !self.cache.contains(&hash_key) {
-                    if self.verbose {
-                        println!("Task '{}': input content changed, must run", task.id);
-                    }
-                    return true;
+                    return Some(RunReason::InputsChanged);
                 }
             }
             Err(e) => {
-                eprintln!(
-                    "Error: Could not process inputs for task '{}': {}",
+                logging::error(format!(
+                    "Could not process inputs for task '{}': {}",
                     task.id, e
-                );
-                return true;
+                ));
+                return Some(RunReason::InputsUnreadable);
             }
         }
 
-        if self.verbose {
-            println!("Task '{}': outputs up-to-date, skipping", task.id);
+        None
+    }
+}
+
+/// Runs a task's command in the shell, or through its executor plugin. The
+/// timeout only applies to the shell: a plugin call cannot be stopped from
+/// outside, so it runs until it returns or runs out of fuel.
+async fn run_task(
+    task: &Task,
+    timeout: Option<Duration>,
+    stdin: Option<File>,
+    stdout: Option<File>,
+    stream_output: bool,
+    env: &[(String, String)],
+) -> Result<CommandOutput, CommandError> {
+    let Some(executor) = task.executor.clone() else {
+        let audit_log = audit::log_path(task);
+        let wrapper = match &audit_log {
+            Some(log) => Some(audit::wrap(task.wrapper.as_deref(), log)),
+            None => task.wrapper.clone(),
+        };
+
+        let grace_period = task
+            .grace_period
+            .as_deref()
+            .and_then(|period| humantime::parse_duration(period).ok())
+            .unwrap_or(DEFAULT_GRACE_PERIOD);
+
+        let sandbox = match task.sandbox {
+            true => Some(Sandbox::create(task).map_err(CommandError::Io)?),
+            false => None,
+        };
+        let dir = match &sandbox {
+            Some(sandbox) => Some(sandbox.dir()),
+            None => task.dir.as_deref(),
+        };
+
+        let output = run_command_with_timeout(
+            &task.id,
+            &task.command,
+            timeout,
+            grace_period,
+            stdin,
+            stdout,
+            stream_output,
+            env,
+            dir,
+            wrapper.as_deref(),
+        )
+        .await;
+
+        if let Some(log) = audit_log {
+            audit::report(task, &log);
         }
-        false
+        if let (Some(sandbox), Ok(output)) = (&sandbox, &output)
+            && output.status.success()
+        {
+            sandbox.copy_outputs(task).map_err(CommandError::Io)?;
+        }
+        return output;
+    };
+
+    let plugin_task = task.clone();
+    let mut output = tokio::task::spawn_blocking(move || plugin::execute(&executor, &plugin_task))
+        .await
+        .map_err(|e| CommandError::Io(io::Error::other(e)))?
+        .map_err(|e| CommandError::Io(io::Error::other(e.to_string())))?;
+    if let Some(mut stdout) = stdout {
+        stdout
+            .write_all(&std::mem::take(&mut output.stdout))
+            .map_err(CommandError::Io)?;
     }
+    replay_output(&task.id, output, stream_output)
 }
 
-fn outputs_exist(task: &Task) -> bool {
-    if task.outputs.is_empty() {
-        return true;
+/// Task ids may contain characters that are not valid in variable names, so
+/// those become underscores.
+pub(crate) fn dependency_env_name(task_id: &str) -> String {
+    let id: String = task_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("DEP_{}_OUTPUTS", id)
+}
+
+/// Levels for the tasks still to run, ignoring dependencies that already ran.
+fn pending_levels(remaining: &[Task]) -> Result<Vec<ExecutionLevel>, CompiError> {
+    let ids: HashSet<&str> = remaining.iter().map(|t| t.id.as_str()).collect();
+    let pending: Vec<Task> = remaining
+        .iter()
+        .map(|task| {
+            let mut task = task.clone();
+            task.dependencies.retain(|dep| ids.contains(dep.as_str()));
+            task
+        })
+        .collect();
+    calculate_dependency_levels(&pending)
+}
+
+/// Reads a generator's stdout, either a JSON array of tasks or `{"tasks": [...]}`,
+/// and prepares each task with `settings`. Generated tasks depend on their
+/// generator.
+fn parse_generated_tasks(
+    generator: &Task,
+    stdout: &[u8],
+    settings: &TaskSettings,
+) -> Result<Vec<Task>, String> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Generated {
+        List(Vec<Task>),
+        Wrapped { tasks: Vec<Task> },
     }
 
-    for output_spec in &task.outputs {
-        match crate::util::expand_globs_any(std::slice::from_ref(output_spec)) {
-            Ok(expanded) => {
-                if expanded.is_empty() {
-                    return false;
-                }
-            }
-            Err(_) => return false,
+    let generated = serde_json::from_slice::<Generated>(stdout).map_err(|e| {
+        format!(
+            "Task '{}' did not print valid generated tasks: {}",
+            generator.id, e
+        )
+    })?;
+    let mut tasks = match generated {
+        Generated::List(tasks) | Generated::Wrapped { tasks } => tasks,
+    };
+
+    for task in &mut tasks {
+        if task.id.is_empty() {
+            return Err(format!(
+                "Task '{}' generated a task without an id",
+                generator.id
+            ));
+        }
+        if task.confirm.is_some() {
+            return Err(format!(
+                "Task '{}' generated task '{}' with confirm, which is only asked before the run starts",
+                generator.id, task.id
+            ));
+        }
+        if !task.dependencies.contains(&generator.id) {
+            task.dependencies.push(generator.id.clone());
         }
+        settings
+            .prepare(task, generator)
+            .map_err(|e| format!("Task '{}' generated an invalid task: {}", generator.id, e))?;
     }
+    Ok(tasks)
+}
 
-    true
+/// Members' inputs are hashed relative to their own directory, so that their
+/// cache stays valid when they are run on their own.
+fn traced_hash(task: &Task, root: &Path) -> Result<blake3::Hash, FileError> {
+    let started = Instant::now();
+    let hash =
+        hash_files(task.inputs.clone(), task.dir.as_deref().unwrap_or(root)).map(
+            |hash| match &task.cache_key {
+                Some(key) => blake3::Hasher::new()
+                    .update(hash.as_bytes())
+                    .update(key.as_bytes())
+                    .finalize(),
+                None => hash,
+            },
+        );
+    trace::span(
+        &format!("hash {}", task.id),
+        "hash",
+        trace::SCHEDULER_LANE,
+        started,
+        Instant::now(),
+        json!({ "inputs": task.inputs }),
+    );
+    hash
+}
+
+/// Substitutes captured variables into the command, hooks, inputs and outputs.
+fn substitute_captures(task: &mut Task, captured: &HashMap<String, String>) {
+    task.command = substitute_variables(&task.command, captured);
+    for (_, command) in task.hooks.commands_mut() {
+        *command = substitute_variables(command, captured);
+    }
+    for path in task.inputs.iter_mut().chain(task.outputs.iter_mut()) {
+        *path = PathBuf::from(substitute_variables(&path.to_string_lossy(), captured));
+    }
+}
+
+/// Outputs that do not exist, where a glob is missing if it matches nothing.
+fn missing_outputs(task: &Task) -> Vec<PathBuf> {
+    task.outputs
+        .iter()
+        .filter(|output| exclusion(output).is_none())
+        .filter(|output| {
+            expand_globs_any(std::slice::from_ref(output))
+                .map(|found| found.is_empty())
+                .unwrap_or(true)
+        })
+        .cloned()
+        .collect()
+}
+
+fn outputs_exist(task: &Task) -> bool {
+    missing_outputs(task).is_empty()
 }
 
 fn outputs_outdated(task: &Task) -> bool {
@@ -452,23 +1220,33 @@ fn newest_timestamp(paths: &[PathBuf]) -> Option<SystemTime> {
 
     expanded_paths
         .iter()
-        .filter_map(|path| {
-            path.metadata()
-                .ok()
-                .and_then(|metadata| metadata.modified().ok())
-        })
+        .filter_map(|path| modified(path))
         .max()
 }
 
+/// A directory output is as old as the oldest file in it that `!` entries do
+/// not exclude, since the directory's own time only changes when entries are
+/// added or removed. An empty directory counts with its own time.
 fn oldest_timestamp(paths: &[PathBuf]) -> Option<SystemTime> {
-    let expanded_paths = crate::util::expand_globs_any(paths).ok()?;
+    let expanded_paths = expand_globs_any(paths).ok()?;
 
     expanded_paths
         .iter()
         .filter_map(|path| {
-            path.metadata()
-                .ok()
-                .and_then(|metadata| metadata.modified().ok())
+            if path.is_dir() {
+                let oldest_file = files_in_dir(path)
+                    .iter()
+                    .filter(|f| patterns_match(paths, &strip_cur_dir(f)))
+                    .filter_map(|f| modified(f))
+                    .min();
+                oldest_file.or_else(|| modified(path))
+            } else {
+                modified(path)
+            }
         })
         .min()
 }
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().ok()?.modified().ok()
+}
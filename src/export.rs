@@ -0,0 +1,314 @@
+use clap::ValueEnum;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::error::Result;
+use crate::logging;
+use crate::task::Task;
+use crate::task::dependency::calculate_dependency_levels;
+use crate::util::exclusion;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CiFormat {
+    /// A GitHub Actions workflow.
+    GithubActions,
+    /// A GitLab CI pipeline.
+    GitlabCi,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JobGrouping {
+    /// One job per task.
+    #[default]
+    Task,
+    /// One job per dependency level, running its tasks one after another.
+    Level,
+}
+
+/// A CI job and the tasks it runs.
+struct Job {
+    id: String,
+    level: usize,
+    commands: Vec<(String, String)>,
+    outputs: Vec<PathBuf>,
+    needs: Vec<String>,
+    /// Earlier jobs whose outputs this job needs on disk.
+    artifacts_from: Vec<String>,
+}
+
+pub fn export_ci(
+    tasks: &[Task],
+    format: CiFormat,
+    grouping: JobGrouping,
+    output: Option<&Path>,
+) -> Result<()> {
+    let jobs = match grouping {
+        JobGrouping::Task => jobs_per_task(tasks)?,
+        JobGrouping::Level => jobs_per_level(tasks)?,
+    };
+
+    let rendered = match format {
+        CiFormat::GithubActions => render_github_actions(&jobs),
+        CiFormat::GitlabCi => render_gitlab_ci(&jobs),
+    };
+
+    match output {
+        Some(path) => {
+            if let Some(parent) = path.parent()
+                && !parent.as_os_str().is_empty()
+            {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, rendered)?;
+            logging::info(format!(
+                "Exported {} jobs to {}",
+                jobs.len(),
+                path.display()
+            ));
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+fn jobs_per_task(tasks: &[Task]) -> Result<Vec<Job>> {
+    let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
+    let mut jobs = Vec::new();
+
+    for level in calculate_dependency_levels(tasks)? {
+        for task_id in level.task_ids {
+            let task = task_map[task_id.as_str()];
+            let mut needs: Vec<String> = task
+                .dependencies
+                .iter()
+                .filter(|dep| task_map.contains_key(dep.as_str()))
+                .map(|dep| job_id(dep))
+                .collect();
+            needs.sort();
+
+            let artifacts_from = ancestors(&task.id, &task_map)
+                .into_iter()
+                .filter(|id| !task_map[id.as_str()].outputs.is_empty())
+                .map(|id| job_id(&id))
+                .collect();
+
+            jobs.push(Job {
+                id: job_id(&task.id),
+                level: level.level,
+                commands: vec![(task.id.clone(), job_command(task))],
+                outputs: task.outputs.clone(),
+                needs,
+                artifacts_from,
+            });
+        }
+    }
+    Ok(jobs)
+}
+
+fn jobs_per_level(tasks: &[Task]) -> Result<Vec<Job>> {
+    let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
+    let mut jobs: Vec<Job> = Vec::new();
+
+    for level in calculate_dependency_levels(tasks)? {
+        let level_tasks: Vec<&Task> = level
+            .task_ids
+            .iter()
+            .map(|id| task_map[id.as_str()])
+            .collect();
+        jobs.push(Job {
+            id: format!("level-{}", level.level),
+            level: level.level,
+            commands: level_tasks
+                .iter()
+                .map(|t| (t.id.clone(), job_command(t)))
+                .collect(),
+            outputs: level_tasks
+                .iter()
+                .flat_map(|t| t.outputs.iter().cloned())
+                .collect(),
+            needs: jobs.last().map(|j| vec![j.id.clone()]).unwrap_or_default(),
+            artifacts_from: jobs
+                .iter()
+                .filter(|j| !j.outputs.is_empty())
+                .map(|j| j.id.clone())
+                .collect(),
+        });
+    }
+    Ok(jobs)
+}
+
+/// Commands of workspace members run from the member's directory, in a subshell
+/// so that later commands in the same job are unaffected.
+fn job_command(task: &Task) -> String {
+    let command = match &task.wrapper {
+        Some(wrapper) => format!("{} sh -c {}", wrapper, shell_words::quote(&task.command)),
+        None => task.command.clone(),
+    };
+    match &task.dir {
+        Some(dir) => format!("(cd {} && {})", dir.display(), command),
+        None => command,
+    }
+}
+
+fn ancestors(task_id: &str, task_map: &HashMap<&str, &Task>) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    let mut stack = vec![task_id.to_string()];
+
+    while let Some(current) = stack.pop() {
+        let Some(task) = task_map.get(current.as_str()) else {
+            continue;
+        };
+        for dep in &task.dependencies {
+            if task_map.contains_key(dep.as_str()) && found.insert(dep.clone()) {
+                stack.push(dep.clone());
+            }
+        }
+    }
+    found
+}
+
+fn render_github_actions(jobs: &[Job]) -> String {
+    let outputs: HashMap<&str, &[PathBuf]> = jobs
+        .iter()
+        .map(|j| (j.id.as_str(), j.outputs.as_slice()))
+        .collect();
+
+    let mut yaml = String::new();
+    yaml.push_str("# Generated by `compi export github-actions`.\n");
+    yaml.push_str("name: compi\n\non:\n  push:\n  pull_request:\n\njobs:\n");
+
+    for job in jobs {
+        let _ = writeln!(yaml, "  {}:", job.id);
+        let _ = writeln!(yaml, "    runs-on: ubuntu-latest");
+        if !job.needs.is_empty() {
+            let _ = writeln!(yaml, "    needs: [{}]", job.needs.join(", "));
+        }
+        yaml.push_str("    steps:\n      - uses: actions/checkout@v4\n");
+
+        for source in &job.artifacts_from {
+            // upload-artifact strips the outputs' common directory, so restore it on download.
+            let root = common_root(outputs[source.as_str()]);
+            let _ = writeln!(yaml, "      - uses: actions/download-artifact@v4");
+            let _ = writeln!(yaml, "        with:");
+            let _ = writeln!(yaml, "          name: {}", source);
+            let _ = writeln!(yaml, "          path: {}", quote(&root.to_string_lossy()));
+        }
+
+        for (task_id, command) in &job.commands {
+            let _ = writeln!(yaml, "      - name: {}", quote(task_id));
+            let _ = writeln!(yaml, "        run: {}", quote(command));
+        }
+
+        if !job.outputs.is_empty() {
+            let _ = writeln!(yaml, "      - uses: actions/upload-artifact@v4");
+            let _ = writeln!(yaml, "        with:");
+            let _ = writeln!(yaml, "          name: {}", job.id);
+            let _ = writeln!(yaml, "          path: |");
+            for output in &job.outputs {
+                let _ = writeln!(yaml, "            {}", output.display());
+            }
+        }
+        yaml.push('\n');
+    }
+    yaml
+}
+
+fn render_gitlab_ci(jobs: &[Job]) -> String {
+    let stages: BTreeSet<usize> = jobs.iter().map(|j| j.level).collect();
+
+    let mut yaml = String::new();
+    yaml.push_str("# Generated by `compi export gitlab-ci`.\n");
+    yaml.push_str("stages:\n");
+    for stage in &stages {
+        let _ = writeln!(yaml, "  - level-{}", stage);
+    }
+    yaml.push('\n');
+
+    for job in jobs {
+        // GitLab only passes artifacts along `needs`, so jobs also need every
+        // earlier job whose outputs they use.
+        let needs: BTreeSet<&String> = job.needs.iter().chain(&job.artifacts_from).collect();
+
+        let _ = writeln!(yaml, "{}:", job.id);
+        let _ = writeln!(yaml, "  stage: level-{}", job.level);
+        if !needs.is_empty() {
+            let needs: Vec<String> = needs.iter().map(|n| quote(n)).collect();
+            let _ = writeln!(yaml, "  needs: [{}]", needs.join(", "));
+        }
+        yaml.push_str("  script:\n");
+        for (_, command) in &job.commands {
+            let _ = writeln!(yaml, "    - {}", quote(command));
+        }
+        if !job.outputs.is_empty() {
+            let (excluded, paths): (Vec<_>, Vec<_>) =
+                job.outputs.iter().partition(|p| exclusion(p).is_some());
+            yaml.push_str("  artifacts:\n    paths:\n");
+            for output in paths {
+                let _ = writeln!(yaml, "      - {}", quote(&output.to_string_lossy()));
+            }
+            if !excluded.is_empty() {
+                yaml.push_str("    exclude:\n");
+                for output in excluded.iter().filter_map(|p| exclusion(p)) {
+                    let _ = writeln!(yaml, "      - {}", quote(&output.to_string_lossy()));
+                }
+            }
+        }
+        yaml.push('\n');
+    }
+    yaml
+}
+
+/// Deepest directory containing all of `paths`, where a directory output counts as its own root.
+fn common_root(paths: &[PathBuf]) -> PathBuf {
+    let mut root: Option<PathBuf> = None;
+    for path in paths.iter().filter(|p| exclusion(p).is_none()) {
+        let parent = if path.is_dir() {
+            path.clone()
+        } else {
+            path.parent().map(Path::to_path_buf).unwrap_or_default()
+        };
+        root = Some(match root {
+            None => parent,
+            Some(root) => root
+                .components()
+                .zip(parent.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+
+    match root {
+        Some(root) if !root.as_os_str().is_empty() => root,
+        _ => PathBuf::from("."),
+    }
+}
+
+/// CI job ids must start with a letter or underscore and avoid most punctuation.
+fn job_id(task_id: &str) -> String {
+    let id: String = task_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+
+    if id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        id
+    } else {
+        format!("_{}", id)
+    }
+}
+
+/// JSON strings are valid double-quoted YAML scalars.
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::BufReader,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::cache::{cache_file_path, write_json};
+use crate::execution::{TaskResult, TaskStatus};
+use crate::logging;
+
+const HISTORY_FILENAME: &str = "compi_history.json";
+const LAST_RUN_FILENAME: &str = "compi_last_run.json";
+const MAX_ENTRIES_PER_TASK: usize = 50;
+const ROLLING_WINDOW: usize = 10;
+const SLOWDOWN_FACTOR: f64 = 1.5;
+const MIN_SLOWDOWN: Duration = Duration::from_millis(500);
+
+/// Format of `compi_last_run.json`, raised when a change would be misread by
+/// older versions.
+pub const LAST_RUN_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    pub status: TaskStatus,
+    pub duration_ms: u64,
+}
+
+impl HistoryEntry {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+
+    pub fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct History {
+    tasks: HashMap<String, Vec<HistoryEntry>>,
+}
+
+/// The tasks of the most recent run and those of them that completed, by
+/// running successfully or being up-to-date, for `--resume`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LastRun {
+    /// Missing, and so 0, in files written before the format was versioned.
+    #[serde(default)]
+    pub version: u32,
+    /// Tasks the run was started for, all tasks when empty.
+    pub targets: Vec<String>,
+    pub tasks: Vec<String>,
+    pub completed: Vec<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Slowdown {
+    pub task: String,
+    pub duration_ms: u64,
+    pub average_ms: u64,
+}
+
+impl History {
+    pub fn entries(&self, task_id: &str) -> &[HistoryEntry] {
+        self.tasks.get(task_id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn task_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = self.tasks.keys().map(String::as_str).collect();
+        ids.sort();
+        ids
+    }
+
+    /// Average duration of the most recent successful executions of a task.
+    pub fn rolling_average(&self, task_id: &str) -> Option<Duration> {
+        let durations: Vec<u64> = self
+            .entries(task_id)
+            .iter()
+            .rev()
+            .filter(|entry| entry.status == TaskStatus::Success)
+            .take(ROLLING_WINDOW)
+            .map(|entry| entry.duration_ms)
+            .collect();
+
+        if durations.is_empty() {
+            return None;
+        }
+
+        let total: u64 = durations.iter().sum();
+        Some(Duration::from_millis(total / durations.len() as u64))
+    }
+
+    /// Compares a successful run against the rolling average, before it is recorded.
+    pub(crate) fn check_slowdown(&self, result: &TaskResult) -> Option<Slowdown> {
+        if result.status != TaskStatus::Success {
+            return None;
+        }
+
+        let average = self.rolling_average(&result.task_id)?;
+        let slow = result.duration.as_secs_f64() > average.as_secs_f64() * SLOWDOWN_FACTOR
+            && result.duration.saturating_sub(average) >= MIN_SLOWDOWN;
+
+        slow.then(|| Slowdown {
+            task: result.task_id.clone(),
+            duration_ms: result.duration.as_millis() as u64,
+            average_ms: average.as_millis() as u64,
+        })
+    }
+
+    /// The most recent result of a task and how long ago it was, such as
+    /// `last run 5m ago: failed in 1.20s`. When the task was up-to-date, the
+    /// duration is that of the last time it ran.
+    pub fn last_result(&self, task_id: &str, now: SystemTime) -> Option<String> {
+        let entries = self.entries(task_id);
+        let last = entries.last()?;
+        let age = format_age(now.duration_since(last.time()).unwrap_or_default());
+        if last.status != TaskStatus::Skipped {
+            return Some(format!(
+                "last run {} ago: {} in {:.2}s",
+                age,
+                status_label(last.status),
+                last.duration().as_secs_f64()
+            ));
+        }
+
+        let ran = entries
+            .iter()
+            .rev()
+            .find(|entry| entry.status != TaskStatus::Skipped);
+        Some(match ran {
+            Some(entry) => format!(
+                "last run {} ago: cached, ran in {:.2}s",
+                age,
+                entry.duration().as_secs_f64()
+            ),
+            None => format!("last run {} ago: cached", age),
+        })
+    }
+
+    pub(crate) fn record(&mut self, result: &TaskResult) {
+        let timestamp = result
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let entries = self.tasks.entry(result.task_id.clone()).or_default();
+        entries.push(HistoryEntry {
+            timestamp,
+            status: result.status,
+            duration_ms: result.duration.as_millis() as u64,
+        });
+
+        if entries.len() > MAX_ENTRIES_PER_TASK {
+            let excess = entries.len() - MAX_ENTRIES_PER_TASK;
+            entries.drain(..excess);
+        }
+    }
+}
+
+pub(crate) fn history_path(cache_dir: Option<&str>, config_path: &str) -> PathBuf {
+    cache_file_path(cache_dir, config_path, HISTORY_FILENAME)
+}
+
+pub fn load_history(cache_dir: Option<&str>, config_path: &str) -> History {
+    let history_path = history_path(cache_dir, config_path);
+
+    let file = match File::open(&history_path) {
+        Ok(file) => file,
+        Err(_) => return History::default(),
+    };
+
+    let reader = BufReader::new(file);
+    serde_json::from_reader(reader).unwrap_or_default()
+}
+
+pub fn save_history(history: &History, cache_dir: Option<&str>, config_path: &str) {
+    let history_path = history_path(cache_dir, config_path);
+    write_json(&history_path, history, "history");
+}
+
+/// The last recorded run, unless it was written in another format.
+pub fn load_last_run(cache_dir: Option<&str>, config_path: &str) -> Option<LastRun> {
+    let path = cache_file_path(cache_dir, config_path, LAST_RUN_FILENAME);
+    let file = File::open(&path).ok()?;
+    let last_run: LastRun = serde_json::from_reader(BufReader::new(file)).ok()?;
+    if last_run.version != LAST_RUN_VERSION {
+        logging::debug(format!(
+            "Ignoring '{}' with version {}, this compi reads version {}",
+            path.display(),
+            last_run.version,
+            LAST_RUN_VERSION
+        ));
+        return None;
+    }
+    Some(last_run)
+}
+
+pub fn save_last_run(last_run: &LastRun, cache_dir: Option<&str>, config_path: &str) {
+    let path = cache_file_path(cache_dir, config_path, LAST_RUN_FILENAME);
+    write_json(&path, last_run, "last run");
+}
+
+pub fn show_history(history: &History, task_id: Option<&str>) {
+    match task_id {
+        Some(task_id) => {
+            let entries = history.entries(task_id);
+            if entries.is_empty() {
+                logging::info(format!("No recorded runs for task '{}'", task_id));
+                return;
+            }
+
+            logging::info(format!("History for task '{}':", task_id));
+            for entry in entries.iter().rev() {
+                logging::info(format!(
+                    "  {}  {:<9}  {:.2}s",
+                    humantime::format_rfc3339_seconds(entry.time()),
+                    status_label(entry.status),
+                    entry.duration().as_secs_f64()
+                ));
+            }
+        }
+        None => {
+            let task_ids = history.task_ids();
+            if task_ids.is_empty() {
+                logging::info("No recorded runs");
+                return;
+            }
+
+            for task_id in task_ids {
+                let entries = history.entries(task_id);
+                let Some(last) = entries.last() else {
+                    continue;
+                };
+                let average = history
+                    .rolling_average(task_id)
+                    .map(|avg| format!("{:.2}s", avg.as_secs_f64()))
+                    .unwrap_or_else(|| "-".to_string());
+
+                logging::info(format!(
+                    "{}: {} runs, average {}, last {} {:.2}s at {}",
+                    task_id,
+                    entries.len(),
+                    average,
+                    status_label(last.status),
+                    last.duration().as_secs_f64(),
+                    humantime::format_rfc3339_seconds(last.time())
+                ));
+            }
+        }
+    }
+}
+
+fn status_label(status: TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Success => "success",
+        TaskStatus::Failed => "failed",
+        TaskStatus::TimedOut => "timed out",
+        TaskStatus::Skipped => "skipped",
+    }
+}
+
+/// An age in its largest whole unit, such as `45s`, `5m` or `3d`.
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
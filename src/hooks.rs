@@ -0,0 +1,421 @@
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::error::{CompiError, Result};
+use crate::execution::{RunSummary, TaskResult, TaskStatus};
+use crate::logging;
+use crate::output::OutputMode;
+use crate::process::{self, DEFAULT_GRACE_PERIOD};
+use crate::task::Task;
+use crate::util::{CommandError, output_print_lock, run_command_with_timeout};
+
+/// Commands run around a task, or around the whole run when set in `[config]`.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Hooks {
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub on_success: Option<String>,
+    pub on_failure: Option<String>,
+    /// Runs after `after`, and also when compi is interrupted with Ctrl-C.
+    pub finally: Option<String>,
+}
+
+static ARMED: OnceLock<Mutex<Vec<Armed>>> = OnceLock::new();
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+/// `finally` hooks taken from [`ARMED`] that have not finished yet.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+fn armed() -> &'static Mutex<Vec<Armed>> {
+    ARMED.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// A `finally` hook whose task or run has started.
+struct Armed {
+    id: u64,
+    owner: String,
+    command: String,
+    /// Set to `interrupted` when the hook runs because of Ctrl-C.
+    status_var: &'static str,
+    context: Vec<(String, String)>,
+    dir: Option<PathBuf>,
+    wrapper: Option<String>,
+}
+
+/// A `finally` hook that runs exactly once: when its task or run ends, or on
+/// Ctrl-C if that comes first.
+pub struct Finally {
+    id: u64,
+}
+
+impl Finally {
+    fn arm(
+        owner: &str,
+        command: &str,
+        status_var: &'static str,
+        context: Vec<(String, String)>,
+        dir: Option<&Path>,
+        wrapper: Option<&str>,
+    ) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        armed().lock().unwrap().push(Armed {
+            id,
+            owner: owner.to_string(),
+            command: command.to_string(),
+            status_var,
+            context,
+            dir: dir.map(Path::to_path_buf),
+            wrapper: wrapper.map(str::to_string),
+        });
+        Self { id }
+    }
+
+    /// Runs the hook with `context`, unless Ctrl-C has already run it.
+    async fn run(self, context: &[(String, String)], output_mode: &OutputMode) {
+        // Counted as running under the same lock, so that Ctrl-C cannot find
+        // the hook neither armed nor running.
+        let taken = {
+            let mut armed = armed().lock().unwrap();
+            let index = armed.iter().position(|a| a.id == self.id);
+            index.map(|index| {
+                RUNNING.fetch_add(1, Ordering::SeqCst);
+                armed.remove(index)
+            })
+        };
+        if let Some(hook) = taken {
+            // Tasks and runs that end because of Ctrl-C report it as such.
+            let mut context = context.to_vec();
+            if process::interrupted() {
+                context.retain(|(name, _)| name != hook.status_var);
+                context.push((hook.status_var.to_string(), "interrupted".to_string()));
+            }
+            run(
+                &hook.owner,
+                "finally",
+                &hook.command,
+                &context,
+                hook.dir.as_deref(),
+                hook.wrapper.as_deref(),
+                output_mode,
+            )
+            .await;
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Arms a task's `finally` hook, before anything else of the task runs.
+pub fn arm_task(task: &Task) -> Option<Finally> {
+    let command = task.hooks.finally.as_ref()?;
+    Some(Finally::arm(
+        &task.id,
+        command,
+        "COMPI_TASK_STATUS",
+        vec![("COMPI_TASK_ID".to_string(), task.id.clone())],
+        task.dir.as_deref(),
+        task.wrapper.as_deref(),
+    ))
+}
+
+/// Arms the run-level `finally` hook, before the run-level `before`.
+pub fn arm_run(hooks: &Hooks, wrapper: Option<&str>) -> Option<Finally> {
+    let command = hooks.finally.as_ref()?;
+    Some(Finally::arm(
+        "compi",
+        command,
+        "COMPI_RUN_STATUS",
+        Vec::new(),
+        None,
+        wrapper,
+    ))
+}
+
+/// Whether a `finally` hook is waiting for its task or run to end.
+pub fn any_armed() -> bool {
+    !armed().lock().unwrap().is_empty()
+}
+
+/// Whether a `finally` hook is running because its task or run ended.
+pub fn any_running() -> bool {
+    RUNNING.load(Ordering::SeqCst) > 0
+}
+
+/// Waits for the `finally` hooks that started before Ctrl-C to finish.
+pub async fn wait_for_running() {
+    while RUNNING.load(Ordering::SeqCst) > 0 {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Runs every armed `finally` hook, the most recently armed first, with the
+/// status variable set to `interrupted`. Called when compi is interrupted.
+pub async fn run_armed() {
+    let hooks: Vec<Armed> = armed().lock().unwrap().drain(..).collect();
+    for hook in hooks.into_iter().rev() {
+        let mut context = hook.context;
+        context.push((hook.status_var.to_string(), "interrupted".to_string()));
+        run(
+            &hook.owner,
+            "finally",
+            &hook.command,
+            &context,
+            hook.dir.as_deref(),
+            hook.wrapper.as_deref(),
+            &OutputMode::Stream,
+        )
+        .await;
+    }
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.before.is_none()
+            && self.after.is_none()
+            && self.on_success.is_none()
+            && self.on_failure.is_none()
+            && self.finally.is_none()
+    }
+
+    /// Each configured hook's command.
+    pub fn commands(&self) -> impl Iterator<Item = &String> {
+        [
+            &self.before,
+            &self.after,
+            &self.on_success,
+            &self.on_failure,
+            &self.finally,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// Each configured hook's name and command.
+    pub fn commands_mut(&mut self) -> impl Iterator<Item = (&'static str, &mut String)> {
+        [
+            ("before", &mut self.before),
+            ("after", &mut self.after),
+            ("on_success", &mut self.on_success),
+            ("on_failure", &mut self.on_failure),
+            ("finally", &mut self.finally),
+        ]
+        .into_iter()
+        .filter_map(|(name, command)| Some((name, command.as_mut()?)))
+    }
+}
+
+/// Runs a task's `before` hook. The task fails without running if it does.
+pub async fn before_task(task: &Task, output_mode: &OutputMode) -> bool {
+    let Some(command) = &task.hooks.before else {
+        return true;
+    };
+
+    let context = vec![("COMPI_TASK_ID".to_string(), task.id.clone())];
+    run(
+        &task.id,
+        "before",
+        command,
+        &context,
+        task.dir.as_deref(),
+        task.wrapper.as_deref(),
+        output_mode,
+    )
+    .await
+}
+
+/// Runs a task's `on_success` or `on_failure` hook, then `after` and
+/// `finally`. Failures are only reported.
+pub async fn after_task(
+    task: &Task,
+    result: &TaskResult,
+    finally: Option<Finally>,
+    output_mode: &OutputMode,
+) {
+    if task.hooks.is_empty() {
+        return;
+    }
+
+    let context = vec![
+        ("COMPI_TASK_ID".to_string(), task.id.clone()),
+        (
+            "COMPI_TASK_STATUS".to_string(),
+            status_name(result.status).to_string(),
+        ),
+        (
+            "COMPI_TASK_DURATION_MS".to_string(),
+            result.duration.as_millis().to_string(),
+        ),
+        (
+            "COMPI_TASK_EXIT_CODE".to_string(),
+            result
+                .exit_code
+                .map(|code| code.to_string())
+                .unwrap_or_default(),
+        ),
+    ];
+
+    let outcome = match result.status {
+        TaskStatus::Success => ("on_success", &task.hooks.on_success),
+        _ => ("on_failure", &task.hooks.on_failure),
+    };
+    for (name, command) in [outcome, ("after", &task.hooks.after)] {
+        if let Some(command) = command {
+            run(
+                &task.id,
+                name,
+                command,
+                &context,
+                task.dir.as_deref(),
+                task.wrapper.as_deref(),
+                output_mode,
+            )
+            .await;
+        }
+    }
+    if let Some(finally) = finally {
+        finally.run(&context, output_mode).await;
+    }
+}
+
+/// Runs the run-level `before` hook, aborting the run if it fails.
+pub async fn before_run(
+    hooks: &Hooks,
+    wrapper: Option<&str>,
+    output_mode: &OutputMode,
+) -> Result<()> {
+    let Some(command) = &hooks.before else {
+        return Ok(());
+    };
+
+    if run("compi", "before", command, &[], None, wrapper, output_mode).await {
+        Ok(())
+    } else {
+        Err(CompiError::Task("before hook failed".to_string()))
+    }
+}
+
+/// Runs the run-level `finally` hook after a failing `before` aborted the run.
+pub async fn abort_run(finally: Option<Finally>, output_mode: &OutputMode) {
+    if let Some(finally) = finally {
+        let context = vec![
+            ("COMPI_RUN_STATUS".to_string(), "failed".to_string()),
+            ("COMPI_RUN_DURATION_MS".to_string(), "0".to_string()),
+            ("COMPI_FAILED_TASKS".to_string(), String::new()),
+        ];
+        finally.run(&context, output_mode).await;
+    }
+}
+
+/// Runs the run-level `on_success` or `on_failure` hook, then `after` and
+/// `finally`.
+pub async fn after_run(
+    hooks: &Hooks,
+    results: &[TaskResult],
+    summary: &RunSummary,
+    finally: Option<Finally>,
+    wrapper: Option<&str>,
+    output_mode: &OutputMode,
+) {
+    if hooks.is_empty() {
+        return;
+    }
+
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|r| matches!(r.status, TaskStatus::Failed | TaskStatus::TimedOut))
+        .map(|r| r.task_id.as_str())
+        .collect();
+    let status = if failed.is_empty() {
+        "success"
+    } else {
+        "failed"
+    };
+    let context = vec![
+        ("COMPI_RUN_STATUS".to_string(), status.to_string()),
+        (
+            "COMPI_RUN_DURATION_MS".to_string(),
+            summary.duration_ms.to_string(),
+        ),
+        ("COMPI_FAILED_TASKS".to_string(), failed.join(" ")),
+    ];
+
+    let outcome = if failed.is_empty() {
+        ("on_success", &hooks.on_success)
+    } else {
+        ("on_failure", &hooks.on_failure)
+    };
+    for (name, command) in [outcome, ("after", &hooks.after)] {
+        if let Some(command) = command {
+            run("compi", name, command, &context, None, wrapper, output_mode).await;
+        }
+    }
+    if let Some(finally) = finally {
+        finally.run(&context, output_mode).await;
+    }
+}
+
+async fn run(
+    owner: &str,
+    hook: &str,
+    command: &str,
+    context: &[(String, String)],
+    dir: Option<&Path>,
+    wrapper: Option<&str>,
+    output_mode: &OutputMode,
+) -> bool {
+    let label = format!("{}:{}", owner, hook);
+    let stream_output = matches!(output_mode, OutputMode::Stream);
+
+    match run_command_with_timeout(
+        &label,
+        command,
+        None,
+        DEFAULT_GRACE_PERIOD,
+        None,
+        None,
+        stream_output,
+        context,
+        dir,
+        wrapper,
+    )
+    .await
+    {
+        Ok(output) => {
+            let show_output = match output_mode {
+                OutputMode::Stream => false,
+                OutputMode::Group => true,
+                OutputMode::Failures => !output.status.success(),
+            };
+            if show_output && (!output.stdout.is_empty() || !output.stderr.is_empty()) {
+                let _guard = output_print_lock().lock().await;
+                logging::task_output_group(&label, &output);
+            }
+            if !output.status.success() {
+                logging::warn(format!(
+                    "Hook '{}' failed with status: {}",
+                    label, output.status
+                ));
+            }
+            output.status.success()
+        }
+        Err(CommandError::Timeout(_)) => false,
+        Err(CommandError::Io(e)) => {
+            logging::warn(format!("Hook '{}' failed to execute: {}", label, e));
+            false
+        }
+    }
+}
+
+fn status_name(status: TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Success => "success",
+        TaskStatus::Failed => "failed",
+        TaskStatus::TimedOut => "timed_out",
+        TaskStatus::Skipped => "skipped",
+    }
+}
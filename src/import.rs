@@ -0,0 +1,367 @@
+use regex::{Captures, Regex};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    fs,
+    path::Path,
+    sync::LazyLock,
+};
+
+use crate::error::{CompiError, Result};
+use crate::logging;
+use crate::task::schema;
+
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+static ASSIGNMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:export\s+)?([A-Za-z_][A-Za-z0-9_]*)\s*(:::=|::=|:=|\?=|\+=|!=|=)\s*(.*)$")
+        .unwrap()
+});
+/// `$(NAME)` and `${NAME}`.
+static REFERENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$[({]([A-Za-z_][A-Za-z0-9_]*)[)}]").unwrap());
+static WILDCARD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\(wildcard\s+([^)]*)\)").unwrap());
+/// `$$`, with the shell variable it escapes if any, and the automatic
+/// variables `$@`, `$<`, `$^` and `$?`.
+static AUTOMATIC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\$(\{|[A-Za-z_][A-Za-z0-9_]*)?|\$[@<^?]").unwrap());
+
+#[derive(Debug, Default)]
+struct Rule {
+    target: String,
+    prerequisites: Vec<String>,
+    recipe: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Makefile {
+    variables: Vec<(String, String)>,
+    rules: Vec<Rule>,
+    phony: HashSet<String>,
+    skipped: Vec<String>,
+}
+
+/// Converts the explicit rules of a Makefile into compi tasks. Pattern rules,
+/// conditionals and includes are skipped and reported.
+pub fn import_makefile(makefile: &Path, output: Option<&Path>) -> Result<()> {
+    let contents = fs::read_to_string(makefile).map_err(|e| {
+        CompiError::Parse(format!(
+            "failed to read makefile '{}': {}",
+            makefile.display(),
+            e
+        ))
+    })?;
+
+    let parsed = parse_makefile(&contents);
+    for line in &parsed.skipped {
+        logging::warn(format!("Skipped unsupported makefile line: {}", line));
+    }
+    if parsed.rules.is_empty() {
+        return Err(CompiError::Parse(format!(
+            "no rules found in '{}'",
+            makefile.display()
+        )));
+    }
+
+    write_config(&render_tasks(&parsed), output)
+}
+
+/// Prints generated configuration, or writes it to `output` if that file does not exist yet.
+pub fn write_config(toml: &str, output: Option<&Path>) -> Result<()> {
+    match output {
+        Some(path) => {
+            if path.exists() {
+                return Err(CompiError::Task(format!(
+                    "'{}' already exists, refusing to overwrite it",
+                    path.display()
+                )));
+            }
+            fs::write(path, toml)?;
+            logging::info(format!("Wrote imported tasks to {}", path.display()));
+        }
+        None => print!("{}", toml),
+    }
+    Ok(())
+}
+
+fn parse_makefile(contents: &str) -> Makefile {
+    let mut makefile = Makefile::default();
+    let mut variables: HashMap<String, String> = HashMap::new();
+    let mut current: Vec<usize> = Vec::new();
+
+    for line in join_continuations(contents) {
+        if let Some(recipe) = line.strip_prefix('\t') {
+            if current.is_empty() {
+                continue;
+            }
+            let recipe = recipe.trim();
+            if !recipe.is_empty() && !recipe.starts_with('#') {
+                for &index in &current {
+                    makefile.rules[index].recipe.push(recipe.to_string());
+                }
+            }
+            continue;
+        }
+
+        let line = strip_comment(&line).trim_end();
+        if line.trim().is_empty() {
+            continue;
+        }
+        current.clear();
+
+        if let Some(caps) = ASSIGNMENT.captures(line) {
+            let (name, operator, value) = (&caps[1], &caps[2], caps[3].trim());
+            match operator {
+                "!=" => makefile.skipped.push(line.to_string()),
+                "?=" if variables.contains_key(name) => {}
+                "+=" => {
+                    let entry = variables.entry(name.to_string()).or_default();
+                    if !entry.is_empty() {
+                        entry.push(' ');
+                    }
+                    entry.push_str(value);
+                }
+                _ => {
+                    variables.insert(name.to_string(), value.to_string());
+                }
+            }
+            if !makefile.variables.iter().any(|(n, _)| n == name) && variables.contains_key(name) {
+                makefile.variables.push((name.to_string(), String::new()));
+            }
+            continue;
+        }
+
+        let Some((targets, prerequisites)) = split_rule(line) else {
+            makefile.skipped.push(line.to_string());
+            continue;
+        };
+
+        let (prerequisites, inline_recipe) = match prerequisites.split_once(';') {
+            Some((prerequisites, recipe)) => (prerequisites, Some(recipe.trim())),
+            None => (prerequisites, None),
+        };
+        let targets = split_words(&expand(targets, &variables, 0));
+        let prerequisites: Vec<String> = split_words(&expand(prerequisites, &variables, 0))
+            .into_iter()
+            .filter(|p| p != "|")
+            .collect();
+
+        if targets.iter().any(|t| t == ".PHONY") {
+            makefile.phony.extend(prerequisites);
+            continue;
+        }
+        if targets.iter().any(|t| t.contains('%')) {
+            makefile.skipped.push(line.to_string());
+            continue;
+        }
+
+        for target in targets {
+            if target.starts_with('.') {
+                continue;
+            }
+
+            let index = match makefile.rules.iter().position(|r| r.target == target) {
+                Some(index) => index,
+                None => {
+                    makefile.rules.push(Rule {
+                        target: target.clone(),
+                        ..Default::default()
+                    });
+                    makefile.rules.len() - 1
+                }
+            };
+            let rule = &mut makefile.rules[index];
+            for prerequisite in &prerequisites {
+                if !rule.prerequisites.contains(prerequisite) {
+                    rule.prerequisites.push(prerequisite.clone());
+                }
+            }
+            if let Some(recipe) = inline_recipe
+                && !recipe.is_empty()
+            {
+                rule.recipe.push(recipe.to_string());
+            }
+            current.push(index);
+        }
+    }
+
+    for (name, value) in &mut makefile.variables {
+        *value = expand(&variables[name.as_str()], &variables, 0);
+    }
+    makefile
+}
+
+fn join_continuations(contents: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut pending = String::new();
+
+    for line in contents.lines() {
+        match line.strip_suffix('\\') {
+            Some(start) => {
+                pending.push_str(start.trim_end());
+                pending.push(' ');
+            }
+            None if pending.is_empty() => lines.push(line.to_string()),
+            None => {
+                pending.push_str(line.trim_start());
+                lines.push(std::mem::take(&mut pending));
+            }
+        }
+    }
+    if !pending.is_empty() {
+        lines.push(pending);
+    }
+    lines
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split_once('#').map_or(line, |(before, _)| before)
+}
+
+/// Splits `targets: prerequisites`, ignoring colons inside variable references.
+fn split_rule(line: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            ':' if depth == 0 => {
+                let rest = line[i + 1..].trim_start_matches(':');
+                return Some((&line[..i], rest));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_words(text: &str) -> Vec<String> {
+    text.split_whitespace().map(str::to_string).collect()
+}
+
+/// Expands `$(NAME)` and `${NAME}` references and the `$(wildcard ...)`
+/// function, leaving anything else for the recipe to handle.
+fn expand(text: &str, variables: &HashMap<String, String>, depth: usize) -> String {
+    if depth > MAX_EXPANSION_DEPTH {
+        return text.to_string();
+    }
+
+    let text = WILDCARD.replace_all(text, "$1");
+    REFERENCE
+        .replace_all(&text, |caps: &Captures| match variables.get(&caps[1]) {
+            Some(value) => expand(value, variables, depth + 1),
+            None => caps[0].to_string(),
+        })
+        .to_string()
+}
+
+fn render_tasks(makefile: &Makefile) -> String {
+    let targets: HashSet<&str> = makefile.rules.iter().map(|r| r.target.as_str()).collect();
+    let mut toml = format!("version = {}\n\n", schema::VERSION);
+
+    let default = makefile
+        .rules
+        .iter()
+        .find(|r| !r.recipe.is_empty() || !r.prerequisites.is_empty());
+    if let Some(rule) = default {
+        let _ = writeln!(toml, "[config]\ndefault = {}\n", toml_string(&rule.target));
+    }
+
+    if !makefile.variables.is_empty() {
+        toml.push_str("[variables]\n");
+        for (name, value) in &makefile.variables {
+            let _ = writeln!(toml, "{} = {}", toml_key(name), toml_string(value));
+        }
+        toml.push('\n');
+    }
+
+    for rule in &makefile.rules {
+        let (dependencies, inputs): (Vec<&String>, Vec<&String>) = rule
+            .prerequisites
+            .iter()
+            .partition(|p| targets.contains(p.as_str()));
+        let phony = makefile.phony.contains(&rule.target);
+
+        let command = translate_recipe(rule, &makefile.variables);
+        if command.contains("$(") {
+            toml.push_str("# Review: the command uses make functions that were not converted.\n");
+        }
+
+        let _ = writeln!(toml, "[task.{}]", toml_key(&rule.target));
+        let command = if command.is_empty() {
+            "true".to_string()
+        } else {
+            command
+        };
+        let _ = writeln!(toml, "command = {}", toml_string(&command));
+        if !dependencies.is_empty() {
+            let _ = writeln!(toml, "dependencies = {}", toml_array(&dependencies));
+        }
+        if !inputs.is_empty() {
+            let _ = writeln!(toml, "inputs = {}", toml_array(&inputs));
+        }
+        if !phony {
+            let _ = writeln!(toml, "outputs = {}", toml_array(&[&rule.target]));
+        }
+        toml.push('\n');
+    }
+
+    toml
+}
+
+/// Joins recipe lines with `&&` and rewrites make syntax into its compi
+/// equivalent. `$$` becomes `$`, except before a shell variable.
+fn translate_recipe(rule: &Rule, variables: &[(String, String)]) -> String {
+    let first = rule.prerequisites.first().cloned().unwrap_or_default();
+    let all = rule.prerequisites.join(" ");
+
+    rule.recipe
+        .iter()
+        .map(|line| line.trim_start_matches(['@', '-', '+']).trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let line = REFERENCE.replace_all(line, |caps: &Captures| {
+                if variables.iter().any(|(name, _)| name == &caps[1]) {
+                    format!("${{{}}}", &caps[1])
+                } else {
+                    caps[0].to_string()
+                }
+            });
+            // A shell variable stays escaped as `$${NAME}`, so that a compi
+            // variable of the same name is not substituted for it.
+            AUTOMATIC
+                .replace_all(&line, |caps: &Captures| match &caps[0] {
+                    "$${" => "$${".to_string(),
+                    "$$" => "$".to_string(),
+                    _ if caps.get(1).is_some() => format!("$${{{}}}", &caps[1]),
+                    "$@" => rule.target.clone(),
+                    "$<" => first.clone(),
+                    _ => all.clone(),
+                })
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(" && ")
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+fn toml_array(values: &[&String]) -> String {
+    let items: Vec<String> = values.iter().map(|v| toml_string(v)).collect();
+    format!("[{}]", items.join(", "))
+}
@@ -0,0 +1,124 @@
+use ::jobserver::{Acquired, Client, FromEnvErrorKind};
+use std::sync::{
+    OnceLock,
+    atomic::{AtomicBool, Ordering},
+};
+use tokio::process::Command;
+
+use crate::logging;
+
+static JOBSERVER: OnceLock<Option<Jobserver>> = OnceLock::new();
+/// Whether the token compi implicitly owns is free. Like make, compi may
+/// always run one job without taking a token from the pipe.
+static IMPLICIT_TOKEN: AtomicBool = AtomicBool::new(true);
+
+struct Jobserver {
+    client: Client,
+    /// Joined from the make that started compi rather than created by it.
+    inherited: bool,
+}
+
+/// Joins the GNU make jobserver of the make or cargo that started compi,
+/// unless `enabled` is `Some(false)`. With `Some(true)` and no jobserver to
+/// join, creates one with `workers` job slots that the commands compi starts
+/// share. Either way, nested `make` and `cargo` invocations take their jobs
+/// from the same slots instead of adding their own parallelism on top.
+pub fn init(enabled: Option<bool>, workers: usize) {
+    JOBSERVER.get_or_init(|| match enabled {
+        Some(false) => None,
+        create => connect(create == Some(true), workers),
+    });
+}
+
+fn connect(create: bool, workers: usize) -> Option<Jobserver> {
+    // SAFETY: called at startup, while the descriptors named in MAKEFLAGS
+    // have not been reused for anything else.
+    let from_env = unsafe { Client::from_env_ext(true) };
+    match from_env.client {
+        Ok(client) => {
+            logging::debug("Using the jobserver of the parent make");
+            return Some(Jobserver {
+                client,
+                inherited: true,
+            });
+        }
+        Err(e) => {
+            if let Some((var, _)) = from_env.var
+                && !matches!(
+                    e.kind(),
+                    FromEnvErrorKind::NoEnvVar
+                        | FromEnvErrorKind::NoJobserver
+                        | FromEnvErrorKind::NegativeFd
+                )
+            {
+                logging::warn(format!("Ignoring the jobserver in {}: {}", var, e));
+            }
+        }
+    }
+
+    if !create {
+        return None;
+    }
+    match Client::new(workers.saturating_sub(1)) {
+        Ok(client) => Some(Jobserver {
+            client,
+            inherited: false,
+        }),
+        Err(e) => {
+            logging::warn(format!("Failed to create a jobserver: {}", e));
+            None
+        }
+    }
+}
+
+/// Lets a command take jobs from the jobserver. A jobserver created by compi
+/// is announced through `MAKEFLAGS`; an inherited one is already named there.
+pub fn configure(cmd: &mut Command) {
+    let Some(jobserver) = JOBSERVER.get().and_then(Option::as_ref) else {
+        return;
+    };
+    if jobserver.inherited {
+        jobserver.client.configure(cmd.as_std_mut());
+    } else {
+        jobserver.client.configure_make(cmd.as_std_mut());
+    }
+}
+
+/// A job slot, held for as long as a task runs.
+pub enum Token {
+    Implicit,
+    Acquired(Acquired),
+    /// No jobserver is in use.
+    Unlimited,
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        if let Token::Implicit = self {
+            IMPLICIT_TOKEN.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Waits for a job slot, taking the implicit one when it is free.
+pub async fn acquire() -> Token {
+    let Some(jobserver) = JOBSERVER.get().and_then(Option::as_ref) else {
+        return Token::Unlimited;
+    };
+    if IMPLICIT_TOKEN.swap(false, Ordering::SeqCst) {
+        return Token::Implicit;
+    }
+
+    let client = jobserver.client.clone();
+    match tokio::task::spawn_blocking(move || client.acquire()).await {
+        Ok(Ok(acquired)) => Token::Acquired(acquired),
+        Ok(Err(e)) => {
+            logging::warn(format!("Failed to acquire a jobserver token: {}", e));
+            Token::Unlimited
+        }
+        Err(e) => {
+            logging::warn(format!("Failed to acquire a jobserver token: {}", e));
+            Token::Unlimited
+        }
+    }
+}
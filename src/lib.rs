@@ -0,0 +1,109 @@
+//! The engine behind the `compi` build tool.
+//!
+//! Tasks are loaded from a `compi.toml` with [`load_tasks`], resolved into an
+//! execution order with [`resolve_task_list`], and run by a [`TaskRunner`],
+//! which skips tasks whose inputs are unchanged since they were last cached.
+//!
+//! ```no_run
+//! use compi::{
+//!     RunOptions, TaskRunner, load_cache, load_history, load_tasks, resolve_task_list, save_cache,
+//! };
+//!
+//! # async fn run() -> compi::Result<()> {
+//! let config = load_tasks("compi.toml")?;
+//! let order = resolve_task_list(&config.tasks, Some("build"))?;
+//!
+//! let mut cache = load_cache(config.cache_dir.as_deref(), "compi.toml");
+//! let mut history = load_history(config.cache_dir.as_deref(), "compi.toml");
+//! let options = RunOptions {
+//!     continue_on_failure: true,
+//!     ..RunOptions::default()
+//! };
+//! let mut runner = TaskRunner::new(
+//!     &config.tasks,
+//!     &mut cache,
+//!     &mut history,
+//!     &config.root,
+//!     options,
+//! );
+//! if runner.run_tasks(&order).await {
+//!     save_cache(&cache, config.cache_dir.as_deref(), "compi.toml");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Messages and task output go through [`logging`], which prints text by
+//! default; call [`logging::init`] first to choose another format.
+
+pub mod cache;
+pub mod error;
+pub mod execution;
+pub mod history;
+pub mod logging;
+pub mod output;
+pub mod task;
+
+// The rest is shared with the `compi` binary and is not part of the
+// library's API: it may change in any release.
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod bench;
+#[doc(hidden)]
+pub mod ci;
+#[doc(hidden)]
+pub mod diagnostics;
+#[doc(hidden)]
+pub mod doctor;
+#[doc(hidden)]
+pub mod dotenv;
+#[doc(hidden)]
+pub mod export;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod import;
+#[doc(hidden)]
+pub mod jobserver;
+#[doc(hidden)]
+pub mod lint;
+#[doc(hidden)]
+pub mod list;
+#[doc(hidden)]
+pub mod mask;
+#[doc(hidden)]
+pub mod plan;
+#[doc(hidden)]
+pub mod plugin;
+#[doc(hidden)]
+pub mod process;
+#[doc(hidden)]
+pub mod report;
+#[doc(hidden)]
+pub mod schedule;
+#[doc(hidden)]
+pub mod spool;
+#[doc(hidden)]
+pub mod telemetry;
+#[doc(hidden)]
+pub mod trace;
+#[doc(hidden)]
+pub mod tree;
+#[doc(hidden)]
+pub mod update;
+#[doc(hidden)]
+pub mod vcs;
+#[doc(hidden)]
+pub mod webhook;
+#[doc(hidden)]
+pub mod why;
+
+mod sandbox;
+mod util;
+
+pub use cache::{Cache, load_cache, save_cache};
+pub use error::{CompiError, Result};
+pub use execution::{RunOptions, RunSummary, TaskResult, TaskRunner, TaskStatus};
+pub use history::{History, load_history, save_history};
+pub use task::{Task, TaskConfiguration, load_tasks, resolve_task_list};
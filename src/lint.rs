@@ -0,0 +1,361 @@
+use clap::ValueEnum;
+use glob::Pattern;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::error::Result;
+use crate::execution::dependency_env_name;
+use crate::task::{Task, resolve_targets, schema};
+use crate::util::{exclusion, is_glob_pattern, strip_cur_dir};
+
+/// A kind of dead weight that `compi lint` looks for.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// A `[variables]` entry that nothing mentions.
+    UnusedVariable,
+    /// A task that the `default` tasks do not need.
+    UnreachableTask,
+    /// A dependency that another dependency already depends on.
+    RedundantDependency,
+    /// An input that another input of the same task already covers.
+    SubsumedInput,
+}
+
+impl Rule {
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnreachableTask => "unreachable-task",
+            Rule::RedundantDependency => "redundant-dependency",
+            Rule::SubsumedInput => "subsumed-input",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LintFormat {
+    /// One line per finding, followed by how to fix it.
+    #[default]
+    Text,
+    /// A JSON array of findings on stdout.
+    Json,
+}
+
+/// Something `compi lint` found, with what to do about it.
+#[derive(Serialize, Clone, Debug)]
+pub struct Finding {
+    pub rule: Rule,
+    /// The task the finding is about, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    /// The config file the finding is about, for variables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub message: String,
+    pub fix: String,
+}
+
+impl Finding {
+    fn task(rule: Rule, task: &str, message: String, fix: impl Into<String>) -> Self {
+        Self {
+            rule,
+            task: Some(task.to_string()),
+            file: None,
+            message,
+            fix: fix.into(),
+        }
+    }
+}
+
+/// Lints the loaded `tasks` and the raw config files they came from: the
+/// project's, including overlays, and each workspace member's.
+pub fn examine(
+    tasks: &[Task],
+    default_tasks: &[String],
+    project_paths: &[&str],
+    member_paths: &[&str],
+) -> Result<Vec<Finding>> {
+    let mut findings = check_variables(project_paths, member_paths)?;
+    findings.extend(check_reachability(tasks, default_tasks)?);
+    for task in tasks {
+        findings.extend(check_dependencies(task, tasks));
+        findings.extend(check_inputs(task));
+    }
+    Ok(findings)
+}
+
+/// A config file's `[variables]`, and every other string value in it.
+struct Source<'a> {
+    path: &'a str,
+    variables: Vec<(String, String)>,
+    text: Vec<String>,
+}
+
+/// A variable is used when its name appears as a word in a string value of
+/// its config file, or of any file for the project's own variables, other
+/// than in the value of a variable that is unused itself.
+fn check_variables(project_paths: &[&str], member_paths: &[&str]) -> Result<Vec<Finding>> {
+    let mut sources = Vec::new();
+    for &path in project_paths.iter().chain(member_paths) {
+        let contents = fs::read_to_string(path)?;
+        let mut table: toml::Table = schema::parse(&contents, path)?;
+
+        let mut variables: Vec<(String, String)> = match table.remove("variables") {
+            Some(toml::Value::Table(variables)) => variables
+                .into_iter()
+                .map(|(name, value)| {
+                    let mut strings = Vec::new();
+                    collect_strings(&value, &mut strings);
+                    (name, strings.join("\n"))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        variables.sort();
+
+        let mut text = Vec::new();
+        collect_strings(&toml::Value::Table(table), &mut text);
+        sources.push(Source {
+            path,
+            variables,
+            text,
+        });
+    }
+
+    let words: HashMap<&str, Regex> = sources
+        .iter()
+        .flat_map(|source| &source.variables)
+        .filter_map(|(name, _)| {
+            let word = Regex::new(&format!(r"\b{}\b", regex::escape(name))).ok()?;
+            Some((name.as_str(), word))
+        })
+        .collect();
+    let mentions = |text: &str, name: &str| words.get(name).is_some_and(|w| w.is_match(text));
+    let mut used: HashSet<(usize, &str)> = HashSet::new();
+    loop {
+        let mut found = Vec::new();
+        for (index, source) in sources.iter().enumerate() {
+            let scope: Vec<usize> = if index < project_paths.len() {
+                (0..sources.len()).collect()
+            } else {
+                vec![index]
+            };
+            for (name, _) in &source.variables {
+                if used.contains(&(index, name.as_str())) {
+                    continue;
+                }
+                let is_mentioned = scope.iter().any(|&i| {
+                    sources[i].text.iter().any(|text| mentions(text, name))
+                        || sources[i].variables.iter().any(|(other, value)| {
+                            other != name
+                                && used.contains(&(i, other.as_str()))
+                                && mentions(value, name)
+                        })
+                });
+                if is_mentioned {
+                    found.push((index, name.as_str()));
+                }
+            }
+        }
+        if found.is_empty() {
+            break;
+        }
+        used.extend(found);
+    }
+
+    let mut findings = Vec::new();
+    for (index, source) in sources.iter().enumerate() {
+        for (name, _) in &source.variables {
+            if !used.contains(&(index, name.as_str())) {
+                findings.push(Finding {
+                    rule: Rule::UnusedVariable,
+                    task: None,
+                    file: Some(source.path.to_string()),
+                    message: format!("Variable '{}' in '{}' is not used", name, source.path),
+                    fix: format!(
+                        "Remove it from [variables], or reference it as ${{{}}} or {{{{ {} }}}}",
+                        name, name
+                    ),
+                });
+            }
+        }
+    }
+    Ok(findings)
+}
+
+fn collect_strings(value: &toml::Value, strings: &mut Vec<String>) {
+    match value {
+        toml::Value::String(text) => strings.push(text.clone()),
+        toml::Value::Array(values) => {
+            for value in values {
+                collect_strings(value, strings);
+            }
+        }
+        toml::Value::Table(table) => {
+            for value in table.values() {
+                collect_strings(value, strings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Tasks that running compi without a task never runs. Without `default`
+/// tasks only run by name or with `--all`, so none is reported.
+fn check_reachability(tasks: &[Task], default_tasks: &[String]) -> Result<Vec<Finding>> {
+    if default_tasks.is_empty() {
+        return Ok(Vec::new());
+    }
+    let reachable: HashSet<String> = resolve_targets(tasks, default_tasks)?.into_iter().collect();
+
+    Ok(tasks
+        .iter()
+        .filter(|task| !reachable.contains(&task.id))
+        .map(|task| {
+            Finding::task(
+                Rule::UnreachableTask,
+                &task.id,
+                format!(
+                    "Task '{}' is not run by default, and no default task depends on it",
+                    task.id
+                ),
+                "Remove it, add it to [config] default or a dependency of a default task, or allow the rule if it is meant to be run by name",
+            )
+        })
+        .collect())
+}
+
+/// Dependencies that another dependency of the task already depends on,
+/// directly or not. Those that `pipe_from` names or whose `DEP_<id>_OUTPUTS`
+/// the task uses are needed as direct dependencies.
+fn check_dependencies(task: &Task, tasks: &[Task]) -> Vec<Finding> {
+    let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
+    let commands: Vec<&str> = [
+        Some(&task.command),
+        task.hooks.before.as_ref(),
+        task.hooks.after.as_ref(),
+        task.hooks.on_success.as_ref(),
+        task.hooks.on_failure.as_ref(),
+        task.hooks.finally.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(String::as_str)
+    .collect();
+
+    let mut findings = Vec::new();
+    for dep in &task.dependencies {
+        if task.pipe_from.as_ref() == Some(dep) {
+            continue;
+        }
+        let env_name = dependency_env_name(dep);
+        if commands.iter().any(|command| command.contains(&env_name)) {
+            continue;
+        }
+
+        let via = task
+            .dependencies
+            .iter()
+            .filter(|other| *other != dep)
+            .find(|other| depends_on(other, dep, &task_map, &mut HashSet::new()));
+        if let Some(via) = via {
+            findings.push(Finding::task(
+                Rule::RedundantDependency,
+                &task.id,
+                format!(
+                    "Task '{}' depends on '{}', which '{}' already depends on",
+                    task.id, dep, via
+                ),
+                format!("Remove '{}' from its dependencies", dep),
+            ));
+        }
+    }
+    findings
+}
+
+fn depends_on<'a>(
+    task_id: &'a str,
+    target: &str,
+    task_map: &HashMap<&str, &'a Task>,
+    visited: &mut HashSet<&'a str>,
+) -> bool {
+    if !visited.insert(task_id) {
+        return false;
+    }
+    let Some(task) = task_map.get(task_id) else {
+        return false;
+    };
+    task.dependencies
+        .iter()
+        .any(|dep| dep == target || depends_on(dep, target, task_map, visited))
+}
+
+/// Inputs that select nothing other inputs do not. An entry is covered by a
+/// later one regardless of exclusions between them, since the later entry
+/// adds its files back, and by an earlier one only when no exclusion lies
+/// between them.
+fn check_inputs(task: &Task) -> Vec<Finding> {
+    let inputs = &task.inputs;
+    let mut findings = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        if exclusion(input).is_some() {
+            continue;
+        }
+
+        // Of two entries for the same files, the later one is reported.
+        let later = inputs[index + 1..]
+            .iter()
+            .filter(|other| exclusion(other).is_none())
+            .filter(|other| strip_cur_dir(other) != strip_cur_dir(input))
+            .find(|other| covers(other, input));
+        let earlier = || {
+            inputs[..index]
+                .iter()
+                .rev()
+                .take_while(|other| exclusion(other).is_none())
+                .find(|other| covers(other, input))
+        };
+        if let Some(other) = later.or_else(earlier) {
+            findings.push(Finding::task(
+                Rule::SubsumedInput,
+                &task.id,
+                format!(
+                    "Input '{}' of task '{}' is already covered by '{}'",
+                    input.display(),
+                    task.id,
+                    other.display()
+                ),
+                format!("Remove '{}' from its inputs", input.display()),
+            ));
+        }
+    }
+    findings
+}
+
+/// Whether every file `inner` selects is also selected by `outer`. Two
+/// globs are only compared when `outer` is a directory followed by `**`.
+fn covers(outer: &Path, inner: &Path) -> bool {
+    let (outer, inner) = (strip_cur_dir(outer), strip_cur_dir(inner));
+    if outer == inner {
+        return true;
+    }
+
+    let (outer_str, inner_str) = (outer.to_string_lossy(), inner.to_string_lossy());
+    match (is_glob_pattern(&outer_str), is_glob_pattern(&inner_str)) {
+        (false, _) => inner.starts_with(&outer),
+        (true, false) => Pattern::new(&outer_str).is_ok_and(|pattern| pattern.matches_path(&inner)),
+        (true, true) => ["**", "**/*"].iter().any(|suffix| {
+            outer
+                .to_str()
+                .and_then(|o| o.strip_suffix(suffix))
+                .map(|dir| dir.trim_end_matches('/'))
+                .is_some_and(|dir| !is_glob_pattern(dir) && inner.starts_with(dir))
+        }),
+    }
+}
@@ -0,0 +1,45 @@
+use std::{collections::HashMap, fmt::Write as _, time::SystemTime};
+
+use crate::history::History;
+use crate::logging::{self, Style};
+use crate::plan::PlannedTask;
+use crate::task::Task;
+
+/// Lists every task with whether it is cached or stale and its most recent
+/// result from the run history.
+pub fn render(tasks: &[Task], planned: &[PlannedTask], history: &History) -> String {
+    let planned: HashMap<&str, &PlannedTask> = planned.iter().map(|t| (t.id.as_str(), t)).collect();
+    let now = SystemTime::now();
+
+    let rows: Vec<(&str, String, Style, String)> = tasks
+        .iter()
+        .map(|task| {
+            let (state, style) = match planned.get(task.id.as_str()) {
+                Some(planned) if planned.run => {
+                    (format!("stale ({})", planned.reason), Style::Skipped)
+                }
+                Some(_) => ("cached".to_string(), Style::Ok),
+                None => ("not planned".to_string(), Style::Skipped),
+            };
+            let last = history
+                .last_result(&task.id, now)
+                .unwrap_or_else(|| "never run".to_string());
+            (task.id.as_str(), state, style, last)
+        })
+        .collect();
+
+    // Padding is added before painting, since color codes have no width.
+    let id_width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0);
+    let state_width = rows.iter().map(|row| row.1.len()).max().unwrap_or(0);
+    let mut text = String::new();
+    for (id, state, style, last) in rows {
+        let _ = writeln!(
+            text,
+            "{}  {}  {}",
+            logging::paint(&format!("{:<1$}", id, id_width), Style::TaskId),
+            logging::paint(&format!("{:<1$}", state, state_width), style),
+            last
+        );
+    }
+    text
+}
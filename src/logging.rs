@@ -0,0 +1,537 @@
+use crate::ci::{self, Ci, escape_workflow_data};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::execution::{RunSummary, TaskResult, TaskStatus};
+use crate::mask;
+use crate::output::ColorChoice;
+use crate::spool::CommandOutput;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable text output.
+    #[default]
+    Text,
+    /// One JSON event per line on stderr.
+    Json,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Style {
+    Ok,
+    Failed,
+    Skipped,
+    TaskId,
+}
+
+struct Logger {
+    format: LogFormat,
+    verbose: bool,
+    color: bool,
+    ci: Option<Ci>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+static LOG_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+pub fn init(format: LogFormat, verbose: bool, color: ColorChoice) {
+    let _ = LOGGER.set(Logger {
+        format,
+        verbose,
+        color: format == LogFormat::Text && color.enabled(),
+        ci: ci::detect(),
+    });
+}
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| Logger {
+        format: LogFormat::Text,
+        verbose: false,
+        color: false,
+        ci: ci::detect(),
+    })
+}
+
+/// Mirrors everything printed during the run, plus all task output, to `path`
+/// with a timestamp on every line.
+pub fn open_log_file(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let file = File::create(path)?;
+    let _ = LOG_FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+/// Wraps `text` in ANSI color codes when color output is enabled.
+pub fn paint(text: &str, style: Style) -> String {
+    if !logger().color {
+        return text.to_string();
+    }
+
+    let code = match style {
+        Style::Ok => "32",
+        Style::Failed => "1;31",
+        Style::Skipped => "33",
+        Style::TaskId => "36",
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+pub(crate) fn is_json() -> bool {
+    logger().format == LogFormat::Json
+}
+
+pub(crate) fn is_verbose() -> bool {
+    logger().verbose
+}
+
+pub fn info(message: impl Display) {
+    match logger().format {
+        LogFormat::Text => print_out(message),
+        LogFormat::Json => {
+            mirror_to_log(&message.to_string());
+            emit_message("info", message);
+        }
+    }
+}
+
+pub fn debug(message: impl Display) {
+    if !is_verbose() {
+        return;
+    }
+
+    match logger().format {
+        LogFormat::Text => print_out(message),
+        LogFormat::Json => {
+            mirror_to_log(&message.to_string());
+            emit_message("debug", message);
+        }
+    }
+}
+
+pub fn warn(message: impl Display) {
+    match logger().format {
+        LogFormat::Text => print_err(format!("{} {}", paint("Warning:", Style::Skipped), message)),
+        LogFormat::Json => {
+            mirror_to_log(&format!("Warning: {}", message));
+            emit_message("warn", message);
+        }
+    }
+}
+
+pub fn error(message: impl Display) {
+    match logger().format {
+        LogFormat::Text => print_err(format!("{} {}", paint("Error:", Style::Failed), message)),
+        LogFormat::Json => {
+            mirror_to_log(&format!("Error: {}", message));
+            emit_message("error", message);
+        }
+    }
+}
+
+/// Reports why a task failed, adding a GitHub Actions error annotation when running there.
+pub(crate) fn task_error(task_id: &str, message: impl Display) {
+    error(&message);
+
+    let logger = logger();
+    if logger.format == LogFormat::Text && logger.ci == Some(Ci::GithubActions) {
+        println!(
+            "::error title={}::{} (see the '{}' group in the job log)",
+            escape_workflow_property(&format!("compi: task {} failed", task_id)),
+            escape_workflow_data(&mask::text(&message.to_string())),
+            escape_workflow_data(task_id)
+        );
+    }
+}
+
+pub(crate) fn task_started(task_id: &str) {
+    match logger().format {
+        LogFormat::Text => {
+            if is_verbose() {
+                println!("Running task: {}", task_id);
+            }
+        }
+        LogFormat::Json => emit("task_started", json!({ "task": task_id })),
+    }
+    mirror_to_log(&format!("Running task: {}", task_id));
+}
+
+/// Streams task output to the terminal as it arrives. `data` should end at a
+/// line break, so that secrets in it are masked whole.
+pub(crate) fn task_output_chunk(
+    task_id: &str,
+    stream: OutputStream,
+    data: &[u8],
+) -> io::Result<()> {
+    let data = &mask::bytes(data);
+    record_task_output(task_id, stream, data);
+
+    match logger().format {
+        LogFormat::Text => {
+            let prefix = format!("{} | ", paint(task_id, Style::TaskId));
+            let mut prefixed = Vec::with_capacity(data.len() + prefix.len());
+            for line in data.split_inclusive(|&b| b == b'\n') {
+                prefixed.extend_from_slice(prefix.as_bytes());
+                prefixed.extend_from_slice(line);
+            }
+            if prefixed.last() != Some(&b'\n') {
+                prefixed.push(b'\n');
+            }
+
+            match stream {
+                OutputStream::Stdout => {
+                    let mut out = io::stdout().lock();
+                    out.write_all(&prefixed)?;
+                    out.flush()
+                }
+                OutputStream::Stderr => {
+                    let mut err = io::stderr().lock();
+                    err.write_all(&prefixed)?;
+                    err.flush()
+                }
+            }
+        }
+        LogFormat::Json => {
+            emit(
+                "task_output_chunk",
+                json!({
+                    "task": task_id,
+                    "stream": stream,
+                    "data": String::from_utf8_lossy(data),
+                }),
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Writes task output to the log file only, for output that is not streamed to the terminal.
+pub(crate) fn record_task_output(task_id: &str, stream: OutputStream, data: &[u8]) {
+    let Some(file) = LOG_FILE.get() else {
+        return;
+    };
+
+    let label = match stream {
+        OutputStream::Stdout => task_id.to_string(),
+        OutputStream::Stderr => format!("{} stderr", task_id),
+    };
+    let timestamp = log_timestamp();
+    let data = mask::bytes(data);
+    let text = String::from_utf8_lossy(&data);
+
+    let mut file = file.lock().unwrap();
+    for line in text.lines() {
+        let _ = writeln!(file, "{} [{}] {}", timestamp, label, line);
+    }
+}
+
+pub(crate) fn task_output_group(task_id: &str, output: &CommandOutput) {
+    if is_json() {
+        for (stream, data) in [
+            (OutputStream::Stdout, &output.stdout),
+            (OutputStream::Stderr, &output.stderr),
+        ] {
+            if !data.is_empty() {
+                let mut lines = mask::Lines::default();
+                let _ = data.for_each_chunk(|chunk| match lines.push(chunk) {
+                    Some(lines) => task_output_chunk(task_id, stream, &lines),
+                    None => Ok(()),
+                });
+                if let Some(rest) = lines.finish() {
+                    let _ = task_output_chunk(task_id, stream, &rest);
+                }
+            }
+        }
+        return;
+    }
+
+    if let Some(ci) = logger().ci
+        && let Some(start) = ci.section_start(task_id)
+    {
+        // Both streams go to stdout so the section folds as one block in the job log.
+        let mut out = io::stdout().lock();
+        let _ = writeln!(out, "{}", start);
+        for data in [&output.stdout, &output.stderr] {
+            if !data.is_empty() {
+                let _ = data.write_to(&mut mask::Writer::new(&mut out));
+                if !data.ends_with_newline() {
+                    let _ = writeln!(out);
+                }
+            }
+        }
+        if let Some(end) = ci.section_end(task_id) {
+            let _ = writeln!(out, "{}", end);
+        }
+        let _ = out.flush();
+        return;
+    }
+
+    if !output.stdout.is_empty() {
+        let mut out = io::stdout();
+        let _ = writeln!(out, "---- {} (stdout) ----", paint(task_id, Style::TaskId));
+        let _ = output.stdout.write_to(&mut mask::Writer::new(&mut out));
+        if !output.stdout.ends_with_newline() {
+            let _ = writeln!(out);
+        }
+        let _ = out.flush();
+    }
+
+    if !output.stderr.is_empty() {
+        let mut err = io::stderr();
+        let _ = writeln!(err, "---- {} (stderr) ----", paint(task_id, Style::TaskId));
+        let _ = output.stderr.write_to(&mut mask::Writer::new(&mut err));
+        if !output.stderr.ends_with_newline() {
+            let _ = writeln!(err);
+        }
+        let _ = err.flush();
+    }
+}
+
+pub(crate) fn task_finished(result: &TaskResult) {
+    if !is_json() {
+        let (label, style) = match result.status {
+            TaskStatus::Success => ("ok", Style::Ok),
+            TaskStatus::Failed => ("failed", Style::Failed),
+            TaskStatus::TimedOut => ("timeout", Style::Failed),
+            TaskStatus::Skipped => ("skipped", Style::Skipped),
+        };
+        let detail = match result.status {
+            TaskStatus::Skipped => "up-to-date".to_string(),
+            _ => format!("{:.2}s", result.duration.as_secs_f64()),
+        };
+        print_out(format!(
+            "{} {} ({})",
+            paint(&format!("{:<8}", label), style),
+            paint(&result.task_id, Style::TaskId),
+            detail
+        ));
+    } else {
+        mirror_to_log(&format!(
+            "Task '{}' finished: {:?} ({:.2}s)",
+            result.task_id,
+            result.status,
+            result.duration.as_secs_f64()
+        ));
+        emit(
+            "task_finished",
+            json!({
+                "task": result.task_id,
+                "status": result.status,
+                "duration_ms": result.duration.as_millis(),
+                "exit_code": result.exit_code,
+            }),
+        );
+    }
+}
+
+/// Lists the problem matchers' findings of the whole run, as GitHub Actions
+/// annotations as well when running there.
+pub fn diagnostics(diagnostics: &[Diagnostic]) {
+    if diagnostics.is_empty() {
+        return;
+    }
+
+    let logger = logger();
+    if logger.format == LogFormat::Json {
+        for diagnostic in diagnostics {
+            mirror_to_log(&diagnostic_line(diagnostic));
+        }
+        emit("diagnostics", json!({ "diagnostics": diagnostics }));
+        return;
+    }
+
+    print_out(format!("Diagnostics ({}):", diagnostics.len()));
+    for diagnostic in diagnostics {
+        let severity = match diagnostic.severity {
+            Severity::Error => paint("error", Style::Failed),
+            Severity::Warning => paint("warning", Style::Skipped),
+            Severity::Note => "note".to_string(),
+        };
+        print_out(format!(
+            "  {}: {}: {} ({})",
+            diagnostic.location(),
+            severity,
+            diagnostic.message,
+            paint(&diagnostic.task, Style::TaskId)
+        ));
+
+        if logger.ci == Some(Ci::GithubActions) {
+            let command = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "notice",
+            };
+            let mut properties = vec![format!(
+                "file={}",
+                escape_workflow_property(&diagnostic.file)
+            )];
+            properties.extend(diagnostic.line.map(|line| format!("line={}", line)));
+            properties.extend(diagnostic.column.map(|col| format!("col={}", col)));
+            properties.push(format!(
+                "title={}",
+                escape_workflow_property(&format!("compi: {}", diagnostic.task))
+            ));
+            println!(
+                "::{} {}::{}",
+                command,
+                properties.join(","),
+                escape_workflow_data(&mask::text(&diagnostic.message))
+            );
+        }
+    }
+}
+
+fn diagnostic_line(diagnostic: &Diagnostic) -> String {
+    format!(
+        "{}: {}: {} ({})",
+        diagnostic.location(),
+        diagnostic.severity.name(),
+        diagnostic.message,
+        diagnostic.task
+    )
+}
+
+pub(crate) fn run_summary(summary: &RunSummary) {
+    let failed = if summary.failed > 0 {
+        paint(&summary.failed.to_string(), Style::Failed)
+    } else {
+        summary.failed.to_string()
+    };
+    let mut lines = vec![format!(
+        "Finished in {:.2}s: {} executed, {} skipped, {} failed",
+        summary.duration_ms as f64 / 1000.0,
+        paint(&summary.executed.to_string(), Style::Ok),
+        paint(&summary.skipped.to_string(), Style::Skipped),
+        failed
+    )];
+    if summary.cacheable > 0 {
+        lines.push(format!(
+            "Cache: {}/{} cacheable tasks up-to-date ({:.0}% hit rate), ~{:.2}s saved",
+            summary.skipped,
+            summary.cacheable,
+            summary.hit_rate() * 100.0,
+            summary.time_saved_ms as f64 / 1000.0
+        ));
+    }
+    if let Some(path) = &summary.critical_path {
+        lines.push(format!(
+            "Critical path: {} ({:.2}s, average parallelism {:.2})",
+            path.tasks.join(" -> "),
+            path.duration_ms as f64 / 1000.0,
+            path.parallelism()
+        ));
+    }
+    for slowdown in &summary.slowdowns {
+        lines.push(format!(
+            "Note: task '{}' took {:.2}s, {:.1}x its recent average of {:.2}s",
+            slowdown.task,
+            slowdown.duration_ms as f64 / 1000.0,
+            slowdown.duration_ms as f64 / slowdown.average_ms.max(1) as f64,
+            slowdown.average_ms as f64 / 1000.0
+        ));
+    }
+
+    match logger().format {
+        LogFormat::Text => lines.into_iter().for_each(print_out),
+        LogFormat::Json => {
+            lines.iter().for_each(|line| mirror_to_log(line));
+            emit("run_summary", json!(summary));
+        }
+    }
+}
+
+fn print_out(line: impl Display) {
+    let line = mask::text(&line.to_string()).into_owned();
+    println!("{}", line);
+    mirror_to_log(&line);
+}
+
+fn print_err(line: impl Display) {
+    let line = mask::text(&line.to_string()).into_owned();
+    eprintln!("{}", line);
+    mirror_to_log(&line);
+}
+
+fn mirror_to_log(text: &str) {
+    let Some(file) = LOG_FILE.get() else {
+        return;
+    };
+
+    let timestamp = log_timestamp();
+    let plain = strip_ansi(&mask::text(text));
+
+    let mut file = file.lock().unwrap();
+    for line in plain.lines() {
+        let _ = writeln!(file, "{} {}", timestamp, line);
+    }
+}
+
+fn log_timestamp() -> String {
+    humantime::format_rfc3339_millis(SystemTime::now()).to_string()
+}
+
+fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+fn escape_workflow_property(value: &str) -> String {
+    escape_workflow_data(value)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+fn emit_message(level: &str, message: impl Display) {
+    emit(
+        "message",
+        json!({ "level": level, "message": mask::text(&message.to_string()) }),
+    );
+}
+
+fn emit(event: &str, fields: Value) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+
+    let mut record = json!({ "event": event, "timestamp_ms": timestamp });
+    if let (Value::Object(record), Value::Object(fields)) = (&mut record, fields) {
+        record.extend(fields);
+    }
+
+    let mut err = io::stderr().lock();
+    let _ = writeln!(err, "{}", record);
+}
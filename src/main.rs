@@ -1,64 +1,479 @@
 use clap::Parser;
-use std::process;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-mod cache;
 mod cli;
-mod error;
-mod execution;
-mod output;
-mod task;
-mod util;
-
-use cache::{load_cache, save_cache};
-use cli::Cli;
-use error::Result;
-use execution::TaskRunner;
-use output::OutputMode;
-use task::{get_required_tasks, load_tasks, show_task_relationships, sort_topologically};
+
+use cli::{Cli, Command, ImportSource};
+use compi::bench::{BenchStats, load_baselines, save_baselines, show_bench};
+use compi::doctor::{self, Checkup, Finding, FindingKind};
+use compi::dotenv::EnvVar;
+use compi::execution::{TaskStatus, default_workers};
+use compi::history::{
+    History, LAST_RUN_VERSION, LastRun, load_last_run, save_last_run, show_history,
+};
+use compi::lint::LintFormat;
+use compi::logging::Style;
+use compi::output::OutputMode;
+use compi::plan::{PLAN_VERSION, Plan, PlannedTask, check_plan, read_plan, write_plan};
+use compi::process::forward_interrupts;
+use compi::schedule::Schedule;
+use compi::task::{
+    LoadOptions, Task, WorkspaceMember, estimate_run_time, get_required_tasks, load_overlaid_tasks,
+    resolve_targets, schema, show_critical_path, show_task_relationships,
+};
+use compi::why::Explanation;
+use compi::{
+    Cache, CompiError, Result, RunOptions, TaskRunner, audit, ci, diagnostics, dotenv, export,
+    hooks, import, jobserver, lint, list, load_cache, load_history, logging, mask, plugin, report,
+    save_cache, save_history, spool, telemetry, trace, tree, update, vcs, webhook, why,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Cli::parse();
 
-    match run_compi(args).await {
+    let result = run_compi(args).await;
+    // On Ctrl-C, the interrupt handler exits once the `finally` hooks have run.
+    if compi::process::interrupted() {
+        if let Err(e) = &result {
+            logging::error(e);
+        }
+        std::future::pending::<()>().await;
+    }
+    match result {
         Ok(()) => Ok(()),
         Err(e) => {
-            eprintln!("Error: {}", e);
+            logging::error(e);
             process::exit(1);
         }
     }
 }
 
 async fn run_compi(args: Cli) -> Result<()> {
-    let config = load_tasks(&args.file)?;
+    let profile = args
+        .profile
+        .clone()
+        .or_else(|| env::var("COMPI_PROFILE").ok().filter(|p| !p.is_empty()));
+    let options = LoadOptions {
+        profile,
+        strict_variables: args.strict,
+        allow_missing: matches!(args.command, Some(Command::Exec { .. })),
+    };
+    let config_path = args.config_path().to_string();
+    let config_paths: Vec<&str> = args.file.iter().map(String::as_str).collect();
+    // Loading only stores its warnings, so the config can choose the log
+    // format before anything is printed.
+    let config = match reads_config(args.command.as_ref()) {
+        true => Some(load_overlaid_tasks(&config_paths, &options)),
+        false => None,
+    };
+    let config_log_format = match &config {
+        Some(Ok(config)) => config.log_format,
+        _ => None,
+    };
+    logging::init(
+        args.log_format.or(config_log_format).unwrap_or_default(),
+        args.verbose,
+        args.color.unwrap_or_default(),
+    );
+    let config = match config {
+        Some(config) => config?,
+        None => return run_without_config(&args),
+    };
+    mask::init(config.mask);
+    diagnostics::init(config.problem_matchers);
+    spool::init(config.output_memory_limit);
+    jobserver::init(
+        config.jobserver,
+        args.workers
+            .or(config.workers)
+            .unwrap_or_else(default_workers),
+    );
+    dotenv::init(&config.env);
+    forward_interrupts(&config.forward_signals);
+    let log_file = args
+        .log_file
+        .clone()
+        .or_else(|| config.log_file.as_ref().map(PathBuf::from));
+    if let Some(path) = &log_file
+        && let Err(e) = logging::open_log_file(path)
+    {
+        logging::warn(format!(
+            "Failed to open log file '{}': {}",
+            path.display(),
+            e
+        ));
+    }
+    for member in config
+        .members
+        .iter()
+        .filter(|m| !m.ignored_config.is_empty())
+    {
+        logging::warn(format!(
+            "Workspace member '{}' sets {} in [config], which only the root's [config] can set",
+            member.name,
+            member.ignored_config.join(", ")
+        ));
+    }
+    plugin::load(&config.plugins)?;
     let mut tasks = config.tasks;
 
-    show_task_relationships(&tasks, args.verbose);
+    match &args.command {
+        Some(Command::History { task }) => {
+            let history = load_history(config.cache_dir.as_deref(), &config_path);
+            show_history(&history, task.as_deref());
+            return Ok(());
+        }
+        Some(Command::CriticalPath { task }) => {
+            let targets = match task {
+                Some(task) => vec![task.clone()],
+                None if args.all => Vec::new(),
+                None => config.default_tasks.clone(),
+            };
+            let task_list = resolve_targets(&tasks, &targets)?;
+            tasks.retain(|task| task_list.contains(&task.id));
+
+            let history = load_history(config.cache_dir.as_deref(), &config_path);
+            let durations: HashMap<String, Duration> = tasks
+                .iter()
+                .filter_map(|t| Some((t.id.clone(), history.rolling_average(&t.id)?)))
+                .collect();
+            let workers = args
+                .workers
+                .or(config.workers)
+                .unwrap_or_else(default_workers);
+
+            show_critical_path(&target_label(&targets), &tasks, &durations, workers);
+            return Ok(());
+        }
+        Some(Command::Export {
+            format,
+            task,
+            per,
+            output,
+        }) => {
+            let targets = match task {
+                Some(task) => vec![task.clone()],
+                None if args.all => Vec::new(),
+                None => config.default_tasks.clone(),
+            };
+            let task_list = resolve_targets(&tasks, &targets)?;
+            tasks.retain(|task| task_list.contains(&task.id));
+
+            let tasks = with_path_lists(&tasks)?;
+            return export::export_ci(&tasks, *format, *per, output.as_deref());
+        }
+        Some(Command::Bench {
+            task,
+            iterations,
+            save_baseline,
+        }) => {
+            if *iterations == 0 {
+                return Err(CompiError::Task(
+                    "--iterations must be at least 1".to_string(),
+                ));
+            }
+            let task_list = get_required_tasks(&tasks, task)?;
+            tasks.retain(|t| task_list.contains(&t.id));
+
+            let mut cache = load_cache(config.cache_dir.as_deref(), &config_path);
+            let mut history = History::default();
+            let mut runner = TaskRunner::new(
+                &tasks,
+                &mut cache,
+                &mut history,
+                &config.root,
+                RunOptions {
+                    default_timeout: args.timeout.or(config.default_timeout),
+                    workers: args.workers.or(config.workers),
+                    output_mode: args
+                        .output
+                        .clone()
+                        .or(config.output.clone())
+                        .unwrap_or_default(),
+                    ..RunOptions::default()
+                },
+            );
+            runner.limit_tags(&config.tag_limits);
+            let results = runner.bench(&task_list, *iterations).await?;
+
+            let task_id = task_list.last().map(String::as_str).unwrap_or(task);
+            if results.iter().any(|r| r.status != TaskStatus::Success) {
+                return Err(CompiError::Task(format!(
+                    "Task '{}' failed on run {} of {}",
+                    task_id,
+                    results.len(),
+                    iterations
+                )));
+            }
 
-    let task_list = match &args.task {
-        Some(task_id) => get_required_tasks(&tasks, task_id)?,
-        None => {
-            if let Some(default) = &config.default_task {
-                get_required_tasks(&tasks, default)?
-            } else {
-                sort_topologically(&tasks)
+            let durations: Vec<Duration> = results.iter().map(|r| r.duration).collect();
+            let Some(stats) = BenchStats::from_durations(&durations) else {
+                return Ok(());
+            };
+            let mut baselines = load_baselines(config.cache_dir.as_deref(), &config_path);
+            show_bench(task_id, &stats, baselines.get(task_id));
+            if *save_baseline {
+                baselines.insert(task_id.to_string(), stats);
+                save_baselines(&baselines, config.cache_dir.as_deref(), &config_path);
+                logging::info(format!("Saved baseline for '{}'", task_id));
             }
+            return Ok(());
+        }
+        Some(Command::Env) => {
+            show_env(&config.env, config.profile.as_deref());
+            return Ok(());
+        }
+        Some(Command::Tree { task, ascii }) => {
+            let targets = match task {
+                Some(task) => vec![task.clone()],
+                None if args.all => Vec::new(),
+                None => config.default_tasks.clone(),
+            };
+            let task_list = resolve_targets(&tasks, &targets)?;
+            let (planned, history) = plan_tasks(
+                &tasks,
+                &task_list,
+                config.cache_dir.as_deref(),
+                &config_path,
+                &config.members,
+                &config.root,
+            );
+            print!(
+                "{}",
+                tree::render(&tasks, &targets, &planned, &history, *ascii)
+            );
+            return Ok(());
+        }
+        Some(Command::List) => {
+            let task_list = resolve_targets(&tasks, &[])?;
+            let (planned, history) = plan_tasks(
+                &tasks,
+                &task_list,
+                config.cache_dir.as_deref(),
+                &config_path,
+                &config.members,
+                &config.root,
+            );
+            print!("{}", list::render(&tasks, &planned, &history));
+            return Ok(());
+        }
+        Some(Command::Doctor) => {
+            let task_list = resolve_targets(&tasks, &[])?;
+            let (planned, history) = plan_tasks(
+                &tasks,
+                &task_list,
+                config.cache_dir.as_deref(),
+                &config_path,
+                &config.members,
+                &config.root,
+            );
+
+            let mut state = vec![(config.cache_dir.as_deref(), config_path.as_str())];
+            state.extend(
+                config
+                    .members
+                    .iter()
+                    .map(|m| (m.cache_dir.as_deref(), m.config_path.as_str())),
+            );
+            let findings = doctor::examine(&Checkup {
+                tasks: &with_path_lists(&tasks)?,
+                planned: &planned,
+                history: &history,
+                state,
+                wrapper: config.wrapper.as_deref(),
+            });
+            return show_doctor(&findings, tasks.len());
         }
+        Some(Command::Lint { format, allow }) => {
+            let member_paths: Vec<&str> = config
+                .members
+                .iter()
+                .map(|m| m.config_path.as_str())
+                .collect();
+            let mut findings =
+                lint::examine(&tasks, &config.default_tasks, &config_paths, &member_paths)?;
+            findings.retain(|finding| !allow.contains(&finding.rule));
+            return show_lint(&findings, *format);
+        }
+        Some(Command::Daemon) => return run_daemon(&args, &tasks).await,
+        Some(Command::Why { path }) => {
+            show_why(&why::explain(&with_path_lists(&tasks)?, path));
+            return Ok(());
+        }
+        Some(Command::Import {
+            source: ImportSource::Plugin { name, path, output },
+        }) => {
+            let generated = plugin::import(name, path)?;
+            return import::write_config(&generated, output.as_deref());
+        }
+        Some(Command::Exec {
+            inputs,
+            outputs,
+            id,
+            timeout,
+            command,
+        }) => {
+            tasks = vec![exec_task(
+                id,
+                inputs,
+                outputs,
+                timeout.as_deref(),
+                command,
+                config.wrapper.as_deref(),
+            )?];
+        }
+        Some(
+            Command::Import { .. }
+            | Command::Migrate
+            | Command::SelfUpdate { .. }
+            | Command::Plan { .. }
+            | Command::Apply { .. },
+        )
+        | None => {}
+    }
+
+    show_task_relationships(&tasks);
+
+    let applied = match &args.command {
+        Some(Command::Apply { plan }) => {
+            let plan = read_plan(plan)?;
+            check_plan(&plan, &tasks)?;
+            Some(plan)
+        }
+        _ => None,
+    };
+    let command_target = match &args.command {
+        Some(Command::Plan { task, .. }) => task.as_deref(),
+        Some(Command::Exec { id, .. }) => Some(id.as_str()),
+        _ => None,
     };
 
+    let last_run = match args.resume {
+        true => load_last_run(config.cache_dir.as_deref(), &config_path),
+        false => None,
+    };
+    if args.resume && last_run.is_none() {
+        logging::warn("No previous run to resume, running every task");
+    }
+
+    let targets = match (&applied, command_target.or(args.task.as_deref())) {
+        (Some(plan), _) => plan.targets.clone(),
+        (None, None) if !args.all && last_run.is_some() => last_run
+            .as_ref()
+            .map(|run| run.targets.clone())
+            .unwrap_or_default(),
+        (None, task) => select_targets(task, args.all, &config.default_tasks)?,
+    };
+    // The completed tasks of another target's run say nothing about this one.
+    if let Some(last_run) = &last_run
+        && last_run.targets != targets
+    {
+        return Err(CompiError::Task(format!(
+            "Cannot resume {}: the last run was for {}",
+            target_label(&targets),
+            target_label(&last_run.targets)
+        )));
+    }
+    let target = target_label(&targets);
+    let mut task_list = match &applied {
+        Some(plan) => plan.tasks.iter().map(|t| t.id.clone()).collect(),
+        None => resolve_targets(&tasks, &targets)?,
+    };
+
+    if let Some(since) = &args.since {
+        let changed = vcs::changed_files(since)?;
+        let affected = vcs::affected_tasks(&with_path_lists(&tasks)?, &changed);
+        logging::debug(format!("{} files changed since '{}'", changed.len(), since));
+        task_list.retain(|task_id| affected.contains(task_id));
+        if task_list.is_empty() {
+            logging::info(format!("No tasks affected by changes since '{}'", since));
+            return Ok(());
+        }
+    }
+
+    let run_list = task_list.clone();
+    if let Some(last_run) = &last_run {
+        let completed: HashSet<&str> = last_run.completed.iter().map(String::as_str).collect();
+        let pending = task_list
+            .iter()
+            .filter(|id| !completed.contains(id.as_str()))
+            .count();
+        if pending == 0 {
+            logging::info("Nothing to resume, every task completed in the last run");
+            return Ok(());
+        }
+
+        // Captured values, piped output and generated tasks are not recorded,
+        // so those tasks run again.
+        task_list.retain(|id| {
+            !completed.contains(id.as_str())
+                || tasks.iter().any(|t| {
+                    (&t.id == id && (t.capture.is_some() || t.generator))
+                        || t.pipe_from.as_ref() == Some(id)
+                })
+        });
+        logging::info(format!(
+            "Resuming {}: {} of {} tasks left",
+            target,
+            pending,
+            run_list.len()
+        ));
+    }
+
     tasks.retain(|task| task_list.contains(&task.id));
 
-    if args.verbose {
-        println!("Task execution order: {}", task_list.join(" -> "));
+    logging::debug(format!("Task execution order: {}", task_list.join(" -> ")));
+
+    let mut cache = load_cache(config.cache_dir.as_deref(), &config_path);
+    plugin::load_cache(&mut cache);
+    let mut member_caches: Vec<Cache> = config
+        .members
+        .iter()
+        .map(|m| load_cache(m.cache_dir.as_deref(), &m.config_path))
+        .collect();
+    let mut combined_cache = cache.clone();
+    for member_cache in &member_caches {
+        combined_cache.extend(member_cache.iter().cloned());
     }
+    let mut history = load_history(config.cache_dir.as_deref(), &config_path);
 
-    if args.dry_run {
-        println!("Dry run mode - showing what would be executed:");
-        for task_id in &task_list {
-            if let Some(task) = tasks.iter().find(|t| t.id == *task_id) {
-                println!("  {} would run: {}", task.id, task.command);
-            }
+    let planning = matches!(args.command, Some(Command::Plan { .. }));
+    if planning || args.dry_run {
+        let planned = TaskRunner::new(
+            &tasks,
+            &mut combined_cache,
+            &mut history,
+            &config.root,
+            RunOptions {
+                rm: args.rm,
+                ..RunOptions::default()
+            },
+        )
+        .plan(&task_list);
+
+        if let Some(Command::Plan { out, .. }) = &args.command {
+            let plan = Plan {
+                version: PLAN_VERSION,
+                config: args.file.join(","),
+                targets,
+                tasks: planned,
+            };
+            return write_plan(&plan, out.as_deref());
         }
+
+        let workers = args
+            .workers
+            .or(config.workers)
+            .unwrap_or_else(default_workers);
+        show_dry_run(&tasks, &planned, &history, workers);
         return Ok(());
     }
 
@@ -70,24 +485,573 @@ async fn run_compi(args: Cli) -> Result<()> {
         .or(config.output.clone())
         .unwrap_or(OutputMode::Group);
 
-    let mut cache = load_cache(config.cache_dir.as_deref(), &args.file);
+    if args.trace.is_some() {
+        trace::enable();
+    }
+    if args.audit_inputs {
+        audit::enable()?;
+    }
+    confirm_tasks(&tasks, args.yes)?;
+
+    let finally = hooks::arm_run(&config.hooks, config.wrapper.as_deref());
+    if let Err(e) = hooks::before_run(&config.hooks, config.wrapper.as_deref(), &output_mode).await
+    {
+        hooks::abort_run(finally, &output_mode).await;
+        return Err(e);
+    }
+
     let mut runner = TaskRunner::new(
         &tasks,
-        &mut cache,
-        args.rm,
-        args.verbose,
-        default_timeout,
-        workers,
-        args.continue_on_failure,
-        output_mode,
+        &mut combined_cache,
+        &mut history,
+        &config.root,
+        RunOptions {
+            rm: args.rm,
+            default_timeout,
+            workers,
+            continue_on_failure: args.continue_on_failure,
+            output_mode: output_mode.clone(),
+        },
     );
+    runner.limit_tags(&config.tag_limits);
+    runner.prepare_generated(&config.settings);
+    if let Some(plan) = &applied {
+        runner.follow_plan(&plan.tasks);
+    }
     let cache_changed = runner.run_tasks(&task_list).await;
+    let cache_additions = runner.cache_additions().to_vec();
+    let mut completed = last_run
+        .as_ref()
+        .map(|run| run.completed.clone())
+        .unwrap_or_default();
+    completed.extend(
+        runner
+            .results()
+            .iter()
+            .filter(|r| matches!(r.status, TaskStatus::Success | TaskStatus::Skipped))
+            .map(|r| r.task_id.clone()),
+    );
+    completed.sort();
+    completed.dedup();
+
+    hooks::after_run(
+        &config.hooks,
+        runner.results(),
+        runner.summary(),
+        finally,
+        config.wrapper.as_deref(),
+        &output_mode,
+    )
+    .await;
+
+    if let Err(e) = report::write_reports(&args.report, runner.results(), runner.summary()) {
+        logging::warn(e);
+    }
+
+    if let Some(trace_path) = &args.trace
+        && let Err(e) = trace::write(trace_path, runner.workers())
+    {
+        logging::warn(e);
+    }
+
+    if let Some(exporter) = telemetry::exporter_from_env(config.otel.as_deref()) {
+        let target = target.clone();
+        let results = runner.results().to_vec();
+        let summary = runner.summary().clone();
+        let exported = tokio::task::spawn_blocking(move || {
+            telemetry::export_run(&exporter, &target, &results, &summary)
+        })
+        .await;
+        if let Ok(Err(e)) = exported {
+            logging::warn(e);
+        }
+    }
+
+    if !config.webhooks.is_empty() {
+        let webhooks = config.webhooks.clone();
+        let target = target.clone();
+        let results = runner.results().to_vec();
+        let summary = runner.summary().clone();
+        let _ = tokio::task::spawn_blocking(move || {
+            webhook::notify(&webhooks, &target, &results, &summary)
+        })
+        .await;
+    }
+
+    if !config.plugins.is_empty() {
+        let target = target.clone();
+        let results = runner.results().to_vec();
+        let summary = runner.summary().clone();
+        let _ =
+            tokio::task::spawn_blocking(move || plugin::notify(&target, &results, &summary)).await;
+    }
+
+    save_history(&history, config.cache_dir.as_deref(), &config_path);
+
+    // `--resume` repeats configured runs, which an ad-hoc command is not.
+    if !matches!(args.command, Some(Command::Exec { .. })) {
+        let last_run = LastRun {
+            version: LAST_RUN_VERSION,
+            targets,
+            tasks: run_list,
+            completed,
+        };
+        save_last_run(&last_run, config.cache_dir.as_deref(), &config_path);
+    }
 
     if cache_changed {
-        save_cache(&cache, config.cache_dir.as_deref(), &args.file);
-    } else if args.verbose {
-        println!("No changes detected, cache not saved.");
+        // Each workspace member keeps the hashes of its own tasks.
+        for (task_id, hash) in cache_additions {
+            let member = task_id
+                .split_once(':')
+                .and_then(|(name, _)| config.members.iter().position(|m| m.name == name));
+            match member {
+                Some(index) => member_caches[index].insert(hash),
+                None => cache.insert(hash),
+            };
+        }
+
+        save_cache(&cache, config.cache_dir.as_deref(), &config_path);
+        for (member, member_cache) in config.members.iter().zip(&member_caches) {
+            save_cache(
+                member_cache,
+                member.cache_dir.as_deref(),
+                &member.config_path,
+            );
+        }
+        plugin::save_cache(&combined_cache);
+    } else {
+        logging::debug("No changes detected, cache not saved.");
     }
 
     Ok(())
 }
+
+/// Whether `command` needs the configuration, which all but a few do.
+fn reads_config(command: Option<&Command>) -> bool {
+    !matches!(
+        command,
+        Some(
+            Command::Import {
+                source: ImportSource::Make { .. },
+            } | Command::SelfUpdate { .. }
+                | Command::Migrate
+        )
+    )
+}
+
+/// Runs the subcommands that [`reads_config`] says work without the
+/// configuration.
+fn run_without_config(args: &Cli) -> Result<()> {
+    match &args.command {
+        Some(Command::Import {
+            source: ImportSource::Make { makefile, output },
+        }) => import::import_makefile(makefile, output.as_deref()),
+        Some(Command::SelfUpdate { check }) => update::self_update(*check),
+        Some(Command::Migrate) => {
+            for config_path in &args.file {
+                migrate(config_path)?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The task `compi exec` runs. A single argument is run by the shell as it
+/// is, several are quoted so they reach the program unchanged.
+fn exec_task(
+    id: &str,
+    inputs: &[PathBuf],
+    outputs: &[PathBuf],
+    timeout: Option<&str>,
+    command: &[String],
+    wrapper: Option<&str>,
+) -> Result<Task> {
+    let command = match command {
+        [command] => command.clone(),
+        args => shell_words::join(args),
+    };
+    let mut task = Task::new(id, command);
+    task.inputs = inputs.to_vec();
+    task.outputs = outputs.to_vec();
+    task.timeout = timeout.map(str::to_string);
+    task.wrapper = wrapper.map(str::to_string);
+    task.cache_key = Some(format!("{}\0{:?}", task.command, task.outputs));
+    task.resolve_paths(Path::new(""))?;
+    Ok(task)
+}
+
+/// Starts each task with a `schedule` at the start of every minute it is due,
+/// as a `compi <task>` run of its own, so that it is cached and recorded in
+/// the history like any other run. A task still running from its last start
+/// is not started again.
+async fn run_daemon(args: &Cli, tasks: &[Task]) -> Result<()> {
+    let scheduled: Vec<(&str, Schedule)> = tasks
+        .iter()
+        .filter_map(|task| {
+            let schedule = Schedule::parse(task.schedule.as_deref()?).ok()?;
+            Some((task.id.as_str(), schedule))
+        })
+        .collect();
+    if scheduled.is_empty() {
+        return Err(CompiError::Task(
+            "No task has a schedule, so there is nothing to run".to_string(),
+        ));
+    }
+    let ids: Vec<&str> = scheduled.iter().map(|(id, _)| *id).collect();
+    logging::info(format!(
+        "Waiting to run scheduled tasks: {}",
+        ids.join(", ")
+    ));
+
+    let exe = env::current_exe()?;
+    let mut running: HashMap<&str, tokio::process::Child> = HashMap::new();
+    loop {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let next_minute = Duration::from_secs((now.as_secs() / 60 + 1) * 60);
+        tokio::time::sleep(next_minute.saturating_sub(now)).await;
+
+        running.retain(|_, child| matches!(child.try_wait(), Ok(None)));
+        for (id, schedule) in &scheduled {
+            if !schedule.matches(UNIX_EPOCH + next_minute) {
+                continue;
+            }
+            if running.contains_key(id) {
+                logging::warn(format!(
+                    "Task '{}' is still running from its last schedule, skipping this one",
+                    id
+                ));
+                continue;
+            }
+
+            let mut command = tokio::process::Command::new(&exe);
+            command.arg("--file").arg(args.file.join(","));
+            if let Some(profile) = &args.profile {
+                command.arg("--profile").arg(profile);
+            }
+            if args.strict {
+                command.arg("--strict");
+            }
+            if args.verbose {
+                command.arg("--verbose");
+            }
+            // After `--`, so that an id starting with `-` is not read as a flag.
+            match command.arg("--").arg(id).spawn() {
+                Ok(child) => {
+                    logging::info(format!("Running scheduled task '{}'", id));
+                    running.insert(id, child);
+                }
+                Err(e) => logging::error(format!("Failed to start scheduled task '{}': {}", id, e)),
+            }
+        }
+    }
+}
+
+/// `tasks` with their `@file` lists read now, for commands that look at their
+/// inputs and outputs without running them.
+fn with_path_lists(tasks: &[Task]) -> Result<Vec<Task>> {
+    let mut tasks = tasks.to_vec();
+    for task in &mut tasks {
+        task.expand_path_lists()?;
+    }
+    Ok(tasks)
+}
+
+/// Asks whether to run each task with `confirm`, before anything runs. Without
+/// a terminal to ask on, such tasks only run with `--yes`.
+fn confirm_tasks(tasks: &[Task], yes: bool) -> Result<()> {
+    for task in tasks {
+        let Some(question) = &task.confirm else {
+            continue;
+        };
+        if yes {
+            logging::debug(format!("Task '{}' confirmed by --yes", task.id));
+            continue;
+        }
+        if let Some(ci) = ci::detect() {
+            return Err(CompiError::Task(format!(
+                "Task '{}' asks for confirmation ({}), pass --yes to run it in {}",
+                task.id,
+                question,
+                ci.name()
+            )));
+        }
+        if !io::stdin().is_terminal() {
+            return Err(CompiError::Task(format!(
+                "Task '{}' asks for confirmation ({}), pass --yes to run it without a terminal",
+                task.id, question
+            )));
+        }
+
+        eprint!(
+            "{} {} Run '{}'? [y/N] ",
+            logging::paint("Confirm:", Style::Skipped),
+            question,
+            task.id
+        );
+        let _ = io::stderr().flush();
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Err(CompiError::Task(format!(
+                "Task '{}' was not confirmed, nothing was run",
+                task.id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Decides which of `task_list` would run, without running anything, for
+/// commands that only report on the project.
+fn plan_tasks(
+    tasks: &[Task],
+    task_list: &[String],
+    cache_dir: Option<&str>,
+    config_path: &str,
+    members: &[WorkspaceMember],
+    root: &Path,
+) -> (Vec<PlannedTask>, History) {
+    let mut cache = load_cache(cache_dir, config_path);
+    plugin::load_cache(&mut cache);
+    for member in members {
+        cache.extend(load_cache(member.cache_dir.as_deref(), &member.config_path));
+    }
+    let mut history = load_history(cache_dir, config_path);
+    let planned = TaskRunner::new(tasks, &mut cache, &mut history, root, RunOptions::default())
+        .plan(task_list);
+    (planned, history)
+}
+
+/// Lists the tasks that would run with their average recorded durations, and
+/// estimates the run's wall time from those.
+fn show_dry_run(tasks: &[Task], planned: &[PlannedTask], history: &History, workers: usize) {
+    logging::info("Dry run mode - showing what would be executed:");
+
+    let mut durations: HashMap<String, Duration> = HashMap::new();
+    let mut unknown = 0;
+    for task in planned {
+        if !task.run {
+            logging::info(format!("  {} is up-to-date, would be skipped", task.id));
+            continue;
+        }
+
+        let estimate = match history.rolling_average(&task.id) {
+            Some(average) => {
+                durations.insert(task.id.clone(), average);
+                format!("~{:.2}s", average.as_secs_f64())
+            }
+            None => {
+                unknown += 1;
+                "no recorded duration".to_string()
+            }
+        };
+        logging::info(format!(
+            "  {} would run: {} ({})",
+            task.id, task.command, estimate
+        ));
+    }
+
+    if durations.is_empty() && unknown > 0 {
+        logging::info("No recorded durations to estimate the run from yet");
+        return;
+    }
+
+    let total = estimate_run_time(tasks, &durations, workers);
+    let note = match unknown {
+        0 => String::new(),
+        1 => ", not counting 1 task without a recorded duration".to_string(),
+        n => format!(", not counting {} tasks without a recorded duration", n),
+    };
+    logging::info(format!(
+        "Estimated duration: {:.2}s with {} worker{}{}",
+        total.as_secs_f64(),
+        workers,
+        if workers == 1 { "" } else { "s" },
+        note
+    ));
+}
+
+/// The tasks to run: `task`, every task with `--all`, or the default tasks.
+/// Without a default, running the whole graph has to be asked for.
+fn select_targets(task: Option<&str>, all: bool, default_tasks: &[String]) -> Result<Vec<String>> {
+    match task {
+        Some(task) if all => Err(CompiError::Task(format!(
+            "--all runs every task, so task '{}' cannot be given as well",
+            task
+        ))),
+        Some(task) => Ok(vec![task.to_string()]),
+        None if all => Ok(Vec::new()),
+        None if default_tasks.is_empty() => Err(CompiError::Task(
+            "No task given and no default set in [config]. Name a task, pass --all to run every task, or set `default = \"<task>\"` in [config]".to_string(),
+        )),
+        None => Ok(default_tasks.to_vec()),
+    }
+}
+
+/// Names the targets of a run in messages and notifications.
+fn target_label(targets: &[String]) -> String {
+    match targets {
+        [] => "all".to_string(),
+        targets => targets.join(", "),
+    }
+}
+
+fn show_doctor(findings: &[Finding], task_count: usize) -> Result<()> {
+    for finding in findings {
+        let label = match finding.kind {
+            FindingKind::Problem => logging::paint("Problem:", Style::Failed),
+            FindingKind::Note => logging::paint("Note:", Style::Skipped),
+        };
+        logging::info(format!("{} {}", label, finding.message));
+        logging::info(format!("  Fix: {}", finding.fix));
+    }
+
+    let problems = findings
+        .iter()
+        .filter(|f| f.kind == FindingKind::Problem)
+        .count();
+    if problems > 0 {
+        return Err(CompiError::Task(format!(
+            "Found {} problem{} in {} tasks",
+            problems,
+            if problems == 1 { "" } else { "s" },
+            task_count
+        )));
+    }
+    logging::info(format!("No problems found in {} tasks", task_count));
+    Ok(())
+}
+
+fn show_lint(findings: &[lint::Finding], format: LintFormat) -> Result<()> {
+    match format {
+        LintFormat::Text => {
+            for finding in findings {
+                let rule = format!("{}:", finding.rule.name());
+                logging::info(format!(
+                    "{} {}",
+                    logging::paint(&rule, Style::Failed),
+                    finding.message
+                ));
+                logging::info(format!("  Fix: {}", finding.fix));
+            }
+        }
+        LintFormat::Json => {
+            let json = serde_json::to_string_pretty(findings)
+                .map_err(|e| CompiError::Parse(format!("Failed to serialize findings: {}", e)))?;
+            println!("{}", json);
+        }
+    }
+
+    match findings.len() {
+        0 => {
+            if format == LintFormat::Text {
+                logging::info("No lint findings");
+            }
+            Ok(())
+        }
+        n => Err(CompiError::Task(format!(
+            "Found {} lint finding{}",
+            n,
+            if n == 1 { "" } else { "s" }
+        ))),
+    }
+}
+
+fn show_why(explanation: &Explanation) {
+    let path = explanation.path.display();
+    if explanation.producers.is_empty() && explanation.consumers.is_empty() {
+        logging::info(format!(
+            "'{}' is not an input or output of any task, so changing it reruns nothing",
+            path
+        ));
+        return;
+    }
+
+    if explanation.producers.is_empty() {
+        logging::info(format!("'{}' is not produced by any task", path));
+    } else {
+        logging::info(format!("'{}' is produced by:", path));
+        for producer in &explanation.producers {
+            logging::info(format!(
+                "  {} (output '{}')",
+                producer.task,
+                producer.entry.display()
+            ));
+        }
+    }
+
+    if explanation.consumers.is_empty() {
+        logging::info(format!(
+            "'{}' is not an input of any task, so changing it reruns nothing",
+            path
+        ));
+        return;
+    }
+    logging::info(format!("'{}' is an input of:", path));
+    for consumer in &explanation.consumers {
+        logging::info(format!(
+            "  {} (input '{}')",
+            consumer.task,
+            consumer.entry.display()
+        ));
+    }
+
+    if explanation.dependents.is_empty() {
+        logging::info("No other task depends on them, so changing it reruns only those");
+        return;
+    }
+    logging::info("Changing it also reruns the tasks that depend on them:");
+    for (task, via) in &explanation.dependents {
+        logging::info(format!("  {} (depends on {})", task, via));
+    }
+}
+
+fn show_env(vars: &[EnvVar], profile: Option<&str>) {
+    logging::info(format!("Profile: {}", profile.unwrap_or("none")));
+    if vars.is_empty() {
+        logging::info("No variables loaded from env files");
+        return;
+    }
+
+    let width = vars.iter().map(|v| v.name.len()).max().unwrap_or(0);
+    for var in vars {
+        let note = if var.overridden() {
+            " (overridden by the environment)"
+        } else {
+            ""
+        };
+        logging::info(format!(
+            "  {:<width$}  {}{}",
+            var.name,
+            var.source.display(),
+            note,
+            width = width
+        ));
+    }
+}
+
+fn migrate(config_path: &str) -> Result<()> {
+    let changes = schema::migrate(config_path)?;
+    if changes.is_empty() {
+        logging::info(format!(
+            "'{}' is already at config version {}",
+            config_path,
+            schema::VERSION
+        ));
+        return Ok(());
+    }
+
+    logging::info(format!(
+        "Migrated '{}' to config version {}:",
+        config_path,
+        schema::VERSION
+    ));
+    for change in changes {
+        logging::info(format!("  {}", change));
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use regex::bytes::Regex;
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    sync::OnceLock,
+};
+
+const MASK: &[u8] = b"***";
+
+static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+
+/// Sets the patterns whose matches are hidden from everything compi prints or writes.
+pub fn init(patterns: Vec<Regex>) {
+    let _ = PATTERNS.set(patterns);
+}
+
+pub fn bytes(data: &[u8]) -> Cow<'_, [u8]> {
+    let Some(patterns) = PATTERNS.get().filter(|p| !p.is_empty()) else {
+        return Cow::Borrowed(data);
+    };
+
+    let mut masked = Cow::Borrowed(data);
+    for pattern in patterns {
+        if let Cow::Owned(replaced) = pattern.replace_all(&masked, MASK) {
+            masked = Cow::Owned(replaced);
+        }
+    }
+    masked
+}
+
+pub fn text(text: &str) -> Cow<'_, str> {
+    match bytes(text.as_bytes()) {
+        Cow::Borrowed(_) => Cow::Borrowed(text),
+        Cow::Owned(masked) => Cow::Owned(String::from_utf8_lossy(&masked).into_owned()),
+    }
+}
+
+/// Longest piece of a line held back while waiting for its end. Longer lines
+/// are masked in pieces, which can miss a secret split between two of them.
+const MAX_PENDING: usize = 64 * 1024;
+
+/// Collects a stream and hands it on in pieces that end at a line break, so
+/// that a secret is never split between two pieces and shown unmasked.
+#[derive(Default)]
+pub struct Lines {
+    pending: Vec<u8>,
+}
+
+impl Lines {
+    /// Adds `data` and returns the complete lines collected so far, or
+    /// everything once the unfinished line outgrows [`MAX_PENDING`].
+    pub fn push(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        self.pending.extend_from_slice(data);
+        if self.pending.len() > MAX_PENDING {
+            return Some(std::mem::take(&mut self.pending));
+        }
+        let end = self.pending.iter().rposition(|&b| b == b'\n')?;
+        Some(self.pending.drain(..=end).collect())
+    }
+
+    /// What is left once the stream has ended.
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
+
+/// Masks everything written through it, a line at a time.
+pub struct Writer<W: Write> {
+    inner: W,
+    lines: Lines,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            lines: Lines::default(),
+        }
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(lines) = self.lines.push(buf) {
+            self.inner.write_all(&bytes(&lines))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(rest) = self.lines.finish() {
+            self.inner.write_all(&bytes(&rest))?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
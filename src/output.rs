@@ -1,11 +1,51 @@
+use crate::ci;
 use clap::ValueEnum;
 use serde::Deserialize;
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
 
-#[derive(ValueEnum, Deserialize, Clone, Debug)]
+#[derive(ValueEnum, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum OutputMode {
     /// Stream task output live.
     Stream,
     /// Print each task's output as a single block after it completes.
+    #[default]
     Group,
+    /// Print a task's output as a single block only if the task fails.
+    Failures,
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Color when writing to a terminal outside CI and NO_COLOR is not set,
+    /// or when FORCE_COLOR is set.
+    #[default]
+    Auto,
+    /// Always color, even when NO_COLOR is set.
+    Always,
+    /// Never color.
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                if is_set("FORCE_COLOR") {
+                    return true;
+                }
+                !is_set("NO_COLOR") && io::stdout().is_terminal() && ci::detect().is_none()
+            }
+        }
+    }
+}
+
+fn is_set(name: &str) -> bool {
+    env::var_os(name).is_some_and(|value| !value.is_empty() && value != "0")
 }
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path};
+
+use crate::error::{CompiError, Result};
+use crate::logging;
+use crate::task::Task;
+
+/// Format of plan files, raised when a change would be misread by older versions.
+pub const PLAN_VERSION: u32 = 2;
+
+/// The tasks a run would execute, in order, with the decision taken for each.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Plan {
+    pub version: u32,
+    /// Config file the plan was made from.
+    pub config: String,
+    /// Tasks the plan was made for, all tasks when empty.
+    pub targets: Vec<String>,
+    pub tasks: Vec<PlannedTask>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlannedTask {
+    pub id: String,
+    pub command: String,
+    pub dependencies: Vec<String>,
+    /// Whether the task runs, or is skipped as up-to-date.
+    pub run: bool,
+    pub reason: RunReason,
+}
+
+/// Why a task runs, or [`RunReason::UpToDate`] when it is skipped.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunReason {
+    Generator,
+    CapturesOutput,
+    /// Another task pipes from its output.
+    OutputPiped,
+    /// It reads the output of this task.
+    ReadsPipe(String),
+    AlwaysRun,
+    NoInputs,
+    OutputsMissing,
+    OutputsOutdated,
+    InputsChanged,
+    InputsUnreadable,
+    /// This dependency runs, which may change its inputs.
+    DependencyRuns(String),
+    UpToDate,
+}
+
+impl fmt::Display for RunReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunReason::Generator => write!(f, "generator"),
+            RunReason::CapturesOutput => write!(f, "captures output"),
+            RunReason::OutputPiped => write!(f, "output is piped"),
+            RunReason::ReadsPipe(producer) => write!(f, "reads the output of '{}'", producer),
+            RunReason::AlwaysRun => write!(f, "always_run=true"),
+            RunReason::NoInputs => write!(f, "no inputs"),
+            RunReason::OutputsMissing => write!(f, "outputs missing"),
+            RunReason::OutputsOutdated => write!(f, "outputs older than inputs"),
+            RunReason::InputsChanged => write!(f, "input content changed"),
+            RunReason::InputsUnreadable => write!(f, "inputs could not be hashed"),
+            RunReason::DependencyRuns(dep) => write!(f, "dependency '{}' runs", dep),
+            RunReason::UpToDate => write!(f, "up-to-date"),
+        }
+    }
+}
+
+pub fn write_plan(plan: &Plan, output: Option<&Path>) -> Result<()> {
+    let json = serde_json::to_string_pretty(plan)
+        .map_err(|e| CompiError::Parse(format!("Failed to serialize plan: {}", e)))?;
+
+    match output {
+        Some(path) => {
+            if let Some(parent) = path.parent()
+                && !parent.as_os_str().is_empty()
+            {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, json + "\n")?;
+            let runs = plan.tasks.iter().filter(|t| t.run).count();
+            logging::info(format!(
+                "Planned {} tasks ({} to run) in {}",
+                plan.tasks.len(),
+                runs,
+                path.display()
+            ));
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+pub fn read_plan(path: &Path) -> Result<Plan> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        CompiError::Task(format!("Failed to read plan '{}': {}", path.display(), e))
+    })?;
+    let plan: Plan = serde_json::from_str(&contents)
+        .map_err(|e| CompiError::Parse(format!("Invalid plan '{}': {}", path.display(), e)))?;
+
+    if plan.version != PLAN_VERSION {
+        return Err(CompiError::Parse(format!(
+            "Plan '{}' has version {}, this compi reads version {}",
+            path.display(),
+            plan.version,
+            PLAN_VERSION
+        )));
+    }
+    Ok(plan)
+}
+
+/// Fails when a planned task no longer exists or its command changed since
+/// the plan was made.
+pub fn check_plan(plan: &Plan, tasks: &[Task]) -> Result<()> {
+    for planned in &plan.tasks {
+        let Some(task) = tasks.iter().find(|t| t.id == planned.id) else {
+            return Err(CompiError::Task(format!(
+                "Plan is out of date: task '{}' no longer exists",
+                planned.id
+            )));
+        };
+        if task.command != planned.command {
+            return Err(CompiError::Task(format!(
+                "Plan is out of date: the command of task '{}' changed since it was planned",
+                planned.id
+            )));
+        }
+    }
+    Ok(())
+}
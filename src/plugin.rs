@@ -0,0 +1,445 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Output,
+    sync::OnceLock,
+    time::Duration,
+};
+use wasmi::{
+    Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+use crate::cache::Cache;
+use crate::error::{CompiError, Result};
+use crate::execution::{RunSummary, TaskResult, TaskStatus};
+use crate::logging;
+use crate::task::Task;
+use crate::util::{exit_status_from_code, parse_size, url_host};
+
+const HOST_MODULE: &str = "compi";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Fuel a hook call gets when the plugin does not set `fuel`, roughly a
+/// billion instructions.
+const DEFAULT_FUEL: u64 = 1_000_000_000;
+/// Bytes of memory a hook call may grow to when the plugin does not set
+/// `memory`.
+const DEFAULT_MEMORY: u64 = 256 * 1024 * 1024;
+
+/// A plugin as declared in `[config] plugins`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PluginConfig {
+    pub name: String,
+    pub path: PathBuf,
+    /// Passed to every hook as `config`, after variable substitution.
+    #[serde(default)]
+    pub config: HashMap<String, String>,
+    /// Hosts, optionally with a port, the plugin may reach with `http_get`
+    /// and `http_post`. Plugins cannot make requests without them.
+    #[serde(default)]
+    pub allow_hosts: Vec<String>,
+    /// Instructions, roughly, a single hook call may run before it is stopped.
+    pub fuel: Option<u64>,
+    /// Bytes of memory a single hook call may grow to, e.g. "64MB".
+    pub memory: Option<String>,
+}
+
+struct Plugin {
+    name: String,
+    module: Module,
+    config: HashMap<String, String>,
+    allow_hosts: Vec<String>,
+    fuel: u64,
+    memory: u64,
+}
+
+/// What the host functions of one hook call may do.
+struct Host {
+    allow_hosts: Vec<String>,
+    limits: StoreLimits,
+}
+
+impl Host {
+    fn allows(&self, url: &str) -> bool {
+        let authority = url_host(url);
+        let host = authority
+            .rsplit_once(':')
+            .map_or(authority, |(host, _)| host);
+        let allowed = self
+            .allow_hosts
+            .iter()
+            .any(|allowed| allowed == authority || allowed == host);
+        if !allowed {
+            logging::warn(format!(
+                "Plugin request to {} refused, the host is not in allow_hosts",
+                authority
+            ));
+        }
+        allowed
+    }
+}
+
+struct Registry {
+    engine: Engine,
+    plugins: Vec<Plugin>,
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Compiles the configured plugins. Hooks run in a fresh instance on every call,
+/// so plugins cannot keep state between calls.
+pub fn load(configs: &[PluginConfig]) -> Result<()> {
+    let engine = Engine::new(Config::default().consume_fuel(true));
+    let mut plugins = Vec::new();
+
+    for config in configs {
+        let bytes = fs::read(&config.path).map_err(|e| {
+            CompiError::Parse(format!(
+                "failed to read plugin '{}' from '{}': {}",
+                config.name,
+                config.path.display(),
+                e
+            ))
+        })?;
+        let module = Module::new(&engine, &bytes)
+            .map_err(|e| CompiError::Parse(format!("invalid plugin '{}': {}", config.name, e)))?;
+        let memory = match &config.memory {
+            Some(memory) => parse_size(memory).map_err(|e| {
+                CompiError::Parse(format!(
+                    "invalid memory '{}' for plugin '{}': {}",
+                    memory, config.name, e
+                ))
+            })?,
+            None => DEFAULT_MEMORY,
+        };
+
+        plugins.push(Plugin {
+            name: config.name.clone(),
+            module,
+            config: config.config.clone(),
+            allow_hosts: config.allow_hosts.clone(),
+            fuel: config.fuel.unwrap_or(DEFAULT_FUEL),
+            memory,
+        });
+    }
+
+    let _ = REGISTRY.set(Registry { engine, plugins });
+    Ok(())
+}
+
+fn registry() -> Option<&'static Registry> {
+    REGISTRY.get()
+}
+
+fn find(name: &str) -> Result<&'static Plugin> {
+    registry()
+        .and_then(|r| r.plugins.iter().find(|p| p.name == name))
+        .ok_or_else(|| CompiError::Task(format!("plugin '{}' is not loaded", name)))
+}
+
+/// Plugins exporting `hook`, in the order they were configured.
+fn with_hook(hook: &str) -> Vec<&'static Plugin> {
+    registry()
+        .map(|r| {
+            r.plugins
+                .iter()
+                .filter(|p| p.module.exports().any(|e| e.name() == hook))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Runs a task through the `execute` hook of the plugin named by its `executor`.
+pub fn execute(plugin_name: &str, task: &Task) -> Result<Output> {
+    let plugin = find(plugin_name)?;
+    let input = json!({
+        "task": {
+            "id": task.id,
+            "command": task.command,
+            "inputs": task.inputs,
+            "outputs": task.outputs,
+        },
+    });
+
+    let response = call(plugin, "execute", &input)?
+        .ok_or_else(|| plugin_error(plugin, "execute", "returned no result"))?;
+
+    #[derive(Deserialize)]
+    struct ExecuteResult {
+        exit_code: i32,
+        #[serde(default)]
+        stdout: String,
+        #[serde(default)]
+        stderr: String,
+    }
+
+    let result: ExecuteResult =
+        serde_json::from_slice(&response).map_err(|e| plugin_error(plugin, "execute", e))?;
+    Ok(Output {
+        status: exit_status_from_code(result.exit_code),
+        stdout: result.stdout.into_bytes(),
+        stderr: result.stderr.into_bytes(),
+    })
+}
+
+/// Passes a run's outcome to plugins exporting `on_run_completed` and, for each
+/// failed task, `on_task_failed`.
+pub fn notify(target: &str, results: &[TaskResult], summary: &RunSummary) {
+    let tasks: Vec<Value> = results.iter().map(task_json).collect();
+    let run = json!({ "target": target, "summary": summary, "tasks": tasks });
+
+    for plugin in with_hook("on_run_completed") {
+        if let Err(e) = call(plugin, "on_run_completed", &run) {
+            logging::warn(e);
+        }
+    }
+
+    for plugin in with_hook("on_task_failed") {
+        for result in results
+            .iter()
+            .filter(|r| matches!(r.status, TaskStatus::Failed | TaskStatus::TimedOut))
+        {
+            let input = json!({ "target": target, "task": task_json(result) });
+            if let Err(e) = call(plugin, "on_task_failed", &input) {
+                logging::warn(e);
+            }
+        }
+    }
+}
+
+/// Adds the hashes returned by every plugin's `cache_load` hook to `cache`.
+pub fn load_cache(cache: &mut Cache) {
+    for plugin in with_hook("cache_load") {
+        let entries = call(plugin, "cache_load", &json!({})).and_then(|response| {
+            let Some(response) = response else {
+                return Ok(Vec::new());
+            };
+            serde_json::from_slice::<Vec<String>>(&response)
+                .map_err(|e| plugin_error(plugin, "cache_load", e))
+        });
+
+        match entries {
+            Ok(entries) => cache.extend(entries),
+            Err(e) => logging::warn(e),
+        }
+    }
+}
+
+/// Hands the full cache to every plugin exporting `cache_save`.
+pub fn save_cache(cache: &Cache) {
+    let mut entries: Vec<&String> = cache.iter().collect();
+    entries.sort();
+
+    for plugin in with_hook("cache_save") {
+        if let Err(e) = call(plugin, "cache_save", &json!({ "entries": entries })) {
+            logging::warn(e);
+        }
+    }
+}
+
+/// Converts a file with the plugin's `import` hook, which returns compi TOML.
+pub fn import(plugin_name: &str, path: &Path) -> Result<String> {
+    let plugin = find(plugin_name)?;
+    let contents = fs::read_to_string(path)?;
+    let input = json!({ "path": path, "contents": contents });
+
+    let response = call(plugin, "import", &input)?
+        .ok_or_else(|| plugin_error(plugin, "import", "returned no configuration"))?;
+    String::from_utf8(response).map_err(|e| plugin_error(plugin, "import", e))
+}
+
+fn task_json(result: &TaskResult) -> Value {
+    json!({
+        "task": result.task_id,
+        "status": result.status,
+        "duration_ms": result.duration.as_millis() as u64,
+        "exit_code": result.exit_code,
+    })
+}
+
+fn plugin_error(plugin: &Plugin, hook: &str, error: impl std::fmt::Display) -> CompiError {
+    CompiError::Task(format!("plugin '{}' {}: {}", plugin.name, hook, error))
+}
+
+/// Calls `hook` with `input` plus the plugin's config as JSON. Hooks take a
+/// pointer and length and return `(pointer << 32) | length`, or 0 for no result.
+fn call(plugin: &Plugin, hook: &str, input: &Value) -> Result<Option<Vec<u8>>> {
+    let registry = registry().ok_or_else(|| plugin_error(plugin, hook, "not loaded"))?;
+    let error = |e: &dyn std::fmt::Display| plugin_error(plugin, hook, e);
+
+    let mut payload = input.clone();
+    if let Value::Object(fields) = &mut payload {
+        fields.insert("config".to_string(), json!(plugin.config));
+    }
+    let payload = payload.to_string();
+
+    let host = Host {
+        allow_hosts: plugin.allow_hosts.clone(),
+        limits: StoreLimitsBuilder::new()
+            .memory_size(usize::try_from(plugin.memory).unwrap_or(usize::MAX))
+            .build(),
+    };
+    let mut store = Store::new(&registry.engine, host);
+    // A hook that loops forever runs out of fuel instead of hanging compi,
+    // and one that keeps allocating fails to grow its memory.
+    store.set_fuel(plugin.fuel).map_err(|e| error(&e))?;
+    store.limiter(|host| &mut host.limits);
+    let linker = host_functions(&registry.engine).map_err(|e| error(&e))?;
+    let instance = linker
+        .instantiate(&mut store, &plugin.module)
+        .and_then(|pre| pre.start(&mut store))
+        .map_err(|e| error(&e))?;
+
+    let memory = instance
+        .get_memory(&store, "memory")
+        .ok_or_else(|| error(&"does not export memory"))?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&store, "compi_alloc")
+        .map_err(|e| error(&e))?;
+    let function = instance
+        .get_typed_func::<(i32, i32), i64>(&store, hook)
+        .map_err(|e| error(&e))?;
+
+    let len = payload.len() as i32;
+    let ptr = alloc.call(&mut store, len).map_err(|e| error(&e))?;
+    memory
+        .write(&mut store, ptr as usize, payload.as_bytes())
+        .map_err(|e| error(&e))?;
+
+    let packed = function
+        .call(&mut store, (ptr, len))
+        .map_err(|e| error(&e))?;
+    if packed == 0 {
+        return Ok(None);
+    }
+
+    let (ptr, len) = unpack(packed);
+    if ptr.saturating_add(len) > memory.data(&store).len() {
+        return Err(error(&"returned a result outside its memory"));
+    }
+    let mut response = vec![0; len];
+    memory
+        .read(&store, ptr, &mut response)
+        .map_err(|e| error(&e))?;
+    Ok(Some(response))
+}
+
+fn unpack(packed: i64) -> (usize, usize) {
+    let packed = packed as u64;
+    ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize)
+}
+
+fn pack(ptr: i32, len: i32) -> i64 {
+    ((ptr as u32 as u64) << 32 | len as u32 as u64) as i64
+}
+
+/// Host functions plugins can import from the `compi` module.
+fn host_functions(
+    engine: &Engine,
+) -> std::result::Result<Linker<Host>, wasmi::errors::LinkerError> {
+    let mut linker = Linker::new(engine);
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "log",
+        |caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            if let Some(message) = read_string(&caller, ptr, len) {
+                logging::info(message);
+            }
+        },
+    )?;
+
+    // Returns the HTTP status code, or -1 if the request could not be sent.
+    linker.func_wrap(
+        HOST_MODULE,
+        "http_post",
+        |caller: Caller<'_, Host>, url_ptr: i32, url_len: i32, body_ptr: i32, body_len: i32| {
+            let (Some(url), Some(body)) = (
+                read_string(&caller, url_ptr, url_len),
+                read_string(&caller, body_ptr, body_len),
+            ) else {
+                return -1;
+            };
+            if !caller.data().allows(&url) {
+                return -1;
+            }
+
+            match http_agent()
+                .post(&url)
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+            {
+                Ok(response) => response.status() as i32,
+                Err(ureq::Error::Status(code, _)) => code as i32,
+                Err(ureq::Error::Transport(_)) => -1,
+            }
+        },
+    )?;
+
+    // Returns the response body as `(pointer << 32) | length`, or 0 on failure.
+    linker.func_wrap(
+        HOST_MODULE,
+        "http_get",
+        |mut caller: Caller<'_, Host>, url_ptr: i32, url_len: i32| -> i64 {
+            let Some(url) = read_string(&caller, url_ptr, url_len) else {
+                return 0;
+            };
+            if !caller.data().allows(&url) {
+                return 0;
+            }
+            let Ok(response) = http_agent().get(&url).call() else {
+                return 0;
+            };
+            let Ok(body) = response.into_string() else {
+                return 0;
+            };
+
+            write_bytes(&mut caller, body.as_bytes()).unwrap_or(0)
+        },
+    )?;
+
+    Ok(linker)
+}
+
+/// An HTTP client that does not follow redirects, which could lead to a host
+/// that is not in `allow_hosts`.
+fn http_agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(HTTP_TIMEOUT)
+        .redirects(0)
+        .build()
+}
+
+fn caller_memory(caller: &Caller<'_, Host>) -> Option<Memory> {
+    caller.get_export("memory").and_then(Extern::into_memory)
+}
+
+/// Reads a string the plugin points to, refusing lengths outside its memory
+/// before allocating anything for them.
+fn read_string(caller: &Caller<'_, Host>, ptr: i32, len: i32) -> Option<String> {
+    let memory = caller_memory(caller)?;
+    let (ptr, len) = (usize::try_from(ptr).ok()?, usize::try_from(len).ok()?);
+    if ptr.saturating_add(len) > memory.data(caller).len() {
+        return None;
+    }
+    let mut buffer = vec![0; len];
+    memory.read(caller, ptr, &mut buffer).ok()?;
+    String::from_utf8(buffer).ok()
+}
+
+fn write_bytes(caller: &mut Caller<'_, Host>, data: &[u8]) -> Option<i64> {
+    let alloc = caller
+        .get_export("compi_alloc")
+        .and_then(Extern::into_func)?
+        .typed::<i32, i32>(&caller)
+        .ok()?;
+    let len = data.len() as i32;
+    let ptr = alloc.call(&mut *caller, len).ok()?;
+
+    caller_memory(caller)?
+        .write(&mut *caller, ptr as usize, data)
+        .ok()?;
+    Some(pack(ptr, len))
+}
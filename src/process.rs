@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::process::{Child, Command};
+
+use crate::error::{CompiError, Result};
+use crate::hooks;
+use crate::logging;
+use crate::sandbox;
+use crate::spool;
+
+/// How long a timed-out command may take to exit after being asked to.
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Running process groups, with how long each may take to exit once asked to.
+static GROUPS: OnceLock<Mutex<HashMap<u32, Duration>>> = OnceLock::new();
+
+fn groups() -> &'static Mutex<HashMap<u32, Duration>> {
+    GROUPS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Starts the command in a process group of its own, so that it can be
+/// stopped together with everything it starts. The group is in the
+/// background, so the command is stopped if it reads from the terminal.
+pub fn isolate(cmd: &mut Command) {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    #[cfg(windows)]
+    cmd.creation_flags(sys::CREATE_NEW_PROCESS_GROUP);
+}
+
+/// The process group of a running command. Groups are interrupted when compi
+/// receives Ctrl-C, since they no longer share its terminal's foreground group.
+pub struct Group {
+    id: u32,
+}
+
+impl Group {
+    pub fn register(child: &Child, grace_period: Duration) -> Option<Self> {
+        let id = child.id()?;
+        groups().lock().unwrap().insert(id, grace_period);
+        Some(Self { id })
+    }
+
+    /// Asks the group to exit, waits up to `grace_period` for the command to
+    /// do so, then kills whatever is left of the group.
+    pub async fn stop(&self, child: &mut Child, grace_period: Duration) {
+        if !grace_period.is_zero() {
+            match sys::terminate(self.id) {
+                Ok(()) => {
+                    if tokio::time::timeout(grace_period, child.wait())
+                        .await
+                        .is_err()
+                    {
+                        logging::debug(format!(
+                            "Process {} did not exit within {}, killing it",
+                            self.id,
+                            humantime::format_duration(grace_period)
+                        ));
+                    }
+                }
+                Err(e) => logging::debug(format!("Failed to terminate process {}: {}", self.id, e)),
+            }
+        }
+
+        if let Err(e) = sys::kill(self.id) {
+            logging::warn(format!("Failed to kill timed-out process: {}", e));
+        }
+        let _ = child.wait().await;
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        groups().lock().unwrap().remove(&self.id);
+    }
+}
+
+/// A signal that compi forwards to running commands or sends in their place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Int,
+    Term,
+    Hup,
+    Quit,
+    Usr1,
+    Usr2,
+    Kill,
+}
+
+impl Signal {
+    /// Signals compi handles itself, which `forward_signals` may map.
+    pub const FORWARDED: [Signal; 3] = [Signal::Term, Signal::Hup, Signal::Usr1];
+
+    /// Parses `TERM` or `SIGTERM`, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_uppercase();
+        let signal = match name.strip_prefix("SIG").unwrap_or(&name) {
+            "INT" => Signal::Int,
+            "TERM" => Signal::Term,
+            "HUP" => Signal::Hup,
+            "QUIT" => Signal::Quit,
+            "USR1" => Signal::Usr1,
+            "USR2" => Signal::Usr2,
+            "KILL" => Signal::Kill,
+            _ => return None,
+        };
+        Some(signal)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Int => "SIGINT",
+            Signal::Term => "SIGTERM",
+            Signal::Hup => "SIGHUP",
+            Signal::Quit => "SIGQUIT",
+            Signal::Usr1 => "SIGUSR1",
+            Signal::Usr2 => "SIGUSR2",
+            Signal::Kill => "SIGKILL",
+        }
+    }
+
+    /// Whether compi shuts down after forwarding the signal, like on Ctrl-C.
+    fn stops(self) -> bool {
+        matches!(self, Signal::Term | Signal::Hup)
+    }
+}
+
+/// Parses `[config] forward_signals`, which maps each of [`Signal::FORWARDED`]
+/// to the signal running commands receive for it, or to `none` to leave the
+/// signal alone. Unmapped signals are forwarded as they are.
+pub fn signal_mapping(config: &HashMap<String, String>) -> Result<Vec<(Signal, Option<Signal>)>> {
+    let mut mapping: Vec<(Signal, Option<Signal>)> =
+        Signal::FORWARDED.iter().map(|&s| (s, Some(s))).collect();
+    for (received, sent) in config {
+        let received = Signal::from_name(received)
+            .filter(|s| Signal::FORWARDED.contains(s))
+            .ok_or_else(|| {
+                CompiError::Parse(format!(
+                    "forward_signals cannot map '{}', only SIGTERM, SIGHUP and SIGUSR1",
+                    received
+                ))
+            })?;
+        let sent = if sent.eq_ignore_ascii_case("none") {
+            None
+        } else {
+            Some(Signal::from_name(sent).ok_or_else(|| {
+                CompiError::Parse(format!(
+                    "invalid signal '{}' in forward_signals for {}",
+                    sent,
+                    received.name()
+                ))
+            })?)
+        };
+        if let Some(entry) = mapping.iter_mut().find(|(s, _)| *s == received) {
+            entry.1 = sent;
+        }
+    }
+    Ok(mapping)
+}
+
+/// Passes Ctrl-C on to the commands that are still running, then exits once
+/// the `finally` hooks of the tasks and the run have run. A second Ctrl-C
+/// exits without waiting for them. On Unix, the signals in `mapping` are
+/// handled the same way, except that SIGUSR1 does not stop compi.
+pub fn forward_interrupts(mapping: &[(Signal, Option<Signal>)]) {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            send_to_groups(Signal::Int);
+            tokio::spawn(shut_down(130));
+        }
+    });
+
+    #[cfg(unix)]
+    for &(received, sent) in mapping {
+        let Some(sent) = sent else {
+            continue;
+        };
+        let kind = tokio::signal::unix::SignalKind::from_raw(sys::number(received));
+        let mut stream = match tokio::signal::unix::signal(kind) {
+            Ok(stream) => stream,
+            Err(e) => {
+                logging::warn(format!("Failed to handle {}: {}", received.name(), e));
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            while stream.recv().await.is_some() {
+                logging::debug(format!(
+                    "Received {}, sending {} to running commands",
+                    received.name(),
+                    sent.name()
+                ));
+                send_to_groups(sent);
+                if received.stops() {
+                    tokio::spawn(shut_down(128 + sys::number(received)));
+                }
+            }
+        });
+    }
+    #[cfg(windows)]
+    let _ = mapping;
+}
+
+fn send_to_groups(signal: Signal) {
+    for &id in groups().lock().unwrap().keys() {
+        let _ = sys::signal(id, signal);
+    }
+}
+
+/// Exits with `code` once the interrupted commands have exited and the
+/// `finally` hooks have run, including those that had already started, or
+/// right away when already shutting down.
+async fn shut_down(code: i32) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        sandbox::remove_all();
+        spool::remove_all();
+        std::process::exit(code);
+    }
+    if hooks::any_armed() || hooks::any_running() {
+        logging::warn("Interrupted, running finally hooks (press Ctrl-C again to skip them)");
+        wait_for_groups().await;
+        hooks::run_armed().await;
+        hooks::wait_for_running().await;
+    }
+    sandbox::remove_all();
+    spool::remove_all();
+    std::process::exit(code);
+}
+
+/// Whether compi has received Ctrl-C, or a signal that stops it.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Waits for the interrupted commands to exit, each for up to the grace
+/// period of its task, so that `finally` hooks do not clean up after commands
+/// that are still running.
+async fn wait_for_groups() {
+    let started = Instant::now();
+    while groups()
+        .lock()
+        .unwrap()
+        .values()
+        .any(|&grace_period| started.elapsed() < grace_period)
+    {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::io;
+
+    use super::Signal;
+
+    fn signal_group(id: u32, signal: libc::c_int) -> io::Result<()> {
+        // SAFETY: `kill` has no memory-safety preconditions; a negative pid
+        // addresses the process group.
+        if unsafe { libc::kill(-(id as libc::pid_t), signal) } == 0 {
+            return Ok(());
+        }
+        match io::Error::last_os_error() {
+            // The whole group has already exited.
+            e if e.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    pub fn number(signal: Signal) -> libc::c_int {
+        match signal {
+            Signal::Int => libc::SIGINT,
+            Signal::Term => libc::SIGTERM,
+            Signal::Hup => libc::SIGHUP,
+            Signal::Quit => libc::SIGQUIT,
+            Signal::Usr1 => libc::SIGUSR1,
+            Signal::Usr2 => libc::SIGUSR2,
+            Signal::Kill => libc::SIGKILL,
+        }
+    }
+
+    pub fn terminate(id: u32) -> io::Result<()> {
+        signal_group(id, libc::SIGTERM)
+    }
+
+    pub fn signal(id: u32, signal: Signal) -> io::Result<()> {
+        signal_group(id, number(signal))
+    }
+
+    pub fn kill(id: u32) -> io::Result<()> {
+        signal_group(id, libc::SIGKILL)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::{
+        io,
+        process::{Command, Stdio},
+    };
+
+    use super::Signal;
+
+    pub const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    const CTRL_BREAK_EVENT: u32 = 1;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GenerateConsoleCtrlEvent(event: u32, group: u32) -> i32;
+    }
+
+    pub fn terminate(id: u32) -> io::Result<()> {
+        // SAFETY: plain FFI call without pointer arguments.
+        if unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, id) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Windows has no signals to send to a process group; they all become
+    /// CTRL_BREAK, and SIGKILL kills it.
+    pub fn signal(id: u32, signal: Signal) -> io::Result<()> {
+        match signal {
+            Signal::Kill => kill(id),
+            _ => terminate(id),
+        }
+    }
+
+    /// Kills the process and its descendants, which Windows does not track
+    /// through the console process group.
+    pub fn kill(id: u32) -> io::Result<()> {
+        Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &id.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,213 @@
+use serde_json::json;
+use std::{fmt::Write as _, fs, path::PathBuf, str::FromStr, time::UNIX_EPOCH};
+
+use crate::error::{CompiError, Result};
+use crate::execution::{RunSummary, TaskResult, TaskStatus};
+use crate::mask;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Junit,
+    Json,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReportSpec {
+    pub format: ReportFormat,
+    pub path: PathBuf,
+}
+
+impl FromStr for ReportSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected FORMAT=PATH, got '{}'", s))?;
+
+        let format = match format {
+            "junit" => ReportFormat::Junit,
+            "json" => ReportFormat::Json,
+            other => return Err(format!("unknown report format '{}'", other)),
+        };
+
+        if path.is_empty() {
+            return Err("report path cannot be empty".to_string());
+        }
+
+        Ok(ReportSpec {
+            format,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+pub fn write_reports(
+    specs: &[ReportSpec],
+    results: &[TaskResult],
+    summary: &RunSummary,
+) -> Result<()> {
+    for spec in specs {
+        let contents = match spec.format {
+            ReportFormat::Junit => render_junit(results, summary),
+            ReportFormat::Json => render_json(results, summary),
+        };
+
+        if let Some(parent) = spec.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&spec.path, contents).map_err(|e| {
+            CompiError::Task(format!(
+                "failed to write report '{}': {}",
+                spec.path.display(),
+                e
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+fn render_junit(results: &[TaskResult], summary: &RunSummary) -> String {
+    let failures = results
+        .iter()
+        .filter(|r| matches!(r.status, TaskStatus::Failed | TaskStatus::TimedOut))
+        .count();
+    let skipped = results
+        .iter()
+        .filter(|r| r.status == TaskStatus::Skipped)
+        .count();
+    let total_time = summary.duration_ms as f64 / 1000.0;
+
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<testsuites name="compi" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+        results.len(),
+        failures,
+        skipped,
+        total_time
+    );
+    let _ = writeln!(
+        xml,
+        r#"  <testsuite name="compi" tests="{}" failures="{}" errors="0" skipped="{}" time="{:.3}">"#,
+        results.len(),
+        failures,
+        skipped,
+        total_time
+    );
+
+    for result in results {
+        let _ = write!(
+            xml,
+            r#"    <testcase name="{}" classname="compi" time="{:.3}""#,
+            escape_xml(&result.task_id),
+            result.duration.as_secs_f64()
+        );
+
+        let has_body = result.status != TaskStatus::Success
+            || !result.stdout.is_empty()
+            || !result.stderr.is_empty();
+        if !has_body {
+            let _ = writeln!(xml, "/>");
+            continue;
+        }
+        let _ = writeln!(xml, ">");
+
+        match result.status {
+            TaskStatus::Success => {}
+            TaskStatus::Skipped => {
+                let _ = writeln!(xml, r#"      <skipped message="outputs up-to-date"/>"#);
+            }
+            TaskStatus::TimedOut => {
+                let _ = writeln!(
+                    xml,
+                    r#"      <failure type="timeout" message="task timed out"/>"#
+                );
+            }
+            TaskStatus::Failed => {
+                let message = match result.exit_code {
+                    Some(code) => format!("task exited with status {}", code),
+                    None => "task failed to complete".to_string(),
+                };
+                let _ = writeln!(
+                    xml,
+                    r#"      <failure type="failed" message="{}"/>"#,
+                    escape_xml(&message)
+                );
+            }
+        }
+
+        if !result.stdout.is_empty() {
+            let _ = writeln!(
+                xml,
+                "      <system-out>{}</system-out>",
+                escape_xml(&mask::text(&String::from_utf8_lossy(&result.stdout)))
+            );
+        }
+        if !result.stderr.is_empty() {
+            let _ = writeln!(
+                xml,
+                "      <system-err>{}</system-err>",
+                escape_xml(&mask::text(&String::from_utf8_lossy(&result.stderr)))
+            );
+        }
+
+        let _ = writeln!(xml, "    </testcase>");
+    }
+
+    let _ = writeln!(xml, "  </testsuite>");
+    let _ = writeln!(xml, "</testsuites>");
+    xml
+}
+
+fn render_json(results: &[TaskResult], summary: &RunSummary) -> String {
+    let tasks: Vec<_> = results
+        .iter()
+        .map(|result| {
+            json!({
+                "id": result.task_id,
+                "status": result.status,
+                "started_at_ms": result
+                    .started_at
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or_default(),
+                "duration_ms": result.duration.as_millis(),
+                "exit_code": result.exit_code,
+                "missing_outputs": result.missing_outputs,
+                "stdout": mask::text(&String::from_utf8_lossy(&result.stdout)),
+                "stderr": mask::text(&String::from_utf8_lossy(&result.stderr)),
+            })
+        })
+        .collect();
+    let diagnostics: Vec<_> = results.iter().flat_map(|r| &r.diagnostics).collect();
+
+    let report = json!({
+        "summary": summary,
+        "tasks": tasks,
+        "diagnostics": diagnostics,
+    });
+    serde_json::to_string_pretty(&report).unwrap_or_default() + "\n"
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
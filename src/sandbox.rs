@@ -0,0 +1,171 @@
+use std::{
+    env, fs, io,
+    path::{Component, Path, PathBuf},
+    process,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::logging;
+use crate::task::Task;
+use crate::util::{
+    exclusion, expand_globs, files_in_dir, is_glob_pattern, normalize_path_lexical, patterns_match,
+};
+
+static NEXT_SANDBOX: AtomicU64 = AtomicU64::new(0);
+/// Roots of the sandboxes that exist, removed by [`remove_all`] when compi is
+/// interrupted and exits without dropping them.
+static LIVE: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A temporary directory mirroring the directory compi runs in, holding only
+/// the declared inputs of a task with `sandbox = true`. It is removed when
+/// dropped.
+pub struct Sandbox {
+    root: PathBuf,
+    /// The directory compi runs in, which task paths are relative to.
+    project: PathBuf,
+    /// Where the command runs, the task's own directory within the sandbox.
+    dir: PathBuf,
+}
+
+impl Sandbox {
+    /// Copies the task's inputs into a new sandbox at the same relative paths,
+    /// and creates the directories its outputs go in. Inputs outside the
+    /// current directory are not copied, and stay readable where they are.
+    /// The task's own directory must lie inside the current directory.
+    pub fn create(task: &Task) -> io::Result<Self> {
+        let project = env::current_dir()?;
+        let task_dir = task.dir.as_deref().unwrap_or(Path::new(""));
+        let Some(relative_dir) = relative_to(&project, task_dir) else {
+            return Err(io::Error::other(format!(
+                "the task directory {} is outside {}, where compi runs, so it cannot be sandboxed",
+                task_dir.display(),
+                project.display()
+            )));
+        };
+
+        let id = NEXT_SANDBOX.fetch_add(1, Ordering::Relaxed);
+        let root = env::temp_dir().join(format!("compi-sandbox-{}-{}", process::id(), id));
+        fs::create_dir(&root)?;
+        LIVE.lock().unwrap().push(root.clone());
+        let sandbox = Self {
+            dir: root.join(relative_dir),
+            project,
+            root,
+        };
+
+        let inputs = expand_globs(&task.inputs).map_err(|e| io::Error::other(e.to_string()))?;
+        let mut copied = 0;
+        for input in inputs {
+            if let Some(relative) = sandbox.relative(&input) {
+                copy_file(&input, &sandbox.root.join(relative))?;
+                copied += 1;
+            }
+        }
+
+        fs::create_dir_all(&sandbox.dir)?;
+        for output in task.outputs.iter().filter(|o| exclusion(o).is_none()) {
+            if let Some(parent) = sandbox.relative(output).and_then(|o| output_parent(&o)) {
+                fs::create_dir_all(sandbox.root.join(parent))?;
+            }
+        }
+
+        logging::debug(format!(
+            "Task '{}': copied {} inputs into sandbox {}",
+            task.id,
+            copied,
+            sandbox.root.display()
+        ));
+        Ok(sandbox)
+    }
+
+    /// The directory the command runs in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Copies the files in the sandbox that the task's outputs select back to
+    /// the project, replacing the files there.
+    pub fn copy_outputs(&self, task: &Task) -> io::Result<()> {
+        let outputs: Vec<PathBuf> = task
+            .outputs
+            .iter()
+            .filter_map(|output| match exclusion(output) {
+                Some(excluded) => Some(PathBuf::from(format!(
+                    "!{}",
+                    self.relative(&excluded)?.display()
+                ))),
+                None => self.relative(output),
+            })
+            .collect();
+
+        for file in files_in_dir(&self.root) {
+            let Ok(relative) = file.strip_prefix(&self.root) else {
+                continue;
+            };
+            if patterns_match(&outputs, relative) {
+                copy_file(&file, &self.project.join(relative))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `path` relative to the current directory, if it lies inside it.
+    fn relative(&self, path: &Path) -> Option<PathBuf> {
+        relative_to(&self.project, path)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        LIVE.lock().unwrap().retain(|root| root != &self.root);
+        if let Err(e) = fs::remove_dir_all(&self.root) {
+            logging::debug(format!(
+                "Failed to remove sandbox {}: {}",
+                self.root.display(),
+                e
+            ));
+        }
+    }
+}
+
+/// Removes every sandbox that still exists, for when compi exits without
+/// dropping them.
+pub fn remove_all() {
+    for root in LIVE.lock().unwrap().drain(..) {
+        let _ = fs::remove_dir_all(root);
+    }
+}
+
+fn relative_to(project: &Path, path: &Path) -> Option<PathBuf> {
+    let absolute = normalize_path_lexical(&project.join(path));
+    let relative = absolute.strip_prefix(project).ok()?;
+    Some(relative.to_path_buf())
+}
+
+/// The directory an output is written into: the part of a glob before its
+/// first wildcard, or the parent of a plain path.
+fn output_parent(output: &Path) -> Option<PathBuf> {
+    if !is_glob_pattern(&output.to_string_lossy()) {
+        return output.parent().map(Path::to_path_buf);
+    }
+    Some(
+        output
+            .components()
+            .take_while(|c| match c {
+                Component::Normal(part) => !is_glob_pattern(&part.to_string_lossy()),
+                _ => true,
+            })
+            .collect(),
+    )
+}
+
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(from, to)?;
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A cron expression from a task's `schedule`, with five fields: minute,
+/// hour, day of the month, month and day of the week, matched in UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of the month or of the week is `*`. When both are
+    /// restricted, a day matching either one is due, as in cron.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// Parses fields made of `*`, numbers, ranges such as `1-5` and steps such
+    /// as `*/15`, separated by commas, or one of `@hourly`, `@daily`,
+    /// `@weekly`, `@monthly` and `@yearly`. Sunday is 0 or 7.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = match text.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            text => text,
+        };
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), found {}",
+                fields.len()
+            ));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7, "weekday")?;
+        // 7 is another name for Sunday.
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        Ok(Schedule {
+            minutes: parse_field(minute, 0, 59, "minute")?,
+            hours: parse_field(hour, 0, 23, "hour")?,
+            days: parse_field(day, 1, 31, "day")?,
+            months: parse_field(month, 1, 12, "month")?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Whether the minute that `time` falls in is due.
+    pub fn matches(&self, time: SystemTime) -> bool {
+        // RFC 3339 in UTC: "2026-10-18T03:00:00Z".
+        let text = humantime::format_rfc3339_seconds(time).to_string();
+        let field = |range: std::ops::Range<usize>| text[range].parse::<u32>().unwrap_or(0);
+        let (month, day, hour, minute) = (field(5..7), field(8..10), field(11..13), field(14..16));
+        let days = time
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs() / 86_400)
+            .unwrap_or(0);
+        // 1 January 1970 was a Thursday.
+        let weekday = ((days + 4) % 7) as u32;
+
+        let has = |set: u64, value: u32| set & (1 << value) != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => has(self.days, day) || has(self.weekdays, weekday),
+            _ => has(self.days, day) && has(self.weekdays, weekday),
+        };
+        has(self.minutes, minute) && has(self.hours, hour) && has(self.months, month) && day_matches
+    }
+}
+
+/// The values a field selects, as a bit set.
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let number = |text: &str| {
+        text.parse::<u32>()
+            .ok()
+            .filter(|n| (min..=max).contains(n))
+            .ok_or_else(|| {
+                format!(
+                    "{} '{}' is not a number from {} to {}",
+                    name, text, min, max
+                )
+            })
+    };
+
+    let mut set = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("{} step '{}' is not a positive number", name, step)),
+            },
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                // `5/15` runs from 5 to the end of the range.
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("{} range '{}' is empty", name, range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> SystemTime {
+        humantime::parse_rfc3339(time).unwrap()
+    }
+
+    #[test]
+    fn steps_start_at_the_range_start() {
+        let every = Schedule::parse("*/15 * * * *").unwrap();
+        assert!(every.matches(at("2026-10-18T03:15:00Z")));
+        assert!(every.matches(at("2026-10-18T03:00:00Z")));
+        assert!(!every.matches(at("2026-10-18T03:16:00Z")));
+
+        let offset = Schedule::parse("5/15 * * * *").unwrap();
+        assert!(offset.matches(at("2026-10-18T03:20:00Z")));
+        assert!(offset.matches(at("2026-10-18T03:50:00Z")));
+        assert!(!offset.matches(at("2026-10-18T03:15:00Z")));
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        let sunday = Schedule::parse("0 0 * * 7").unwrap();
+        assert_eq!(sunday, Schedule::parse("0 0 * * 0").unwrap());
+        assert!(sunday.matches(at("2026-10-18T00:00:00Z")));
+        assert!(!sunday.matches(at("2026-10-19T00:00:00Z")));
+    }
+
+    #[test]
+    fn restricted_day_and_weekday_match_either() {
+        // The 1st of the month or any Monday.
+        let schedule = Schedule::parse("0 0 1 * 1").unwrap();
+        assert!(schedule.matches(at("2026-10-19T00:00:00Z")));
+        assert!(schedule.matches(at("2026-10-01T00:00:00Z")));
+        assert!(schedule.matches(at("2026-06-01T00:00:00Z")));
+        assert!(!schedule.matches(at("2026-10-20T00:00:00Z")));
+
+        // With `*` as the weekday, only the 1st.
+        let monthly = Schedule::parse("@monthly").unwrap();
+        assert!(monthly.matches(at("2026-10-01T00:00:00Z")));
+        assert!(!monthly.matches(at("2026-10-19T00:00:00Z")));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(Schedule::parse("* * *").is_err());
+        assert!(Schedule::parse("61 * * * *").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+        assert!(Schedule::parse("0 0 * * 8").is_err());
+        assert!(Schedule::parse("30-10 * * * *").is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, Read, Write},
+    path::PathBuf,
+    process::{self, ExitStatus},
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// Bytes of each output stream kept in memory when no limit is configured.
+pub const DEFAULT_MEMORY_LIMIT: u64 = 16 * 1024 * 1024;
+
+static MEMORY_LIMIT: OnceLock<u64> = OnceLock::new();
+static NEXT_SPOOL: AtomicUsize = AtomicUsize::new(0);
+/// Temporary files that exist, removed by [`remove_all`] when compi is
+/// interrupted and exits without dropping them.
+static LIVE: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Sets how many bytes of each output stream are kept in memory before the
+/// rest goes to a temporary file.
+pub fn init(limit: Option<u64>) {
+    let _ = MEMORY_LIMIT.set(limit.unwrap_or(DEFAULT_MEMORY_LIMIT));
+}
+
+fn memory_limit() -> u64 {
+    MEMORY_LIMIT.get().copied().unwrap_or(DEFAULT_MEMORY_LIMIT)
+}
+
+/// The exit status and output of a command.
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stdout: Captured,
+    pub stderr: Captured,
+}
+
+/// Everything a command wrote to one stream. Once it outgrows the memory
+/// limit, the whole stream is written to a temporary file and only its start
+/// stays in memory.
+#[derive(Debug, Default)]
+pub struct Captured {
+    head: Vec<u8>,
+    spool: Option<Spool>,
+    len: u64,
+    last: Option<u8>,
+}
+
+#[derive(Debug)]
+struct Spool {
+    path: PathBuf,
+    file: File,
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        LIVE.lock().unwrap().retain(|path| path != &self.path);
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Removes every temporary output file that still exists, for when compi
+/// exits without dropping them.
+pub fn remove_all() {
+    for path in LIVE.lock().unwrap().drain(..) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+impl From<Vec<u8>> for Captured {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            len: bytes.len() as u64,
+            last: bytes.last().copied(),
+            head: bytes,
+            spool: None,
+        }
+    }
+}
+
+impl Captured {
+    pub fn push(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.len += data.len() as u64;
+        self.last = data.last().copied();
+
+        if let Some(spool) = &mut self.spool {
+            return spool.file.write_all(data);
+        }
+
+        let limit = memory_limit();
+        if self.head.len() as u64 + data.len() as u64 <= limit {
+            self.head.extend_from_slice(data);
+            return Ok(());
+        }
+
+        let mut spool = Spool::create()?;
+        spool.file.write_all(&self.head)?;
+        spool.file.write_all(data)?;
+        let room = (limit as usize).saturating_sub(self.head.len());
+        self.head.extend_from_slice(&data[..room.min(data.len())]);
+        self.spool = Some(spool);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Total bytes written, including those only in the temporary file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn ends_with_newline(&self) -> bool {
+        self.last == Some(b'\n')
+    }
+
+    /// The start of the stream that is kept in memory, all of it unless
+    /// the stream outgrew the memory limit.
+    pub fn head(&self) -> &[u8] {
+        &self.head
+    }
+
+    pub fn into_head(self) -> Vec<u8> {
+        self.head
+    }
+
+    /// The whole stream, read back from the temporary file if necessary.
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Copies the whole stream to `out` without reading it into memory.
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        match &self.spool {
+            Some(spool) => {
+                let mut reader = BufReader::new(File::open(&spool.path)?);
+                io::copy(&mut reader, out)?;
+                Ok(())
+            }
+            None => out.write_all(&self.head),
+        }
+    }
+
+    /// Passes the whole stream to `f` in pieces of at most 64 KiB.
+    pub fn for_each_chunk(&self, mut f: impl FnMut(&[u8]) -> io::Result<()>) -> io::Result<()> {
+        let Some(spool) = &self.spool else {
+            return f(&self.head);
+        };
+
+        let mut file = File::open(&spool.path)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            f(&buf[..n])?;
+        }
+    }
+}
+
+/// A command's stdout written straight to a temporary file, which tasks that
+/// pipe from the command read as their stdin.
+#[derive(Debug)]
+pub struct Pipe {
+    spool: Spool,
+}
+
+impl Pipe {
+    pub fn create() -> io::Result<Self> {
+        Ok(Self {
+            spool: Spool::create()?,
+        })
+    }
+
+    /// A handle for the command to write its stdout to.
+    pub fn writer(&self) -> io::Result<File> {
+        self.spool.file.try_clone()
+    }
+
+    /// A handle that reads everything written, from the start.
+    pub fn reader(&self) -> io::Result<File> {
+        File::open(&self.spool.path)
+    }
+
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.spool.path)
+    }
+}
+
+impl Spool {
+    fn create() -> io::Result<Self> {
+        let id = NEXT_SPOOL.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("compi-output-{}-{}", process::id(), id));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // Output is written unmasked, so only the user running compi may read it.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&path)?;
+        LIVE.lock().unwrap().push(path.clone());
+        Ok(Self { path, file })
+    }
+}
@@ -1,70 +1,335 @@
-use std::{collections::HashMap, env, fs, path::PathBuf};
+use glob::glob;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::SystemTime,
+};
 
-use regex::Regex;
+use regex::{Regex, bytes};
 use serde::Deserialize;
 
-use super::{Task, dependency::validate_tasks};
+use super::{
+    Task,
+    dependency::{resolve_file_dependencies, validate_tasks},
+    schema,
+    template::{self, Renderer, Templates},
+};
+use crate::diagnostics::{BUILTIN_MATCHERS, ProblemMatcher, ProblemMatcherConfig};
+use crate::dotenv::{self, EnvVar};
 use crate::error::{CompiError, Result};
+use crate::hooks::Hooks;
+use crate::logging::LogFormat;
 use crate::output::OutputMode;
+use crate::plugin::PluginConfig;
+use crate::process::{self, Signal};
+use crate::schedule::Schedule;
+use crate::util::{is_glob_pattern, parse_size, resolve_path};
+use crate::vcs;
+use crate::webhook::Webhook;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 struct Config {
     #[serde(rename = "task")]
     tasks: HashMap<String, Task>,
     config: Option<ConfigSection>,
     #[serde(default)]
     variables: HashMap<String, String>,
+    workspace: Option<WorkspaceSection>,
+    #[serde(rename = "profile", default)]
+    profiles: HashMap<String, ProfileSection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileSection {
+    #[serde(default)]
+    env_files: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceSection {
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+/// A project registered in `[workspace] members`, with its own config and cache.
+#[derive(Debug, Clone)]
+pub struct WorkspaceMember {
+    pub name: String,
+    pub dir: PathBuf,
+    pub config_path: String,
+    pub cache_dir: Option<String>,
+    /// Settings in the member's `[config]` other than `cache_dir`, which have
+    /// no effect since they come from the root.
+    pub ignored_config: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ConfigSection {
-    default: Option<String>,
+    default: Option<DefaultTasks>,
     cache_dir: Option<String>,
     workers: Option<usize>,
+    #[serde(default)]
+    tag_limits: HashMap<String, usize>,
     default_timeout: Option<String>,
+    grace_period: Option<String>,
     output: Option<OutputMode>,
+    output_memory_limit: Option<String>,
+    log_format: Option<LogFormat>,
+    log_file: Option<String>,
+    otel: Option<String>,
+    #[serde(default)]
+    webhooks: Vec<Webhook>,
+    #[serde(default)]
+    mask: Vec<String>,
+    #[serde(default)]
+    plugins: Vec<PluginConfig>,
+    #[serde(default)]
+    env_files: Vec<String>,
+    #[serde(default)]
+    problem_matchers: Vec<ProblemMatcherConfig>,
+    wrapper: Option<String>,
+    jobserver: Option<bool>,
+    strict_outputs: Option<bool>,
+    strict_variables: Option<bool>,
+    #[serde(default)]
+    templates: Templates,
+    #[serde(default)]
+    forward_signals: HashMap<String, String>,
+    #[serde(flatten)]
+    hooks: Hooks,
+}
+
+/// `default = "build"` or `default = ["lint", "test"]`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged, expecting = "a task name or a list of task names")]
+enum DefaultTasks {
+    One(String),
+    Many(Vec<String>),
 }
 
 #[derive(Debug)]
 pub struct TaskConfiguration {
     pub tasks: Vec<Task>,
-    pub default_task: Option<String>,
+    /// Tasks run when none is given, all tasks when empty.
+    pub default_tasks: Vec<String>,
     pub cache_dir: Option<String>,
     pub workers: Option<usize>,
+    /// Most tasks with each tag that may run at once.
+    pub tag_limits: HashMap<String, usize>,
     pub default_timeout: Option<String>,
     pub output: Option<OutputMode>,
+    /// Bytes of each output stream kept in memory, see [`crate::spool`].
+    pub output_memory_limit: Option<u64>,
+    /// `true` to create a GNU make jobserver when there is none to join,
+    /// `false` to not join one either, see [`crate::jobserver`].
+    pub jobserver: Option<bool>,
+    pub log_format: Option<LogFormat>,
+    pub log_file: Option<String>,
+    pub otel: Option<String>,
+    pub webhooks: Vec<Webhook>,
+    pub mask: Vec<bytes::Regex>,
+    pub plugins: Vec<PluginConfig>,
+    /// Matchers declared in `[config]`, passed to [`crate::diagnostics::init`].
+    pub problem_matchers: Vec<ProblemMatcher>,
+    pub hooks: Hooks,
+    /// Signal each handled signal is forwarded as, see [`process::signal_mapping`].
+    pub forward_signals: Vec<(Signal, Option<Signal>)>,
+    pub members: Vec<WorkspaceMember>,
+    pub profile: Option<String>,
+    /// Wrapper for run-level hooks. Tasks already carry the wrapper that applies to them.
+    pub wrapper: Option<String>,
+    /// Variables from `env_files`, which commands receive once passed to [`dotenv::init`].
+    pub env: Vec<EnvVar>,
+    /// Directory containing the config file.
+    pub root: PathBuf,
+    /// What loading did to each task, for tasks generated during the run.
+    pub settings: TaskSettings,
+}
+
+/// Variables and `[config]` defaults that loading applies to each task, kept
+/// so that tasks printed by a generator are prepared the same way.
+#[derive(Debug, Clone, Default)]
+pub struct TaskSettings {
+    variables: HashMap<String, String>,
+    /// Variables of each workspace member, by its directory.
+    member_variables: HashMap<PathBuf, HashMap<String, String>>,
+    strict: bool,
+    captures: HashSet<String>,
+    runtime: HashSet<String>,
+    templates: Templates,
+    wrapper: Option<String>,
+    strict_outputs: Option<bool>,
+    grace_period: Option<String>,
+    executors: Vec<String>,
+    problem_matchers: Vec<String>,
+    root: PathBuf,
+}
+
+impl TaskSettings {
+    /// Substitutes variables into a task printed by `generator`, resolves its
+    /// paths, fills in defaults from `[config]` and checks it, as loading does
+    /// for the tasks of the generator's config file.
+    pub(crate) fn prepare(&self, task: &mut Task, generator: &Task) -> Result<()> {
+        let (variables, dir) = match generator
+            .dir
+            .as_ref()
+            .and_then(|dir| self.member_variables.get_key_value(dir))
+        {
+            Some((dir, variables)) => (variables, dir.as_path()),
+            None => (&self.variables, self.root.as_path()),
+        };
+        let renderer = renderer(variables, self.strict, &self.captures, &self.runtime)
+            .templates(self.templates);
+        substitute_variables_in_task(task, &renderer)?;
+        task.resolve_paths(dir)?;
+
+        let renderer = self::renderer(&self.variables, self.strict, &self.captures, &self.runtime)
+            .templates(self.templates);
+        apply_defaults(
+            task,
+            &renderer,
+            self.wrapper.as_ref(),
+            self.strict_outputs,
+            self.grace_period.as_ref(),
+        )?;
+        check_task(task, &self.executors, &self.problem_matchers)
+    }
+}
+
+/// Settings that change how a config file is loaded.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Profile whose env files are added, from `[profile.<name>]`.
+    pub profile: Option<String>,
+    /// Fail on undefined variables even without `[config] strict_variables`.
+    pub strict_variables: bool,
+    /// Load a config file that does not exist as an empty one, for commands
+    /// such as `compi exec` that do not need tasks from it.
+    pub allow_missing: bool,
 }
 
+/// Reads and validates a config file, substituting variables into every task.
 pub fn load_tasks(config_path: &str) -> Result<TaskConfiguration> {
-    let config = load_and_parse_config(config_path)?;
-    process_config(config)
+    load_tasks_with(config_path, &LoadOptions::default())
+}
+
+/// Like [`load_tasks`], with a profile or stricter checks.
+pub fn load_tasks_with(config_path: &str, options: &LoadOptions) -> Result<TaskConfiguration> {
+    load_overlaid_tasks(&[config_path], options)
+}
+
+/// Loads `config_paths[0]` with each later file laid over it. Tables such as
+/// `[config]`, `[variables]` and each `[task.<id>]` are merged key by key, so
+/// an overlay adds tasks and changes single fields of existing ones. Paths in
+/// every file are relative to the first one, which also owns the cache.
+pub fn load_overlaid_tasks(
+    config_paths: &[&str],
+    options: &LoadOptions,
+) -> Result<TaskConfiguration> {
+    let (&config_path, overlays) = config_paths
+        .split_first()
+        .ok_or_else(|| CompiError::Parse("no config file given".to_string()))?;
+    let config = if overlays.is_empty() {
+        if options.allow_missing && !Path::new(config_path).exists() {
+            Config::default()
+        } else {
+            load_and_parse_config(config_path)?
+        }
+    } else {
+        load_and_merge_configs(config_paths)?
+    };
+    process_config(config, config_path, options)
 }
 
 fn load_and_parse_config(config_path: &str) -> Result<Config> {
     let contents = fs::read_to_string(config_path)?;
-    let config = toml::from_str(&contents).map_err(|e| {
+    schema::parse(&contents, config_path)
+}
+
+fn load_and_merge_configs(config_paths: &[&str]) -> Result<Config> {
+    let mut merged = toml::Table::new();
+    for &config_path in config_paths {
+        let contents = fs::read_to_string(config_path).map_err(|e| {
+            CompiError::Parse(format!(
+                "failed to read config file '{}': {}",
+                config_path, e
+            ))
+        })?;
+        let table: toml::Table = schema::parse(&contents, config_path)?;
+        merge_tables(&mut merged, table);
+    }
+
+    toml::Value::Table(merged).try_into().map_err(|e| {
         CompiError::Parse(format!(
-            "failed to parse config file '{}': {}",
-            config_path, e
+            "failed to parse config files '{}': {}",
+            config_paths.join("', '"),
+            e
         ))
-    })?;
-    Ok(config)
+    })
 }
 
-fn process_config(config: Config) -> Result<TaskConfiguration> {
-    let default_task = config.config.as_ref().and_then(|c| c.default.clone());
+/// Merges `overlay` into `base`, recursing into tables. Anything else in
+/// `overlay`, including arrays, replaces the value in `base`.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge_tables(existing, table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn process_config(
+    config: Config,
+    config_path: &str,
+    options: &LoadOptions,
+) -> Result<TaskConfiguration> {
+    let profile = options.profile.as_deref();
+    let root = Path::new(config_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .to_path_buf();
+    let default_tasks = match config.config.as_ref().and_then(|c| c.default.as_ref()) {
+        Some(DefaultTasks::One(task)) => vec![task.clone()],
+        Some(DefaultTasks::Many(tasks)) => tasks.clone(),
+        None => Vec::new(),
+    };
     let cache_dir = config.config.as_ref().and_then(|c| c.cache_dir.clone());
 
     let workers = config.config.as_ref().and_then(|c| c.workers);
     if let Some(0) = workers {
         return Err(CompiError::Parse("workers cannot be 0".to_string()));
     }
+    let tag_limits = config
+        .config
+        .as_ref()
+        .map(|c| c.tag_limits.clone())
+        .unwrap_or_default();
+    if let Some((tag, _)) = tag_limits.iter().find(|(_, limit)| **limit == 0) {
+        return Err(CompiError::Parse(format!(
+            "tag_limits for '{}' cannot be 0",
+            tag
+        )));
+    }
 
     let default_timeout = config
         .config
         .as_ref()
         .and_then(|c| c.default_timeout.clone());
     let output = config.config.as_ref().and_then(|c| c.output.clone());
+    let log_format = config.config.as_ref().and_then(|c| c.log_format);
+    let log_file = config
+        .config
+        .as_ref()
+        .and_then(|c| c.log_file.as_deref())
+        .map(|file| resolve_path(Path::new(file), &root).display().to_string());
+    let otel = config.config.as_ref().and_then(|c| c.otel.clone());
 
     if let Some(ref timeout_str) = default_timeout {
         humantime::parse_duration(timeout_str).map_err(|e| {
@@ -72,34 +337,418 @@ fn process_config(config: Config) -> Result<TaskConfiguration> {
         })?;
     }
 
+    let output_memory_limit = config
+        .config
+        .as_ref()
+        .and_then(|c| c.output_memory_limit.as_deref())
+        .map(|limit| {
+            parse_size(limit).map_err(|e| {
+                CompiError::Parse(format!("invalid output_memory_limit '{}': {}", limit, e))
+            })
+        })
+        .transpose()?;
+
+    let forward_signals = match &config.config {
+        Some(c) => process::signal_mapping(&c.forward_signals)?,
+        None => process::signal_mapping(&HashMap::new())?,
+    };
+
+    let env = load_env_files(&config, config_path, profile)?;
+
+    let member_configs = match &config.workspace {
+        Some(workspace) => read_members(&workspace.members, config_path)?,
+        None => Vec::new(),
+    };
+
+    let referenced =
+        referenced_variables(std::iter::once(&config).chain(member_configs.iter().map(|(_, c)| c)));
     let mut variables = config.variables;
-    add_builtin_variables(&mut variables);
+    add_builtin_variables(&mut variables, config_path, |name| {
+        referenced.contains(name)
+    });
+    for var in env.iter().filter(|var| !var.overridden()) {
+        variables.insert(format!("ENV_{}", var.name), var.value.clone());
+    }
 
-    let tasks: Vec<Task> = config
+    let strict = options.strict_variables
+        || config
+            .config
+            .as_ref()
+            .and_then(|c| c.strict_variables)
+            .unwrap_or(false);
+    // Captured variables and env file entries are only set when commands run.
+    let captures: HashSet<String> = config
+        .tasks
+        .values()
+        .chain(member_configs.iter().flat_map(|(_, c)| c.tasks.values()))
+        .filter_map(|task| task.capture.clone())
+        .collect();
+    let runtime: HashSet<String> = captures
+        .iter()
+        .cloned()
+        .chain(env.iter().map(|var| var.name.clone()))
+        .collect();
+    let templates = config
+        .config
+        .as_ref()
+        .map(|c| c.templates)
+        .unwrap_or_default();
+    let renderer = renderer(&variables, strict, &captures, &runtime).templates(templates);
+
+    let mut webhooks = config
+        .config
+        .as_ref()
+        .map(|c| c.webhooks.clone())
+        .unwrap_or_default();
+    for webhook in &mut webhooks {
+        webhook.url = renderer
+            .render(&webhook.url)
+            .map_err(context("webhook url"))?;
+        if webhook.url.is_empty() {
+            return Err(CompiError::Parse("webhook url cannot be empty".to_string()));
+        }
+    }
+
+    let mask = config
+        .config
+        .as_ref()
+        .map(|c| parse_mask_patterns(&c.mask, &renderer))
+        .transpose()?
+        .unwrap_or_default();
+
+    let problem_matchers = config
+        .config
+        .as_ref()
+        .map(|c| c.problem_matchers.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|matcher| {
+            ProblemMatcher::new(matcher).map_err(|e| {
+                CompiError::Parse(format!("problem matcher '{}': {}", matcher.name, e))
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut plugins = config
+        .config
+        .as_ref()
+        .map(|c| c.plugins.clone())
+        .unwrap_or_default();
+    for plugin in &mut plugins {
+        for (key, value) in plugin.config.iter_mut() {
+            *value = renderer.render(value).map_err(context(&format!(
+                "plugin '{}' config '{}'",
+                plugin.name, key
+            )))?;
+        }
+    }
+
+    let mut hooks = config
+        .config
+        .as_ref()
+        .map(|c| c.hooks.clone())
+        .unwrap_or_default();
+    for (name, command) in hooks.commands_mut() {
+        *command = renderer
+            .render_command(command)
+            .map_err(context(&format!("[config] {}", name)))?;
+    }
+
+    let mut tasks: Vec<Task> = config
         .tasks
         .into_iter()
         .map(|(name, mut task)| {
             if task.id.is_empty() {
                 task.id = name;
             }
-            substitute_variables_in_task(&mut task, &variables);
-            task
+            substitute_variables_in_task(&mut task, &renderer)?;
+            task.resolve_paths(&root)?;
+            Ok(task)
         })
-        .collect();
+        .collect::<Result<_>>()?;
 
+    let mut members = Vec::new();
+    let mut member_variables = HashMap::new();
+    for (member, member_config) in member_configs {
+        let mut own_variables = variables.clone();
+        own_variables.extend(member_config.variables);
+        let member_renderer =
+            self::renderer(&own_variables, strict, &captures, &runtime).templates(templates);
+        for (id, mut task) in member_config.tasks {
+            if task.id.is_empty() {
+                task.id = id;
+            }
+            substitute_variables_in_task(&mut task, &member_renderer)?;
+            namespace_task(&mut task, &member.name);
+            task.resolve_paths(&member.dir)?;
+            tasks.push(task);
+        }
+        member_variables.insert(member.dir.clone(), own_variables);
+        members.push(member);
+    }
+    // Tables have no order once parsed, so errors about tasks and every
+    // listing name them by id.
+    tasks.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let wrapper = config
+        .config
+        .as_ref()
+        .and_then(|c| c.wrapper.as_deref())
+        .map(|w| {
+            renderer
+                .render_command(w)
+                .map_err(context("[config] wrapper"))
+        })
+        .transpose()?
+        .filter(|w| !w.trim().is_empty());
+    let strict_outputs = config.config.as_ref().and_then(|c| c.strict_outputs);
+    let grace_period = config.config.as_ref().and_then(|c| c.grace_period.clone());
+    if let Some(wrapper) = &wrapper {
+        check_wrapper(wrapper)?;
+    }
+    for task in &mut tasks {
+        apply_defaults(
+            task,
+            &renderer,
+            wrapper.as_ref(),
+            strict_outputs,
+            grace_period.as_ref(),
+        )?;
+    }
+
+    resolve_file_dependencies(&mut tasks)?;
     validate_tasks(&tasks)?;
 
+    let executors: Vec<String> = plugins.iter().map(|p| p.name.clone()).collect();
+    let matcher_names: Vec<String> = problem_matchers.iter().map(|m| m.name.clone()).collect();
+    for task in &tasks {
+        check_task(task, &executors, &matcher_names)?;
+    }
+
+    let settings = TaskSettings {
+        variables,
+        member_variables,
+        strict,
+        captures,
+        runtime,
+        templates,
+        wrapper: wrapper.clone(),
+        strict_outputs,
+        grace_period,
+        executors,
+        problem_matchers: matcher_names,
+        root: root.clone(),
+    };
+
     Ok(TaskConfiguration {
         tasks,
-        default_task,
+        default_tasks,
         cache_dir,
         workers,
+        tag_limits,
         default_timeout,
         output,
+        output_memory_limit,
+        jobserver: config.config.as_ref().and_then(|c| c.jobserver),
+        log_format,
+        log_file,
+        otel,
+        webhooks,
+        mask,
+        plugins,
+        problem_matchers,
+        hooks,
+        forward_signals,
+        members,
+        profile: profile.map(str::to_string),
+        wrapper,
+        env,
+        root,
+        settings,
     })
 }
 
-fn add_builtin_variables(variables: &mut HashMap<String, String>) {
+/// `[config] env_files` followed by the profile's, so that the profile's
+/// values take precedence. Paths are relative to the config file.
+fn load_env_files(
+    config: &Config,
+    config_path: &str,
+    profile: Option<&str>,
+) -> Result<Vec<EnvVar>> {
+    let root = Path::new(config_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let mut files: Vec<PathBuf> = config
+        .config
+        .as_ref()
+        .map(|c| {
+            c.env_files
+                .iter()
+                .map(|f| resolve_path(Path::new(f), root))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if let Some(name) = profile {
+        let section = config.profiles.get(name).ok_or_else(|| {
+            let mut available: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+            available.sort();
+            CompiError::Parse(format!(
+                "profile '{}' is not defined (available: {})",
+                name,
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            ))
+        })?;
+        files.extend(
+            section
+                .env_files
+                .iter()
+                .map(|f| resolve_path(Path::new(f), root)),
+        );
+    }
+
+    dotenv::load(&files)
+}
+
+/// Reads each member's config. Their tasks are added as `member:task`, with
+/// paths relative to the member's directory, where their commands run too.
+fn read_members(patterns: &[String], config_path: &str) -> Result<Vec<(WorkspaceMember, Config)>> {
+    let root = Path::new(config_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let mut members: Vec<(WorkspaceMember, Config)> = Vec::new();
+
+    for dir in member_dirs(root, patterns)? {
+        let name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| {
+                CompiError::Parse(format!("invalid workspace member '{}'", dir.display()))
+            })?;
+        if members.iter().any(|(m, _)| m.name == name) {
+            return Err(CompiError::Parse(format!(
+                "workspace member name '{}' is used by more than one directory",
+                name
+            )));
+        }
+
+        let member_path = dir.join("compi.toml").to_string_lossy().to_string();
+        let contents = fs::read_to_string(&member_path).map_err(|e| {
+            CompiError::Parse(format!(
+                "failed to read workspace member config '{}': {}",
+                member_path, e
+            ))
+        })?;
+        let config: Config = schema::parse(&contents, &member_path)?;
+        let table: toml::Table = toml::from_str(&contents).unwrap_or_default();
+        let mut ignored_config: Vec<String> = table
+            .get("config")
+            .and_then(|section| section.as_table())
+            .map(|section| {
+                section
+                    .keys()
+                    .filter(|k| *k != "cache_dir")
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        ignored_config.sort();
+        if config.workspace.is_some() {
+            return Err(CompiError::Parse(format!(
+                "workspace member '{}' cannot define its own workspace",
+                name
+            )));
+        }
+
+        let member = WorkspaceMember {
+            name,
+            dir,
+            config_path: member_path,
+            cache_dir: config.config.as_ref().and_then(|c| c.cache_dir.clone()),
+            ignored_config,
+        };
+        members.push((member, config));
+    }
+    Ok(members)
+}
+
+/// Member directories, expanding glob patterns to directories with a `compi.toml`.
+fn member_dirs(root: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for pattern in patterns {
+        let path = root.join(pattern);
+        let path_str = path.to_string_lossy();
+        if !is_glob_pattern(&path_str) {
+            dirs.push(path);
+            continue;
+        }
+
+        let mut matched: Vec<PathBuf> = glob(&path_str)
+            .map_err(|e| {
+                CompiError::Parse(format!("invalid workspace member '{}': {}", pattern, e))
+            })?
+            .filter_map(|entry| entry.ok())
+            .filter(|dir| dir.join("compi.toml").is_file())
+            .collect();
+        matched.sort();
+        dirs.extend(matched);
+    }
+    Ok(dirs)
+}
+
+/// Dependencies without a `member:` prefix refer to tasks in the same member.
+fn namespace_task(task: &mut Task, member: &str) {
+    task.id = format!("{}:{}", member, task.id);
+    for dep in task.dependencies.iter_mut().chain(&mut task.pipe_from) {
+        if !dep.contains(':') {
+            *dep = format!("{}:{}", member, dep);
+        }
+    }
+    for alias in &mut task.aliases {
+        *alias = format!("{}:{}", member, alias);
+    }
+}
+
+/// Entries prefixed with `regex:` are regular expressions, anything else is a literal value.
+fn parse_mask_patterns(entries: &[String], renderer: &Renderer) -> Result<Vec<bytes::Regex>> {
+    let mut patterns = Vec::new();
+    for entry in entries {
+        let source = match entry.strip_prefix("regex:") {
+            Some(pattern) => pattern.to_string(),
+            None => regex::escape(
+                &renderer
+                    .render(entry)
+                    .map_err(context(&format!("mask entry '{}'", entry)))?,
+            ),
+        };
+        if source.is_empty() {
+            continue;
+        }
+
+        let pattern = bytes::Regex::new(&source)
+            .map_err(|e| CompiError::Parse(format!("invalid mask pattern '{}': {}", entry, e)))?;
+        patterns.push(pattern);
+    }
+    Ok(patterns)
+}
+
+/// Built-in variables that describe the machine and the time of the run.
+const BUILTIN_VARIABLES: [&str; 3] = ["TIMESTAMP", "OS", "ARCH"];
+
+/// Adds `ENV_*`, `PWD` and the built-in variables, without replacing
+/// variables of the same name from `[variables]`. The environment never
+/// replaces a built-in, since its variables are added as `ENV_<NAME>`.
+/// Built-ins are only computed when `referenced` says a rendered value refers
+/// to them, as the `GIT_*` ones run git.
+fn add_builtin_variables(
+    variables: &mut HashMap<String, String>,
+    config_path: &str,
+    referenced: impl Fn(&str) -> bool,
+) {
     for (key, value) in env::vars() {
         variables.insert(format!("ENV_{}", key), value);
     }
@@ -107,47 +756,224 @@ fn add_builtin_variables(variables: &mut HashMap<String, String>) {
     if let Ok(pwd) = env::current_dir() {
         variables.insert("PWD".to_string(), pwd.to_string_lossy().to_string());
     }
+
+    let root = Path::new(config_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    for name in BUILTIN_VARIABLES.into_iter().chain(vcs::BUILTIN_VARIABLES) {
+        if variables.contains_key(name) || !referenced(name) {
+            continue;
+        }
+        let value = match name {
+            "TIMESTAMP" => Some(
+                humantime::format_rfc3339_seconds(SystemTime::now())
+                    .to_string()
+                    .replace(['-', ':'], ""),
+            ),
+            "OS" => Some(env::consts::OS.to_string()),
+            "ARCH" => Some(env::consts::ARCH.to_string()),
+            _ => vcs::variable(name, root),
+        };
+        if let Some(value) = value {
+            variables.insert(name.to_string(), value);
+        }
+    }
 }
 
-fn substitute_variables_in_task(task: &mut Task, variables: &HashMap<String, String>) {
-    task.command = substitute_variables(&task.command, variables);
+/// Variables referenced by the values that are rendered, so that comments and
+/// other text do not count.
+fn referenced_variables<'a>(configs: impl Iterator<Item = &'a Config>) -> HashSet<String> {
+    let mut referenced = HashSet::new();
+    for config in configs {
+        let templates = config
+            .config
+            .as_ref()
+            .map(|c| c.templates)
+            .unwrap_or_default();
+        if let Some(section) = &config.config {
+            let values = section
+                .webhooks
+                .iter()
+                .map(|webhook| &webhook.url)
+                .chain(&section.mask)
+                .chain(section.plugins.iter().flat_map(|p| p.config.values()))
+                .chain(&section.wrapper)
+                .chain(section.hooks.commands());
+            for value in values {
+                referenced.extend(template::references(value, templates));
+            }
+        }
+        for task in config.tasks.values() {
+            let templates = task.templates.unwrap_or(templates);
+            let paths = task.inputs.iter().chain(&task.outputs);
+            let values = [&task.command]
+                .into_iter()
+                .chain(&task.wrapper)
+                .chain(task.hooks.commands())
+                .map(|value| value.to_string())
+                .chain(paths.map(|path| path.to_string_lossy().to_string()));
+            for value in values {
+                referenced.extend(template::references(&value, templates));
+            }
+        }
+    }
+    referenced
+}
 
-    task.inputs = task
-        .inputs
-        .iter()
-        .map(|path| PathBuf::from(substitute_variables(&path.to_string_lossy(), variables)))
-        .collect();
+/// Fills in `strict_outputs`, `grace_period` and `wrapper` from `[config]`
+/// where the task does not set them, rendering its own wrapper.
+fn apply_defaults(
+    task: &mut Task,
+    renderer: &Renderer,
+    wrapper: Option<&String>,
+    strict_outputs: Option<bool>,
+    grace_period: Option<&String>,
+) -> Result<()> {
+    task.strict_outputs = task.strict_outputs.or(strict_outputs);
+    task.grace_period = task.grace_period.take().or_else(|| grace_period.cloned());
+    if let Some(grace_period) = &task.grace_period {
+        humantime::parse_duration(grace_period).map_err(|e| {
+            CompiError::Parse(format!(
+                "invalid grace_period '{}' for task '{}': {}",
+                grace_period, task.id, e
+            ))
+        })?;
+    }
+    task.wrapper = match task.wrapper.take() {
+        Some(own) => Some(
+            task_renderer(renderer, task)
+                .render_command(&own)
+                .map_err(context(&format!("task '{}' field 'wrapper'", task.id)))?,
+        )
+        .filter(|w| !w.trim().is_empty()),
+        None => wrapper.cloned(),
+    };
+    Ok(())
+}
+
+fn check_wrapper(wrapper: &str) -> Result<()> {
+    shell_words::split(wrapper)
+        .map(|_| ())
+        .map_err(|e| CompiError::Parse(format!("invalid wrapper '{}': {}", wrapper, e)))
+}
 
-    task.outputs = task
-        .outputs
+/// Checks what [`validate_tasks`] does not: the task's wrapper, capture name,
+/// schedule, problem matchers and executor against the config's `executors` and
+/// `problem_matchers`.
+fn check_task(task: &Task, executors: &[String], problem_matchers: &[String]) -> Result<()> {
+    static VARIABLE_NAME: OnceLock<Regex> = OnceLock::new();
+    let variable_name =
+        VARIABLE_NAME.get_or_init(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+    if let Some(wrapper) = &task.wrapper {
+        check_wrapper(wrapper)?;
+    }
+    if let Some(capture) = &task.capture
+        && !variable_name.is_match(capture)
+    {
+        return Err(CompiError::Parse(format!(
+            "task '{}' captures into '{}' which is not a valid variable name",
+            task.id, capture
+        )));
+    }
+    if let Some(name) = task
+        .problem_matchers
         .iter()
-        .map(|path| PathBuf::from(substitute_variables(&path.to_string_lossy(), variables)))
-        .collect();
+        .find(|name| !BUILTIN_MATCHERS.contains(&name.as_str()) && !problem_matchers.contains(name))
+    {
+        return Err(CompiError::Parse(format!(
+            "task '{}' uses problem matcher '{}' which is neither built in nor listed in problem_matchers",
+            task.id, name
+        )));
+    }
+    if let Some(schedule) = &task.schedule {
+        Schedule::parse(schedule).map_err(|e| {
+            CompiError::Parse(format!(
+                "task '{}' has an invalid schedule '{}': {}",
+                task.id, schedule, e
+            ))
+        })?;
+    }
+    if let Some(executor) = &task.executor
+        && task.sandbox
+    {
+        return Err(CompiError::Parse(format!(
+            "task '{}' cannot use sandbox, since executor '{}' runs it instead of the shell",
+            task.id, executor
+        )));
+    }
+    if let Some(executor) = &task.executor
+        && !executors.contains(executor)
+    {
+        return Err(CompiError::Parse(format!(
+            "task '{}' uses executor '{}' which is not listed in plugins",
+            task.id, executor
+        )));
+    }
+    Ok(())
 }
 
-fn substitute_variables(text: &str, variables: &HashMap<String, String>) -> String {
-    let braced_regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
-    let simple_regex = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)\b").unwrap();
+fn substitute_variables_in_task(task: &mut Task, renderer: &Renderer) -> Result<()> {
+    let renderer = &task_renderer(renderer, task);
+    let field = |name: &str, text: &str| {
+        renderer
+            .render(text)
+            .map_err(context(&format!("task '{}' field '{}'", task.id, name)))
+    };
+    let command_field = |name: &str, text: &str| {
+        renderer
+            .render_command(text)
+            .map_err(context(&format!("task '{}' field '{}'", task.id, name)))
+    };
 
-    let mut result = braced_regex
-        .replace_all(text, |caps: &regex::Captures| {
-            let var_name = &caps[1];
-            variables
-                .get(var_name)
-                .cloned()
-                .unwrap_or_else(|| caps[0].to_string())
-        })
-        .to_string();
-
-    result = simple_regex
-        .replace_all(&result, |caps: &regex::Captures| {
-            let var_name = &caps[1];
-            variables
-                .get(var_name)
-                .cloned()
-                .unwrap_or_else(|| caps[0].to_string())
-        })
-        .to_string();
+    let command = command_field("command", &task.command)?;
+    let mut hooks = task.hooks.clone();
+    for (name, command) in hooks.commands_mut() {
+        *command = command_field(name, command)?;
+    }
 
-    result
+    let render_paths = |name: &str, paths: &[PathBuf]| -> Result<Vec<PathBuf>> {
+        paths
+            .iter()
+            .map(|path| field(name, &path.to_string_lossy()).map(PathBuf::from))
+            .collect()
+    };
+    let inputs = render_paths("inputs", &task.inputs)?;
+    let outputs = render_paths("outputs", &task.outputs)?;
+
+    task.command = command;
+    task.hooks = hooks;
+    task.inputs = inputs;
+    task.outputs = outputs;
+    Ok(())
+}
+
+/// `renderer` with the task's own `templates` setting, if it has one.
+fn task_renderer<'a>(renderer: &Renderer<'a>, task: &Task) -> Renderer<'a> {
+    match task.templates {
+        Some(templates) => renderer.clone().templates(templates),
+        None => renderer.clone(),
+    }
+}
+
+fn renderer<'a>(
+    variables: &'a HashMap<String, String>,
+    strict: bool,
+    captures: &HashSet<String>,
+    runtime: &HashSet<String>,
+) -> Renderer<'a> {
+    let renderer = Renderer::new(variables).defer(captures.clone());
+    if strict {
+        renderer.strict(runtime.clone())
+    } else {
+        renderer
+    }
+}
+
+/// Prefixes parse errors with where the failing value came from.
+fn context(location: &str) -> impl Fn(CompiError) -> CompiError + '_ {
+    move |e| match e {
+        CompiError::Parse(msg) => CompiError::Parse(format!("{}: {}", location, msg)),
+        other => other,
+    }
 }
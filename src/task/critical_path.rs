@@ -0,0 +1,182 @@
+use serde::Serialize;
+use std::{collections::HashMap, time::Duration};
+
+use super::{Task, dependency::calculate_dependency_levels, sort_topologically};
+use crate::logging;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct CriticalPath {
+    pub tasks: Vec<String>,
+    pub duration_ms: u64,
+    pub total_work_ms: u64,
+}
+
+impl CriticalPath {
+    /// Average number of tasks that could run at once if workers were unlimited.
+    pub fn parallelism(&self) -> f64 {
+        if self.duration_ms == 0 {
+            return 1.0;
+        }
+        self.total_work_ms as f64 / self.duration_ms as f64
+    }
+
+    /// Lower bound on wall time with the given number of workers.
+    pub fn estimated_wall_time(&self, workers: usize) -> Duration {
+        let per_worker = self.total_work_ms / workers.max(1) as u64;
+        Duration::from_millis(self.duration_ms.max(per_worker))
+    }
+}
+
+/// Finds the longest dependency chain through `tasks`, weighting each task by
+/// its duration. Tasks without a known duration count as zero.
+pub(crate) fn find_critical_path(
+    tasks: &[Task],
+    durations: &HashMap<String, Duration>,
+) -> CriticalPath {
+    let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
+    let weight = |id: &str| durations.get(id).map_or(0, |d| d.as_millis() as u64);
+
+    let mut finish: HashMap<&str, u64> = HashMap::new();
+    let mut predecessor: HashMap<&str, &str> = HashMap::new();
+
+    for task_id in sort_topologically(tasks) {
+        let Some(task) = task_map.get(task_id.as_str()) else {
+            continue;
+        };
+
+        let mut deps: Vec<&str> = task
+            .dependencies
+            .iter()
+            .map(String::as_str)
+            .filter(|dep| finish.contains_key(dep))
+            .collect();
+        deps.sort();
+
+        let mut start = 0;
+        let mut slowest_dep = None;
+        for dep in deps {
+            if slowest_dep.is_none() || finish[dep] > start {
+                start = finish[dep];
+                slowest_dep = Some(dep);
+            }
+        }
+        if let Some(dep) = slowest_dep {
+            predecessor.insert(&task.id, dep);
+        }
+
+        finish.insert(&task.id, start + weight(&task.id));
+    }
+
+    let mut ends: Vec<(&str, u64)> = finish.iter().map(|(id, f)| (*id, *f)).collect();
+    ends.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then(a_id.cmp(b_id)));
+
+    let Some(&(end, duration_ms)) = ends.first() else {
+        return CriticalPath::default();
+    };
+
+    let mut chain = vec![end.to_string()];
+    let mut current = end;
+    while let Some(prev) = predecessor.get(current) {
+        chain.push(prev.to_string());
+        current = prev;
+    }
+    chain.reverse();
+
+    CriticalPath {
+        tasks: chain,
+        duration_ms,
+        total_work_ms: tasks.iter().map(|t| weight(&t.id)).sum(),
+    }
+}
+
+/// Wall time of running `tasks` the way the runner does: level by level, with
+/// up to `workers` tasks of a level at once. Within a level the longest tasks
+/// are placed first, each on the worker that frees up soonest.
+pub fn estimate_run_time(
+    tasks: &[Task],
+    durations: &HashMap<String, Duration>,
+    workers: usize,
+) -> Duration {
+    let Ok(levels) = calculate_dependency_levels(tasks) else {
+        return Duration::ZERO;
+    };
+
+    let mut total = Duration::ZERO;
+    for level in levels {
+        let mut level_durations: Vec<Duration> = level
+            .task_ids
+            .iter()
+            .map(|id| durations.get(id).copied().unwrap_or_default())
+            .collect();
+        level_durations.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut lanes = vec![Duration::ZERO; workers.max(1)];
+        for duration in level_durations {
+            if let Some(lane) = lanes.iter_mut().min() {
+                *lane += duration;
+            }
+        }
+        total += lanes.into_iter().max().unwrap_or_default();
+    }
+    total
+}
+
+pub fn show_critical_path(
+    target: &str,
+    tasks: &[Task],
+    durations: &HashMap<String, Duration>,
+    workers: usize,
+) {
+    let path = find_critical_path(tasks, durations);
+
+    logging::info(format!(
+        "Critical path for '{}' (based on recorded durations):",
+        target
+    ));
+    for task_id in &path.tasks {
+        let duration = durations.get(task_id).copied().unwrap_or_default();
+        logging::info(format!(
+            "  {:<24} {:>8.2}s",
+            task_id,
+            duration.as_secs_f64()
+        ));
+    }
+
+    logging::info(format!(
+        "Critical path: {:.2}s",
+        path.duration_ms as f64 / 1000.0
+    ));
+    logging::info(format!(
+        "Total work: {:.2}s across {} tasks",
+        path.total_work_ms as f64 / 1000.0,
+        tasks.len()
+    ));
+    logging::info(format!(
+        "Average parallelism: {:.2} (configured workers: {})",
+        path.parallelism(),
+        workers
+    ));
+
+    let estimate = path.estimated_wall_time(workers);
+    let bound = if estimate.as_millis() as u64 == path.duration_ms {
+        "bounded by the critical path; more workers will not help"
+    } else {
+        "bounded by worker count; more workers would help"
+    };
+    logging::info(format!(
+        "Estimated wall time with {} workers: {:.2}s ({})",
+        workers,
+        estimate.as_secs_f64(),
+        bound
+    ));
+
+    let mut unknown: Vec<&str> = tasks
+        .iter()
+        .filter(|t| !durations.contains_key(&t.id))
+        .map(|t| t.id.as_str())
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        logging::info(format!("No recorded duration for: {}", unknown.join(", ")));
+    }
+}
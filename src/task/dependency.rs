@@ -1,8 +1,16 @@
-use std::collections::{hash_map::Entry::Occupied, HashMap, HashSet, VecDeque};
+use glob::Pattern;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque, hash_map::Entry::Occupied},
+    path::{Path, PathBuf},
+};
 
 use super::Task;
 use crate::error::{CompiError, Result};
+use crate::util::{exclusion, is_glob_pattern, patterns_match, strip_cur_dir};
 
+/// Orders tasks so that every task comes after its dependencies. Of the tasks
+/// whose dependencies are all placed, the one with the smallest id comes
+/// first, so the order is the same on every run.
 pub fn sort_topologically(tasks: &[Task]) -> Vec<String> {
     let mut in_degrees: HashMap<&str, usize> = HashMap::new();
 
@@ -10,16 +18,15 @@ pub fn sort_topologically(tasks: &[Task]) -> Vec<String> {
         in_degrees.insert(&task.id, task.dependencies.len());
     }
 
-    let mut queue: VecDeque<&str> = VecDeque::new();
-    for (task_id, &in_degree) in &in_degrees {
-        if in_degree == 0 {
-            queue.push_back(task_id);
-        }
-    }
+    let mut ready: BTreeSet<&str> = in_degrees
+        .iter()
+        .filter(|&(_, &in_degree)| in_degree == 0)
+        .map(|(&task_id, _)| task_id)
+        .collect();
 
     let mut sorted_tasks: Vec<String> = Vec::new();
 
-    while let Some(task_id) = queue.pop_front() {
+    while let Some(task_id) = ready.pop_first() {
         sorted_tasks.push(task_id.to_string());
 
         for dependent in tasks {
@@ -29,10 +36,10 @@ pub fn sort_topologically(tasks: &[Task]) -> Vec<String> {
 
             let entry = in_degrees.entry(&dependent.id).and_modify(|c| *c -= 1);
 
-            if let Occupied(entry) = entry {
-                if *entry.get() == 0 {
-                    queue.push_back(&dependent.id);
-                }
+            if let Occupied(entry) = entry
+                && *entry.get() == 0
+            {
+                ready.insert(&dependent.id);
             }
         }
     }
@@ -40,11 +47,179 @@ pub fn sort_topologically(tasks: &[Task]) -> Vec<String> {
     sorted_tasks
 }
 
-pub fn validate_tasks(tasks: &[Task]) -> Result<()> {
+#[derive(Debug)]
+pub(crate) struct ExecutionLevel {
+    pub level: usize,
+    pub task_ids: Vec<String>,
+}
+
+/// Groups tasks into levels where every task only depends on tasks in earlier levels.
+/// Each level lists its tasks by id.
+pub(crate) fn calculate_dependency_levels(tasks: &[Task]) -> Result<Vec<ExecutionLevel>> {
+    let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
+    let mut levels: HashMap<String, usize> = HashMap::new();
+
+    for task in tasks {
+        let mut visited = HashSet::new();
+        calculate_task_level(&task.id, &task_map, &mut levels, &mut visited)?;
+    }
+
+    let mut level_groups: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for (task_id, level) in levels {
+        level_groups.entry(level).or_default().push(task_id);
+    }
+
+    Ok(level_groups
+        .into_iter()
+        .map(|(level, mut task_ids)| {
+            task_ids.sort();
+            ExecutionLevel { level, task_ids }
+        })
+        .collect())
+}
+
+fn calculate_task_level(
+    task_id: &str,
+    task_map: &HashMap<&str, &Task>,
+    levels: &mut HashMap<String, usize>,
+    visited: &mut HashSet<String>,
+) -> Result<usize> {
+    if let Some(&level) = levels.get(task_id) {
+        return Ok(level);
+    }
+
+    if visited.contains(task_id) {
+        return Err(CompiError::Dependency(format!(
+            "Circular dependency detected involving task '{}'",
+            task_id
+        )));
+    }
+
+    let task = match task_map.get(task_id) {
+        Some(task) => task,
+        None => {
+            levels.insert(task_id.to_string(), 0);
+            return Ok(0);
+        }
+    };
+
+    if task.dependencies.is_empty() {
+        levels.insert(task_id.to_string(), 0);
+        return Ok(0);
+    }
+
+    visited.insert(task_id.to_string());
+
+    let mut max_dep_level = 0;
+    for dep in &task.dependencies {
+        let dep_level = calculate_task_level(dep, task_map, levels, visited)?;
+        max_dep_level = max_dep_level.max(dep_level);
+    }
+
+    visited.remove(task_id);
+
+    let level = max_dep_level + 1;
+    levels.insert(task_id.to_string(), level);
+    Ok(level)
+}
+
+/// Subcommands of the `compi` binary. `compi <name>` runs the subcommand, so
+/// no task or alias can have one of these names.
+pub const SUBCOMMANDS: [&str; 18] = [
+    "apply",
+    "bench",
+    "critical-path",
+    "daemon",
+    "doctor",
+    "env",
+    "exec",
+    "export",
+    "help",
+    "history",
+    "import",
+    "lint",
+    "list",
+    "migrate",
+    "plan",
+    "self-update",
+    "tree",
+    "why",
+];
+
+/// Marks a dependency on a file, e.g. `file:build/lib.a`, rather than on a task.
+pub(crate) const FILE_DEPENDENCY_PREFIX: &str = "file:";
+
+/// Replaces each `file:<path>` dependency with the task that declares `<path>`
+/// as an output, either directly, inside an output directory or through a glob.
+pub(crate) fn resolve_file_dependencies(tasks: &mut [Task]) -> Result<()> {
+    let producers: Vec<(String, Vec<PathBuf>)> = tasks
+        .iter()
+        .map(|t| (t.id.clone(), t.outputs.clone()))
+        .collect();
+
+    for task in tasks.iter_mut() {
+        if !task
+            .dependencies
+            .iter()
+            .any(|dep| dep.starts_with(FILE_DEPENDENCY_PREFIX))
+        {
+            continue;
+        }
+
+        let mut dependencies: Vec<String> = Vec::new();
+        for dep in std::mem::take(&mut task.dependencies) {
+            let dep = match dep.strip_prefix(FILE_DEPENDENCY_PREFIX) {
+                Some(file) => producer_of(&task.id, file, &producers)?,
+                None => dep,
+            };
+            if !dependencies.contains(&dep) {
+                dependencies.push(dep);
+            }
+        }
+        task.dependencies = dependencies;
+    }
+    Ok(())
+}
+
+fn producer_of(task_id: &str, file: &str, producers: &[(String, Vec<PathBuf>)]) -> Result<String> {
+    let path = strip_cur_dir(Path::new(file));
+    let matching: Vec<&str> = producers
+        .iter()
+        .filter(|(_, outputs)| patterns_match(outputs, &path))
+        .map(|(id, _)| id.as_str())
+        .collect();
+
+    match matching.as_slice() {
+        [producer] => Ok(producer.to_string()),
+        [] => Err(CompiError::Dependency(format!(
+            "Task '{}' depends on file '{}' which no task declares as an output",
+            task_id, file
+        ))),
+        several => Err(CompiError::Dependency(format!(
+            "Task '{}' depends on file '{}' which is an output of several tasks: {}",
+            task_id,
+            file,
+            several
+                .iter()
+                .map(|id| format!("'{}'", id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+pub(crate) fn validate_tasks(tasks: &[Task]) -> Result<()> {
     let task_ids: HashSet<&str> = tasks.iter().map(|t| t.id.as_str()).collect();
     let mut aliases: HashMap<&str, &str> = HashMap::new();
 
     for task in tasks {
+        if SUBCOMMANDS.contains(&task.id.as_str()) {
+            return Err(CompiError::Dependency(format!(
+                "Task '{}' has the name of the `compi {}` subcommand, which would run instead. Rename the task",
+                task.id, task.id
+            )));
+        }
+
         for dep_id in &task.dependencies {
             if dep_id == &task.id {
                 return Err(CompiError::Dependency(format!(
@@ -53,14 +228,33 @@ pub fn validate_tasks(tasks: &[Task]) -> Result<()> {
                 )));
             }
             if !task_ids.contains(dep_id.as_str()) {
+                let hint = did_you_mean(dep_id, tasks.iter().map(|t| t.id.as_str()))
+                    .map(|hint| format!(". {}", hint))
+                    .unwrap_or_default();
                 return Err(CompiError::Dependency(format!(
-                    "Task '{}' depends on '{}' which doesn't exist",
-                    task.id, dep_id
+                    "Task '{}' depends on '{}' which doesn't exist{}",
+                    task.id, dep_id, hint
                 )));
             }
         }
 
+        if let Some(producer) = &task.pipe_from
+            && !task.dependencies.contains(producer)
+        {
+            return Err(CompiError::Dependency(format!(
+                "Task '{}' pipes from '{}' which is not one of its dependencies",
+                task.id, producer
+            )));
+        }
+
         for alias in &task.aliases {
+            if SUBCOMMANDS.contains(&alias.as_str()) {
+                return Err(CompiError::Dependency(format!(
+                    "Task '{}' defines alias '{}' which is the name of the `compi {}` subcommand. Choose another alias",
+                    task.id, alias, alias
+                )));
+            }
+
             if task_ids.contains(alias.as_str()) {
                 return Err(CompiError::Dependency(format!(
                     "Task '{}' defines alias '{}' which conflicts with task ID '{}'",
@@ -80,9 +274,41 @@ pub fn validate_tasks(tasks: &[Task]) -> Result<()> {
     }
 
     detect_cycles(tasks)?;
+    detect_output_conflicts(tasks)?;
     Ok(())
 }
 
+/// Returns the execution order for `target` and its dependencies, or for every
+/// task when no target is given.
+pub fn resolve_task_list(tasks: &[Task], target: Option<&str>) -> Result<Vec<String>> {
+    match target {
+        Some(task_id) => get_required_tasks(tasks, task_id),
+        None => Ok(sort_topologically(tasks)),
+    }
+}
+
+/// Returns the execution order for every target and their dependencies, or
+/// for every task when there are no targets.
+pub fn resolve_targets(tasks: &[Task], targets: &[String]) -> Result<Vec<String>> {
+    if targets.is_empty() {
+        return Ok(sort_topologically(tasks));
+    }
+
+    let mut needed_tasks = HashSet::new();
+    for target in targets {
+        needed_tasks.extend(get_required_tasks(tasks, target)?);
+    }
+    let filtered_tasks: Vec<Task> = tasks
+        .iter()
+        .filter(|task| needed_tasks.contains(&task.id))
+        .cloned()
+        .collect();
+
+    Ok(sort_topologically(&filtered_tasks))
+}
+
+/// Returns `target_task_id`, or the task it is an alias of, and everything it
+/// depends on, in execution order.
 pub fn get_required_tasks(tasks: &[Task], target_task_id: &str) -> Result<Vec<String>> {
     let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
 
@@ -98,9 +324,20 @@ pub fn get_required_tasks(tasks: &[Task], target_task_id: &str) -> Result<Vec<St
                 resolved_id = &task.id;
             }
             None => {
+                let names = tasks
+                    .iter()
+                    .flat_map(|t| std::iter::once(&t.id).chain(&t.aliases))
+                    .map(String::as_str);
+                let mut ids: Vec<&str> = tasks.iter().map(|t| t.id.as_str()).collect();
+                ids.sort();
+                let hint = did_you_mean(target_task_id, names)
+                    .map(|hint| format!(" {}", hint))
+                    .unwrap_or_default();
                 return Err(CompiError::Task(format!(
-                    "Task '{}' not found",
-                    target_task_id
+                    "Task '{}' not found.{} Available tasks: {}",
+                    target_task_id,
+                    hint,
+                    ids.join(", ")
                 )));
             }
         }
@@ -136,6 +373,163 @@ pub fn get_required_tasks(tasks: &[Task], target_task_id: &str) -> Result<Vec<St
     Ok(sort_topologically(&filtered_tasks))
 }
 
+/// `Did you mean 'x'?` for the names closest to `name`, if any are close
+/// enough to be a likely typo.
+fn did_you_mean<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let threshold = (name.chars().count() / 3).max(2);
+    let mut close: Vec<(usize, &str)> = candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .collect();
+    close.sort();
+    close.dedup();
+
+    let names: Vec<String> = close
+        .iter()
+        .take(3)
+        .map(|(_, candidate)| format!("'{}'", candidate))
+        .collect();
+    match names.as_slice() {
+        [] => None,
+        [only] => Some(format!("Did you mean {}?", only)),
+        [rest @ .., last] => Some(format!("Did you mean {} or {}?", rest.join(", "), last)),
+    }
+}
+
+/// Levenshtein distance, counting a swap of adjacent characters as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<usize>>()];
+
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Tasks that may run at the same time, because neither depends on the
+/// other, may not declare the same output or an output inside the other's.
+fn detect_output_conflicts(tasks: &[Task]) -> Result<()> {
+    let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
+    let mut ancestors: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for task in tasks {
+        collect_ancestors(&task.id, &task_map, &mut ancestors);
+    }
+    let ordered = |a: &str, b: &str| ancestors[a].contains(b) || ancestors[b].contains(a);
+
+    for (index, task) in tasks.iter().enumerate() {
+        for other in tasks[index + 1..]
+            .iter()
+            .filter(|other| !ordered(&task.id, &other.id))
+        {
+            for output in task.outputs.iter().filter(|p| exclusion(p).is_none()) {
+                for other_output in other.outputs.iter().filter(|p| exclusion(p).is_none()) {
+                    if !outputs_overlap(output, other_output) {
+                        continue;
+                    }
+                    if output == other_output {
+                        return Err(CompiError::Dependency(format!(
+                            "Tasks '{}' and '{}' both write '{}'",
+                            task.id,
+                            other.id,
+                            output.display()
+                        )));
+                    }
+                    return Err(CompiError::Dependency(format!(
+                        "Tasks '{}' and '{}' have overlapping outputs '{}' and '{}'",
+                        task.id,
+                        other.id,
+                        output.display(),
+                        other_output.display()
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether two outputs are the same path or one lies inside the other. Globs
+/// are compared with literal paths by matching, and with each other by their
+/// literal directories and the text after their last wildcard.
+fn outputs_overlap(a: &Path, b: &Path) -> bool {
+    let (a, b) = (strip_cur_dir(a), strip_cur_dir(b));
+    if a == b {
+        return true;
+    }
+
+    let (a_str, b_str) = (a.to_string_lossy(), b.to_string_lossy());
+    match (is_glob_pattern(&a_str), is_glob_pattern(&b_str)) {
+        (true, true) => globs_overlap(&a, &b),
+        (true, false) => glob_overlaps(&a_str, &b),
+        (false, true) => glob_overlaps(&b_str, &a),
+        (false, false) => a.starts_with(&b) || b.starts_with(&a),
+    }
+}
+
+fn glob_overlaps(pattern: &str, path: &Path) -> bool {
+    Pattern::new(pattern).is_ok_and(|pattern| path.ancestors().any(|p| pattern.matches_path(p)))
+}
+
+/// Whether two globs may select the same file: one's literal directory lies
+/// inside the other's, and their endings, such as `.rs` in `src/*.rs`, agree.
+/// `gen/*.rs` and `gen/*.txt` do not overlap, while `gen/**` and
+/// `gen/sub/*.rs` do.
+fn globs_overlap(a: &Path, b: &Path) -> bool {
+    let literal_dir = |pattern: &Path| -> PathBuf {
+        pattern
+            .components()
+            .take_while(|c| !is_glob_pattern(&c.as_os_str().to_string_lossy()))
+            .collect()
+    };
+    let ending = |pattern: &Path| {
+        let pattern = pattern.to_string_lossy();
+        pattern
+            .rsplit(['*', '?', ']'])
+            .next()
+            .unwrap_or("")
+            .to_string()
+    };
+
+    let (a_dir, b_dir) = (literal_dir(a), literal_dir(b));
+    let (a_end, b_end) = (ending(a), ending(b));
+    (a_dir.starts_with(&b_dir) || b_dir.starts_with(&a_dir))
+        && (a_end.ends_with(&b_end) || b_end.ends_with(&a_end))
+}
+
+fn collect_ancestors<'a>(
+    task_id: &'a str,
+    task_map: &HashMap<&'a str, &'a Task>,
+    ancestors: &mut HashMap<&'a str, HashSet<&'a str>>,
+) {
+    if ancestors.contains_key(task_id) {
+        return;
+    }
+
+    let mut found = HashSet::new();
+    if let Some(task) = task_map.get(task_id) {
+        for dep in &task.dependencies {
+            collect_ancestors(dep, task_map, ancestors);
+            found.insert(dep.as_str());
+            if let Some(dep_ancestors) = ancestors.get(dep.as_str()) {
+                found.extend(dep_ancestors.iter().copied());
+            }
+        }
+    }
+    ancestors.insert(task_id, found);
+}
+
 fn detect_cycles(tasks: &[Task]) -> Result<()> {
     let task_map: HashMap<&str, &Task> = tasks.iter().map(|t| (t.id.as_str(), t)).collect();
 
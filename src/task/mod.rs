@@ -1,21 +1,43 @@
-pub mod analysis;
-pub mod config;
-pub mod dependency;
+mod analysis;
+mod config;
+mod critical_path;
+pub(crate) mod dependency;
+#[doc(hidden)]
+pub mod schema;
+pub(crate) mod template;
 
 pub use analysis::show_task_relationships;
-pub use config::load_tasks;
-pub use dependency::{get_required_tasks, sort_topologically};
+pub use config::{
+    LoadOptions, TaskConfiguration, TaskSettings, WorkspaceMember, load_overlaid_tasks, load_tasks,
+    load_tasks_with,
+};
+pub(crate) use critical_path::find_critical_path;
+pub use critical_path::{CriticalPath, estimate_run_time, show_critical_path};
+pub use dependency::{
+    SUBCOMMANDS, get_required_tasks, resolve_targets, resolve_task_list, sort_topologically,
+};
+pub use template::Templates;
 
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug, Deserialize, Clone)]
+use crate::error::{CompiError, Result};
+use crate::hooks::Hooks;
+use crate::util::{exclusion, path_list, resolve_path};
+
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct Task {
     #[serde(default)]
     pub id: String,
     pub command: String,
     #[serde(default)]
     pub aliases: Vec<String>,
+    /// Labels such as `network`, limited by `[config] tag_limits`.
+    #[serde(default)]
+    pub tags: Vec<String>,
     #[serde(default)]
     pub dependencies: Vec<String>,
     #[serde(default)]
@@ -28,4 +50,181 @@ pub struct Task {
     pub always_run: bool,
     #[serde(default)]
     pub timeout: Option<String>,
+    /// How long the command may take to exit after a timeout before it is killed.
+    #[serde(default)]
+    pub grace_period: Option<String>,
+    /// Parse the task's stdout as JSON describing more tasks to run.
+    #[serde(default)]
+    pub generator: bool,
+    /// Variable that receives the task's trimmed stdout for later tasks.
+    #[serde(default)]
+    pub capture: Option<String>,
+    /// Dependency whose stdout is written to this task's stdin.
+    #[serde(default)]
+    pub pipe_from: Option<String>,
+    /// Problem matchers run over the task's output, see [`crate::diagnostics`].
+    #[serde(default)]
+    pub problem_matchers: Vec<String>,
+    /// Fail instead of warning when a declared output is missing after success.
+    #[serde(default)]
+    pub strict_outputs: Option<bool>,
+    /// Question asked before a run that includes the task, answered with `y`
+    /// or `--yes`.
+    #[serde(default)]
+    pub confirm: Option<String>,
+    /// Cron expression saying when `compi daemon` runs the task, in UTC.
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Run the command in a temporary copy of its inputs, so that it only
+    /// sees the files it declares.
+    #[serde(default)]
+    pub sandbox: bool,
+    /// Command the shell is run through, e.g. `nix develop --command`. An empty
+    /// string disables `[config] wrapper` for this task.
+    #[serde(default)]
+    pub wrapper: Option<String>,
+    /// Template syntax of this task's fields, instead of `[config] templates`.
+    #[serde(default)]
+    pub templates: Option<Templates>,
+    /// Name of a plugin that runs this task instead of the shell.
+    #[serde(default)]
+    pub executor: Option<String>,
+    #[serde(flatten)]
+    pub hooks: Hooks,
+    /// Directory the command runs in, set for tasks of workspace members.
+    #[serde(skip)]
+    pub dir: Option<PathBuf>,
+    /// `@file` entries of `inputs` and `outputs`, read when the task is
+    /// scheduled, see [`Task::expand_path_lists`].
+    #[serde(skip)]
+    pub(crate) path_lists: Vec<PathList>,
+    /// Text hashed along with the inputs, so that ad-hoc commands sharing
+    /// inputs are not cached as one another.
+    #[serde(skip)]
+    pub cache_key: Option<String>,
+}
+
+/// An `@file` entry that lists more `inputs` or `outputs`.
+#[derive(Debug, Clone)]
+pub(crate) struct PathList {
+    outputs: bool,
+    /// Where the listed entries go, so that `!` entries around the list
+    /// apply as written.
+    index: usize,
+    path: PathBuf,
+}
+
+impl Task {
+    /// A task that runs `command`, with every other field unset, for tasks
+    /// that do not come from a config file.
+    pub fn new(id: impl Into<String>, command: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            command: command.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Makes `inputs`, `outputs` and `file:` dependencies relative to `dir`,
+    /// the directory of the config file that declared the task, and runs the
+    /// command there. `~` is expanded to the home directory. `@file` entries
+    /// are set aside in `path_lists`; an input list stays an input itself, so
+    /// the task reruns when the list changes.
+    pub fn resolve_paths(&mut self, dir: &Path) -> Result<()> {
+        for outputs in [false, true] {
+            let entries = match outputs {
+                false => std::mem::take(&mut self.inputs),
+                true => std::mem::take(&mut self.outputs),
+            };
+            let mut kept = Vec::new();
+            for entry in entries {
+                let Some(list) = path_list(&entry) else {
+                    kept.push(resolve_entry(&entry, dir));
+                    continue;
+                };
+                let path = resolve_path(&list, dir);
+                if !outputs {
+                    kept.push(path.clone());
+                }
+                self.path_lists.push(PathList {
+                    outputs,
+                    index: kept.len(),
+                    path,
+                });
+            }
+            match outputs {
+                false => self.inputs = kept,
+                true => self.outputs = kept,
+            }
+        }
+        for dep in &mut self.dependencies {
+            if let Some(file) = dep.strip_prefix(dependency::FILE_DEPENDENCY_PREFIX) {
+                *dep = format!(
+                    "{}{}",
+                    dependency::FILE_DEPENDENCY_PREFIX,
+                    resolve_path(Path::new(file), dir).display()
+                );
+            }
+        }
+        if !dir.as_os_str().is_empty() {
+            self.dir = Some(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Inserts the entries of each `@file` list into `inputs` or `outputs`,
+    /// one per line, skipping blank lines and `#` comments. A file that does
+    /// not exist yet lists nothing. Lists are read once, so this does nothing
+    /// when called again.
+    pub fn expand_path_lists(&mut self) -> Result<()> {
+        let dir = self.dir.clone().unwrap_or_default();
+        for list in std::mem::take(&mut self.path_lists).into_iter().rev() {
+            let field = if list.outputs { "outputs" } else { "inputs" };
+            let contents = match fs::read_to_string(&list.path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    return Err(CompiError::Parse(format!(
+                        "task '{}' {} list '{}' could not be read: {}",
+                        self.id,
+                        field,
+                        list.path.display(),
+                        e
+                    )));
+                }
+            };
+
+            let mut entries = Vec::new();
+            for line in contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            {
+                if line.starts_with('@') {
+                    return Err(CompiError::Parse(format!(
+                        "task '{}' {} list '{}' names another list '{}', lists cannot be nested",
+                        self.id,
+                        field,
+                        list.path.display(),
+                        line
+                    )));
+                }
+                entries.push(resolve_entry(Path::new(line), &dir));
+            }
+            let paths = match list.outputs {
+                false => &mut self.inputs,
+                true => &mut self.outputs,
+            };
+            paths.splice(list.index..list.index, entries);
+        }
+        Ok(())
+    }
+}
+
+/// `path` relative to `dir`, keeping the `!` of an exclusion.
+fn resolve_entry(path: &Path, dir: &Path) -> PathBuf {
+    match exclusion(path) {
+        Some(excluded) => PathBuf::from(format!("!{}", resolve_path(&excluded, dir).display())),
+        None => resolve_path(path, dir),
+    }
 }
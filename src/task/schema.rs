@@ -0,0 +1,69 @@
+use serde::de::DeserializeOwned;
+use std::fs;
+use toml_edit::DocumentMut;
+
+use crate::error::{CompiError, Result};
+
+/// The config version this compi reads and writes.
+pub const VERSION: i64 = 1;
+
+/// Parses a config file after checking that this compi understands its
+/// `version`. Configs without one predate versioning and read the same as
+/// version 1.
+pub fn parse<T: DeserializeOwned>(contents: &str, config_path: &str) -> Result<T> {
+    let table: toml::Table = toml::from_str(contents).map_err(|e| parse_error(config_path, e))?;
+    if let Some(version) = table.get("version") {
+        check_version(version.as_integer(), config_path)?;
+    }
+
+    toml::from_str(contents).map_err(|e| parse_error(config_path, e))
+}
+
+/// Rewrites the config file to the current version and returns a description
+/// of each change. Files that are already current are left untouched.
+pub fn migrate(config_path: &str) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(config_path)?;
+    let document = parse_document(&contents, config_path)?;
+
+    if let Some(version) = document.get("version") {
+        check_version(version.as_integer(), config_path)?;
+        return Ok(Vec::new());
+    }
+
+    let mut migrated = DocumentMut::new();
+    migrated.insert("version", toml_edit::value(VERSION));
+
+    fs::write(
+        config_path,
+        format!("{}\n{}", migrated, document.to_string().trim_start()),
+    )?;
+    Ok(vec![format!("added version = {}", VERSION)])
+}
+
+fn check_version(version: Option<i64>, config_path: &str) -> Result<()> {
+    match version {
+        Some(VERSION) => Ok(()),
+        Some(version) if version > VERSION => Err(CompiError::Parse(format!(
+            "'{}' is written for config version {}, but compi {} only reads version {}. Upgrade compi to load it",
+            config_path,
+            version,
+            env!("CARGO_PKG_VERSION"),
+            VERSION
+        ))),
+        _ => Err(CompiError::Parse(format!(
+            "'{}' has an invalid version, expected version = {}",
+            config_path, VERSION
+        ))),
+    }
+}
+
+fn parse_document(contents: &str, config_path: &str) -> Result<DocumentMut> {
+    contents.parse().map_err(|e| parse_error(config_path, e))
+}
+
+fn parse_error(config_path: &str, e: impl std::fmt::Display) -> CompiError {
+    CompiError::Parse(format!(
+        "failed to parse config file '{}': {}",
+        config_path, e
+    ))
+}
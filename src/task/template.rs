@@ -0,0 +1,394 @@
+use minijinja::{Environment, ErrorKind, UndefinedBehavior};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::OnceLock,
+};
+
+use crate::error::{CompiError, Result};
+
+static REFERENCE: OnceLock<Regex> = OnceLock::new();
+
+/// Which template syntax config values use besides `${NAME}`, set with
+/// `templates` in `[config]` or on a task.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Templates {
+    /// `{{`, `{%` and `{#` are kept as written, as the shell, awk or Go
+    /// templates expect.
+    #[default]
+    None,
+    /// `{{ ... }}` and `{% ... %}` are rendered with MiniJinja.
+    Jinja,
+}
+
+/// Names that `text` refers to with `${NAME}` or `$NAME`, or in a template
+/// when `templates` is [`Templates::Jinja`]. Escaped `$${NAME}` references
+/// are not included.
+pub(crate) fn references(text: &str, templates: Templates) -> HashSet<String> {
+    let mut names: HashSet<String> = reference()
+        .captures_iter(text)
+        .filter_map(|caps| caps.name("name").or(caps.name("bare")))
+        .map(|name| name.as_str().to_string())
+        .collect();
+    if templates == Templates::Jinja
+        && has_template_tags(text)
+        && let Ok(template) = Environment::new().template_from_str(text)
+    {
+        names.extend(template.undeclared_variables(false));
+    }
+    names
+}
+
+/// Renders config values against a set of variables, optionally rejecting
+/// references to variables that are not defined.
+#[derive(Clone)]
+pub(crate) struct Renderer<'a> {
+    variables: &'a HashMap<String, String>,
+    strict: bool,
+    templates: Templates,
+    runtime: HashSet<String>,
+    deferred: HashSet<String>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(variables: &'a HashMap<String, String>) -> Self {
+        Self {
+            variables,
+            strict: false,
+            templates: Templates::None,
+            runtime: HashSet::new(),
+            deferred: HashSet::new(),
+        }
+    }
+
+    /// Leaves `${NAME@q}` references to `names`, and `$${NAME}` escapes of
+    /// them, in place for [`substitute_variables`] when commands run, so that
+    /// an escaped reference is not substituted there.
+    pub fn defer(mut self, names: HashSet<String>) -> Self {
+        self.deferred = names;
+        self
+    }
+
+    /// Renders `{{ ... }}` and `{% ... %}` before substituting references
+    /// when `templates` is [`Templates::Jinja`].
+    pub fn templates(mut self, templates: Templates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Fails on undefined `${NAME}` and `{{ NAME }}` references. Names in
+    /// `runtime` are set when commands run, as are variables in compi's
+    /// environment and the `DEP_<id>_OUTPUTS` and `COMPI_*` variables.
+    pub fn strict(mut self, runtime: HashSet<String>) -> Self {
+        self.strict = true;
+        self.runtime = runtime;
+        self
+    }
+
+    /// Renders a value that is not run by the shell, where `${NAME@q}` and
+    /// the `quote` filter are rejected.
+    pub fn render(&self, text: &str) -> Result<String> {
+        self.render_as(text, false)
+    }
+
+    /// Renders a command, where `${NAME@q}` and the `quote` filter quote
+    /// values for the shell that runs it.
+    pub fn render_command(&self, text: &str) -> Result<String> {
+        self.render_as(text, true)
+    }
+
+    fn render_as(&self, text: &str, command: bool) -> Result<String> {
+        let rendered = if self.templates == Templates::Jinja && has_template_tags(text) {
+            self.render_template(text, command)?
+        } else {
+            text.to_string()
+        };
+
+        if !command
+            && let Some(caps) = reference()
+                .captures_iter(&rendered)
+                .find(|caps| caps.name("quote").is_some())
+        {
+            return Err(CompiError::Parse(format!(
+                "'{}' quotes for the shell, so it is only allowed in commands",
+                &caps[0]
+            )));
+        }
+
+        if self.strict
+            && let Some(name) = self.undefined_references(&rendered).into_iter().next()
+        {
+            return Err(CompiError::Parse(format!("undefined variable '{}'", name)));
+        }
+        Ok(substitute(&rendered, self.variables, &self.deferred))
+    }
+
+    fn render_template(&self, text: &str, command: bool) -> Result<String> {
+        let mut env = Environment::new();
+        env.set_keep_trailing_newline(true);
+        env.add_filter("quote", move |value: String| {
+            if command {
+                Ok(quote(&value))
+            } else {
+                Err(minijinja::Error::new(
+                    ErrorKind::InvalidOperation,
+                    "quote is only allowed in commands",
+                ))
+            }
+        });
+        if self.strict {
+            env.set_undefined_behavior(UndefinedBehavior::Strict);
+        }
+
+        env.render_str(text, self.variables).map_err(|e| {
+            if e.kind() == ErrorKind::UndefinedError
+                && let Some(name) = self.undefined_template_variable(&env, text)
+            {
+                return CompiError::Parse(format!("undefined variable '{}'", name));
+            }
+            if e.kind() == ErrorKind::InvalidOperation {
+                return CompiError::Parse(format!("invalid template '{}': {}", text, e));
+            }
+            CompiError::Parse(format!(
+                "invalid template '{}': {}. Wrap literal braces in {{% raw %}}...{{% endraw %}}",
+                text, e
+            ))
+        })
+    }
+
+    /// The undefined name that makes rendering fail, found by defining every
+    /// other candidate, since names used with `default` are undeclared too.
+    fn undefined_template_variable(&self, env: &Environment, text: &str) -> Option<String> {
+        let template = env.template_from_str(text).ok()?;
+        let mut candidates: Vec<String> = template
+            .undeclared_variables(false)
+            .into_iter()
+            .filter(|name| !self.variables.contains_key(name))
+            .collect();
+        candidates.sort();
+
+        candidates.iter().find_map(|name| {
+            let mut context = self.variables.clone();
+            for other in candidates.iter().filter(|other| *other != name) {
+                context.insert(other.clone(), String::new());
+            }
+            match template.render(&context) {
+                Err(e) if e.kind() == ErrorKind::UndefinedError => Some(name.clone()),
+                _ => None,
+            }
+        })
+    }
+
+    /// `${NAME}` references without a default that nothing defines.
+    fn undefined_references(&self, text: &str) -> Vec<String> {
+        reference()
+            .captures_iter(text)
+            .filter(|caps| caps.name("default").is_none())
+            .filter_map(|caps| caps.name("name"))
+            .map(|name| name.as_str().to_string())
+            .filter(|name| !self.is_defined(name))
+            .collect()
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.variables.contains_key(name)
+            || self.runtime.contains(name)
+            || env::var_os(name).is_some()
+            || name.starts_with("COMPI_")
+            || (name.starts_with("DEP_") && name.ends_with("_OUTPUTS"))
+    }
+}
+
+fn has_template_tags(text: &str) -> bool {
+    ["{{", "{%", "{#"].iter().any(|tag| text.contains(tag))
+}
+
+fn reference() -> &'static Regex {
+    REFERENCE.get_or_init(|| {
+        Regex::new(concat!(
+            r"\$\$\{(?P<escaped>[A-Za-z_][A-Za-z0-9_]*)?",
+            r"|\$\{(?P<name>[A-Za-z_][A-Za-z0-9_]*)(?:(?P<quote>@q)|:-(?P<default>[^}]*))?\}",
+            r"|\$(?P<bare>[A-Za-z_][A-Za-z0-9_]*)\b",
+        ))
+        .unwrap()
+    })
+}
+
+/// Replaces `${NAME}`, `${NAME:-default}`, `${NAME@q}` and `$NAME` in one
+/// pass, so values are never substituted again. As in the shell, the default
+/// is also used for empty values. `@q` quotes the value with [`quote`], and
+/// for unknown names becomes `"${NAME}"` (`"%NAME%"` on Windows) so the
+/// shell's expansion is not split either. Other unknown names are left for
+/// the shell, and `$${NAME}` is written out as `${NAME}`, except for deferred
+/// names, whose escape is undone by the pass that substitutes them.
+pub(crate) fn substitute_variables(text: &str, variables: &HashMap<String, String>) -> String {
+    substitute(text, variables, &HashSet::new())
+}
+
+fn substitute(
+    text: &str,
+    variables: &HashMap<String, String>,
+    deferred: &HashSet<String>,
+) -> String {
+    reference()
+        .replace_all(text, |caps: &Captures| {
+            let Some(name) = caps.name("name").or_else(|| caps.name("bare")) else {
+                return match caps.name("escaped") {
+                    Some(escaped) if deferred.contains(escaped.as_str()) => caps[0].to_string(),
+                    Some(escaped) => format!("${{{}", escaped.as_str()),
+                    None => "${".to_string(),
+                };
+            };
+            let value = variables.get(name.as_str());
+
+            if caps.name("quote").is_some() {
+                return match value {
+                    Some(value) => quote(value),
+                    None if deferred.contains(name.as_str()) => caps[0].to_string(),
+                    None if cfg!(windows) => format!("\"%{}%\"", name.as_str()),
+                    None => format!("\"${{{}}}\"", name.as_str()),
+                };
+            }
+
+            match (value, caps.name("default")) {
+                (Some(value), Some(default)) if value.is_empty() => default.as_str().to_string(),
+                (Some(value), _) => value.clone(),
+                (None, Some(default)) => default.as_str().to_string(),
+                (None, None) => caps[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+/// Quotes `value` as one argument for the shell commands run in: `sh` on
+/// Unix, and `cmd` on Windows.
+pub(crate) fn quote(value: &str) -> String {
+    if cfg!(windows) {
+        quote_cmd(value)
+    } else {
+        shell_words::quote(value).into_owned()
+    }
+}
+
+/// Double-quotes `value` the way programs split their command line on
+/// Windows. `cmd` still expands `%NAME%` inside quotes.
+fn quote_cmd(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in value.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Backslashes before a quote escape each other, then the quote.
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    // Backslashes before the closing quote would escape it.
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> HashMap<String, String> {
+        HashMap::from([
+            ("NAME".to_string(), "a b".to_string()),
+            ("EMPTY".to_string(), String::new()),
+        ])
+    }
+
+    #[test]
+    fn substitutes_references_once() {
+        let variables = HashMap::from([("A".to_string(), "${B}".to_string())]);
+        assert_eq!(substitute_variables("${A} $A", &variables), "${B} ${B}");
+    }
+
+    #[test]
+    fn defaults_apply_to_unset_and_empty_values() {
+        let variables = variables();
+        assert_eq!(substitute_variables("${UNSET:-d}", &variables), "d");
+        assert_eq!(substitute_variables("${EMPTY:-d}", &variables), "d");
+        assert_eq!(substitute_variables("${NAME:-d}", &variables), "a b");
+    }
+
+    #[test]
+    fn unknown_names_are_left_for_the_shell() {
+        assert_eq!(
+            substitute_variables("${UNSET} $UNSET", &variables()),
+            "${UNSET} $UNSET"
+        );
+    }
+
+    #[test]
+    fn escapes_are_written_out_once() {
+        let variables = variables();
+        assert_eq!(substitute_variables("$${NAME}", &variables), "${NAME}");
+        assert_eq!(
+            substitute_variables("$${#arr[@]}", &variables),
+            "${#arr[@]}"
+        );
+    }
+
+    #[test]
+    fn escapes_of_deferred_names_are_kept() {
+        let deferred = HashSet::from(["CAPTURED".to_string()]);
+        assert_eq!(
+            substitute("$${CAPTURED} ${CAPTURED@q}", &variables(), &deferred),
+            "$${CAPTURED} ${CAPTURED@q}"
+        );
+    }
+
+    #[test]
+    fn quote_modifier_quotes_values() {
+        let variables = variables();
+        assert_eq!(substitute_variables("${NAME@q}", &variables), quote("a b"));
+        let unknown = if cfg!(windows) {
+            "\"%UNSET%\""
+        } else {
+            "\"${UNSET}\""
+        };
+        assert_eq!(substitute_variables("${UNSET@q}", &variables), unknown);
+    }
+
+    #[test]
+    fn quote_modifier_is_only_allowed_in_commands() {
+        let variables = variables();
+        let renderer = Renderer::new(&variables);
+        assert!(renderer.render("${NAME@q}").is_err());
+        assert_eq!(
+            renderer.render_command("echo ${NAME@q}").unwrap(),
+            format!("echo {}", quote("a b"))
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn quotes_for_sh() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn quotes_for_cmd() {
+        assert_eq!(quote_cmd("a b"), "\"a b\"");
+        assert_eq!(quote_cmd(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_cmd(r"C:\dir\"), r#""C:\dir\\""#);
+        assert_eq!(quote_cmd(r#"a\"b"#), r#""a\\\"b""#);
+    }
+}
@@ -0,0 +1,196 @@
+use serde_json::{Value, json};
+use std::{
+    env, process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::{CompiError, Result};
+use crate::execution::{RunSummary, TaskResult, TaskStatus};
+
+const TRACES_PATH: &str = "/v1/traces";
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SPAN_KIND_INTERNAL: u8 = 1;
+const STATUS_CODE_OK: u8 = 1;
+const STATUS_CODE_ERROR: u8 = 2;
+
+#[derive(Clone, Debug)]
+pub struct OtlpExporter {
+    endpoint: String,
+    headers: Vec<(String, String)>,
+    service_name: String,
+}
+
+/// Builds an exporter from the standard `OTEL_*` environment variables,
+/// falling back to the `[config] otel` endpoint. Returns `None` when neither is set.
+pub fn exporter_from_env(config_endpoint: Option<&str>) -> Option<OtlpExporter> {
+    let endpoint = match env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+        Ok(url) if !url.is_empty() => url,
+        _ => {
+            let base = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+                .ok()
+                .filter(|url| !url.is_empty())
+                .or_else(|| config_endpoint.map(str::to_string))?;
+            format!("{}{}", base.trim_end_matches('/'), TRACES_PATH)
+        }
+    };
+
+    let headers = env::var("OTEL_EXPORTER_OTLP_HEADERS")
+        .map(|raw| parse_headers(&raw))
+        .unwrap_or_default();
+
+    let service_name = env::var("OTEL_SERVICE_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "compi".to_string());
+
+    Some(OtlpExporter {
+        endpoint,
+        headers,
+        service_name,
+    })
+}
+
+fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    raw.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+pub fn export_run(
+    exporter: &OtlpExporter,
+    target: &str,
+    results: &[TaskResult],
+    summary: &RunSummary,
+) -> Result<()> {
+    let payload = build_payload(exporter, target, results, summary);
+
+    let mut request = ureq::post(&exporter.endpoint)
+        .timeout(EXPORT_TIMEOUT)
+        .set("Content-Type", "application/json");
+    for (key, value) in &exporter.headers {
+        request = request.set(key, value);
+    }
+
+    request.send_string(&payload.to_string()).map_err(|e| {
+        CompiError::Http(format!(
+            "failed to export spans to '{}': {}",
+            exporter.endpoint, e
+        ))
+    })?;
+
+    Ok(())
+}
+
+fn build_payload(
+    exporter: &OtlpExporter,
+    target: &str,
+    results: &[TaskResult],
+    summary: &RunSummary,
+) -> Value {
+    let trace_id = random_id(16);
+    let root_span_id = random_id(8);
+
+    let run_end = SystemTime::now();
+    let run_start = run_end
+        .checked_sub(Duration::from_millis(summary.duration_ms as u64))
+        .unwrap_or(UNIX_EPOCH);
+
+    let mut spans = vec![json!({
+        "traceId": trace_id,
+        "spanId": root_span_id,
+        "name": "compi run",
+        "kind": SPAN_KIND_INTERNAL,
+        "startTimeUnixNano": unix_nanos(run_start),
+        "endTimeUnixNano": unix_nanos(run_end),
+        "attributes": [
+            attribute("compi.target", json!({ "stringValue": target })),
+            attribute("compi.tasks.executed", int_value(summary.executed as i64)),
+            attribute("compi.tasks.skipped", int_value(summary.skipped as i64)),
+            attribute("compi.tasks.failed", int_value(summary.failed as i64)),
+        ],
+        "status": status(summary.failed == 0),
+    })];
+
+    for result in results {
+        let mut attributes = vec![
+            attribute("compi.task.id", json!({ "stringValue": result.task_id })),
+            attribute(
+                "compi.task.cached",
+                json!({ "boolValue": result.status == TaskStatus::Skipped }),
+            ),
+            attribute(
+                "compi.task.duration_ms",
+                int_value(result.duration.as_millis() as i64),
+            ),
+        ];
+        if let Some(code) = result.exit_code {
+            attributes.push(attribute("compi.task.exit_code", int_value(code as i64)));
+        }
+
+        let end = result.started_at + result.duration;
+        spans.push(json!({
+            "traceId": trace_id,
+            "spanId": random_id(8),
+            "parentSpanId": root_span_id,
+            "name": result.task_id,
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": unix_nanos(result.started_at),
+            "endTimeUnixNano": unix_nanos(end),
+            "attributes": attributes,
+            "status": status(matches!(
+                result.status,
+                TaskStatus::Success | TaskStatus::Skipped
+            )),
+        }));
+    }
+
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    attribute("service.name", json!({ "stringValue": exporter.service_name })),
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "compi", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn int_value(value: i64) -> Value {
+    json!({ "intValue": value.to_string() })
+}
+
+fn status(ok: bool) -> Value {
+    json!({ "code": if ok { STATUS_CODE_OK } else { STATUS_CODE_ERROR } })
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn random_id(bytes: usize) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let seed = format!(
+        "{}:{}:{}",
+        unix_nanos(SystemTime::now()),
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let hash = blake3::hash(seed.as_bytes());
+    hash.to_hex()[..bytes * 2].to_string()
+}
@@ -0,0 +1,122 @@
+use serde_json::{Value, json};
+use std::{
+    fs,
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+use crate::error::{CompiError, Result};
+
+pub const SCHEDULER_LANE: usize = 0;
+
+const PROCESS_ID: u32 = 1;
+
+struct TraceRecorder {
+    origin: Instant,
+    events: Vec<Value>,
+    next_async_id: u64,
+}
+
+static TRACE: OnceLock<Mutex<TraceRecorder>> = OnceLock::new();
+
+pub fn enable() {
+    let _ = TRACE.set(Mutex::new(TraceRecorder {
+        origin: Instant::now(),
+        events: Vec::new(),
+        next_async_id: 0,
+    }));
+}
+
+pub fn span(name: &str, category: &str, lane: usize, start: Instant, end: Instant, args: Value) {
+    let Some(trace) = TRACE.get() else {
+        return;
+    };
+    let mut trace = trace.lock().unwrap();
+
+    let ts = micros_since(trace.origin, start);
+    let dur = end.saturating_duration_since(start).as_micros();
+    trace.events.push(json!({
+        "name": name,
+        "cat": category,
+        "ph": "X",
+        "ts": ts,
+        "dur": dur,
+        "pid": PROCESS_ID,
+        "tid": lane,
+        "args": args,
+    }));
+}
+
+pub fn queue_span(task_id: &str, queued_at: Instant, started_at: Instant) {
+    let Some(trace) = TRACE.get() else {
+        return;
+    };
+    let mut trace = trace.lock().unwrap();
+
+    let id = trace.next_async_id;
+    trace.next_async_id += 1;
+
+    let begin = micros_since(trace.origin, queued_at);
+    let end = micros_since(trace.origin, started_at);
+    for (phase, ts) in [("b", begin), ("e", end)] {
+        trace.events.push(json!({
+            "name": task_id,
+            "cat": "queue",
+            "ph": phase,
+            "id": id,
+            "ts": ts,
+            "pid": PROCESS_ID,
+            "tid": SCHEDULER_LANE,
+        }));
+    }
+}
+
+pub fn write(path: &Path, workers: usize) -> Result<()> {
+    let Some(trace) = TRACE.get() else {
+        return Ok(());
+    };
+    let trace = trace.lock().unwrap();
+
+    let mut events = vec![json!({
+        "name": "process_name",
+        "ph": "M",
+        "pid": PROCESS_ID,
+        "args": { "name": "compi" },
+    })];
+
+    for lane in 0..=workers {
+        let name = if lane == SCHEDULER_LANE {
+            "scheduler".to_string()
+        } else {
+            format!("worker {}", lane)
+        };
+        events.push(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": PROCESS_ID,
+            "tid": lane,
+            "args": { "name": name },
+        }));
+    }
+
+    events.extend(trace.events.iter().cloned());
+
+    let contents = json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+    });
+
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, contents.to_string())
+        .map_err(|e| CompiError::Task(format!("failed to write trace '{}': {}", path.display(), e)))
+}
+
+fn micros_since(origin: Instant, instant: Instant) -> u128 {
+    instant.saturating_duration_since(origin).as_micros()
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write as _,
+    time::SystemTime,
+};
+
+use crate::history::History;
+use crate::logging::{self, Style};
+use crate::plan::PlannedTask;
+use crate::task::Task;
+
+/// Prefixes for a dependency, its last sibling, and the lines below them.
+struct Branches {
+    item: &'static str,
+    last: &'static str,
+    line: &'static str,
+    blank: &'static str,
+}
+
+const UNICODE: Branches = Branches {
+    item: "├── ",
+    last: "└── ",
+    line: "│   ",
+    blank: "    ",
+};
+
+const ASCII: Branches = Branches {
+    item: "|-- ",
+    last: "`-- ",
+    line: "|   ",
+    blank: "    ",
+};
+
+struct Tree<'a> {
+    tasks: HashMap<&'a str, &'a Task>,
+    planned: HashMap<&'a str, &'a PlannedTask>,
+    history: &'a History,
+    branches: &'a Branches,
+    shown: HashSet<&'a str>,
+    text: String,
+}
+
+/// Draws the dependencies of each of `targets`, or of every task nothing
+/// else depends on when `targets` is empty, with whether each task is cached
+/// or stale and its most recent result. A task already drawn is marked
+/// with `(*)` instead of repeating its dependencies.
+pub fn render(
+    tasks: &[Task],
+    targets: &[String],
+    planned: &[PlannedTask],
+    history: &History,
+    ascii: bool,
+) -> String {
+    let mut tree = Tree {
+        tasks: tasks.iter().map(|t| (t.id.as_str(), t)).collect(),
+        planned: planned.iter().map(|t| (t.id.as_str(), t)).collect(),
+        history,
+        branches: if ascii { &ASCII } else { &UNICODE },
+        shown: HashSet::new(),
+        text: String::new(),
+    };
+
+    for root in roots(tasks, targets, planned) {
+        tree.task(root, "", "");
+    }
+    tree.text
+}
+
+/// Tasks the tree starts from, with aliases resolved.
+fn roots<'a>(tasks: &'a [Task], targets: &[String], planned: &[PlannedTask]) -> Vec<&'a str> {
+    if targets.is_empty() {
+        let listed: HashSet<&str> = planned.iter().map(|t| t.id.as_str()).collect();
+        let depended_on: HashSet<&str> = planned
+            .iter()
+            .flat_map(|t| &t.dependencies)
+            .map(String::as_str)
+            .collect();
+        return tasks
+            .iter()
+            .map(|t| t.id.as_str())
+            .filter(|id| listed.contains(id) && !depended_on.contains(id))
+            .collect();
+    }
+
+    targets
+        .iter()
+        .filter_map(|target| {
+            tasks
+                .iter()
+                .find(|t| &t.id == target)
+                .or_else(|| tasks.iter().find(|t| t.aliases.contains(target)))
+        })
+        .map(|t| t.id.as_str())
+        .collect()
+}
+
+impl<'a> Tree<'a> {
+    fn task(&mut self, id: &'a str, prefix: &str, children_prefix: &str) {
+        let Some(task) = self.tasks.get(id).copied() else {
+            return;
+        };
+        let repeated = !task.dependencies.is_empty() && !self.shown.insert(id);
+        let _ = writeln!(
+            self.text,
+            "{}{} ({}){}",
+            prefix,
+            logging::paint(id, Style::TaskId),
+            self.annotation(id),
+            if repeated { " (*)" } else { "" }
+        );
+        if repeated {
+            return;
+        }
+
+        let count = task.dependencies.len();
+        for (index, dep) in task.dependencies.iter().enumerate() {
+            let (branch, below) = if index + 1 == count {
+                (self.branches.last, self.branches.blank)
+            } else {
+                (self.branches.item, self.branches.line)
+            };
+            self.task(
+                dep,
+                &format!("{}{}", children_prefix, branch),
+                &format!("{}{}", children_prefix, below),
+            );
+        }
+    }
+
+    fn annotation(&self, id: &str) -> String {
+        let status = match self.planned.get(id) {
+            Some(planned) if planned.run => format!(
+                "{}: {}",
+                logging::paint("stale", Style::Skipped),
+                planned.reason
+            ),
+            Some(_) => logging::paint("cached", Style::Ok),
+            None => "not planned".to_string(),
+        };
+        match self.history.last_result(id, SystemTime::now()) {
+            Some(last) => format!("{}; {}", status, last),
+            None => status,
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::error::{CompiError, Result};
+use crate::logging;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest release binary that is downloaded.
+const MAX_DOWNLOAD_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+/// Replaces the running binary with the latest GitHub release, once it
+/// matches the SHA-256 checksum published with it. Releases are not signed:
+/// the checksum comes from the same release over HTTPS, so it only catches
+/// corrupted downloads. With `check`, only reports whether a newer release
+/// exists, and fails if one does.
+pub fn self_update(check: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: Release = serde_json::from_str(&get_text(&latest_release_url())?)
+        .map_err(|e| CompiError::Parse(format!("Unexpected release information: {}", e)))?;
+    let latest = release.tag_name.trim_start_matches('v');
+
+    if !is_newer(latest, current) {
+        logging::info(format!("compi {} is up to date", current));
+        return Ok(());
+    }
+    if check {
+        return Err(CompiError::Task(format!(
+            "compi {} is available, this is {}. Run `compi self-update` to install it",
+            latest, current
+        )));
+    }
+
+    let name = asset_name().ok_or_else(|| {
+        CompiError::Task(format!(
+            "No prebuilt binary is published for {} {}, install compi with `cargo install compi`",
+            env::consts::OS,
+            env::consts::ARCH
+        ))
+    })?;
+    let name = name.as_str();
+    let missing = |what: &str| {
+        CompiError::Task(format!(
+            "Release {} has no {}, download it from {}",
+            release.tag_name, what, release.html_url
+        ))
+    };
+    let asset = release.asset(name).ok_or_else(|| missing(name))?;
+    let checksum_name = format!("{}.sha256", name);
+    let checksum_asset = release
+        .asset(&checksum_name)
+        .ok_or_else(|| missing(&format!("checksum for {}", name)))?;
+
+    let checksum = get_text(&checksum_asset.browser_download_url)?;
+    let expected = checksum
+        .split_whitespace()
+        .next()
+        .filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| CompiError::Parse(format!("Invalid checksum file '{}'", checksum_name)))?;
+
+    logging::info(format!("Downloading compi {}...", latest));
+    let binary = get_bytes(&asset.browser_download_url)?;
+    let actual = format!("{:x}", Sha256::digest(&binary));
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(CompiError::Task(format!(
+            "Checksum mismatch for {}: expected {}, got {}. compi was not updated",
+            name, expected, actual
+        )));
+    }
+
+    let path = replace_executable(&binary)?;
+    logging::info(format!(
+        "Updated {} from {} to {}",
+        path.display(),
+        current,
+        latest
+    ));
+    Ok(())
+}
+
+/// Name of the release asset built for this platform, e.g. `compi-linux-x86_64`.
+fn asset_name() -> Option<String> {
+    let extension = match env::consts::OS {
+        "linux" | "macos" => "",
+        "windows" => ".exe",
+        _ => return None,
+    };
+    match env::consts::ARCH {
+        "x86_64" | "aarch64" => Some(format!(
+            "compi-{}-{}{}",
+            env::consts::OS,
+            env::consts::ARCH,
+            extension
+        )),
+        _ => None,
+    }
+}
+
+fn latest_release_url() -> String {
+    let repository = env!("CARGO_PKG_REPOSITORY").trim_start_matches("https://github.com/");
+    format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        repository
+    )
+}
+
+fn is_newer(latest: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Option<Vec<u64>> {
+        version
+            .split(['-', '+'])
+            .next()?
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect()
+    };
+    match (parse(latest), parse(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => latest != current,
+    }
+}
+
+fn get(url: &str) -> Result<ureq::Response> {
+    let mut request = ureq::get(url).timeout(HTTP_TIMEOUT);
+    // Raises the API's rate limit, which CI runners share.
+    if url.starts_with("https://api.github.com/")
+        && let Ok(token) = env::var("GITHUB_TOKEN")
+        && !token.is_empty()
+    {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+
+    request.call().map_err(|e| match e {
+        ureq::Error::Status(code, _) => {
+            CompiError::Http(format!("{} responded with status {}", url, code))
+        }
+        ureq::Error::Transport(transport) => {
+            CompiError::Http(format!("request to {} failed: {}", url, transport))
+        }
+    })
+}
+
+fn get_text(url: &str) -> Result<String> {
+    get(url)?
+        .into_string()
+        .map_err(|e| CompiError::Http(format!("failed to read {}: {}", url, e)))
+}
+
+fn get_bytes(url: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    get(url)?
+        .into_reader()
+        .take(MAX_DOWNLOAD_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|e| CompiError::Http(format!("failed to download {}: {}", url, e)))?;
+    Ok(bytes)
+}
+
+/// Writes `binary` next to the running executable, then moves it into place.
+/// Windows cannot overwrite a running executable, so there the old one is
+/// renamed out of the way first, and back if the new one cannot take its place.
+fn replace_executable(binary: &[u8]) -> Result<PathBuf> {
+    let path = env::current_exe()?;
+    let path = fs::canonicalize(&path).unwrap_or(path);
+    let staged = sibling(&path, "new");
+
+    let cannot_write = |e: io::Error| {
+        CompiError::Task(format!(
+            "Cannot replace '{}': {}. Run compi self-update as a user that can write to it",
+            path.display(),
+            e
+        ))
+    };
+    fs::write(&staged, binary).map_err(cannot_write)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path)
+            .map(|m| m.permissions().mode())
+            .unwrap_or(0o755);
+        fs::set_permissions(&staged, fs::Permissions::from_mode(mode)).map_err(cannot_write)?;
+    }
+    #[cfg(windows)]
+    let old = sibling(&path, "old");
+    #[cfg(windows)]
+    {
+        let _ = fs::remove_file(&old);
+        if let Err(e) = fs::rename(&path, &old) {
+            let _ = fs::remove_file(&staged);
+            return Err(cannot_write(e));
+        }
+    }
+
+    if let Err(e) = fs::rename(&staged, &path) {
+        let _ = fs::remove_file(&staged);
+        #[cfg(windows)]
+        {
+            if let Err(restore) = fs::rename(&old, &path) {
+                return Err(CompiError::Task(format!(
+                    "Cannot replace '{}': {}, and restoring it from '{}' failed: {}",
+                    path.display(),
+                    e,
+                    old.display(),
+                    restore
+                )));
+            }
+        }
+        return Err(cannot_write(e));
+    }
+    Ok(path)
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", suffix));
+    path.with_file_name(name)
+}
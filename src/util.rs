@@ -1,18 +1,28 @@
 use blake3::Hash;
-use glob::{GlobError, PatternError, glob};
-use std::process::{Output, Stdio};
+use glob::{GlobError, Pattern, PatternError, glob};
+use std::process::{ExitStatus, Output, Stdio};
 use std::{
     collections::HashSet,
+    env,
     ffi::OsString,
-    fmt, fs,
-    io::Error as IoError,
-    path::{Component, Path, PathBuf},
+    fmt,
+    fs::{self, File},
+    io::{Error as IoError, ErrorKind},
+    path::{Component, MAIN_SEPARATOR, Path, PathBuf},
     sync::OnceLock,
     time::Duration,
 };
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::process::Command as TokioCommand;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::dotenv;
+use crate::jobserver;
+use crate::logging::{self, OutputStream};
+use crate::mask;
+use crate::process;
+use crate::spool::{Captured, CommandOutput};
 
 #[derive(Debug)]
 pub enum FileError {
@@ -24,7 +34,8 @@ pub enum FileError {
 #[derive(Debug)]
 pub enum CommandError {
     Io(IoError),
-    Timeout,
+    /// With what the command wrote before it was stopped, when known.
+    Timeout(Option<Box<CommandOutput>>),
 }
 
 impl fmt::Display for FileError {
@@ -69,7 +80,7 @@ impl fmt::Display for CommandError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CommandError::Io(e) => write!(f, "Command execution error: {}", e),
-            CommandError::Timeout => write!(f, "Command timed out"),
+            CommandError::Timeout(_) => write!(f, "Command timed out"),
         }
     }
 }
@@ -78,11 +89,35 @@ impl std::error::Error for CommandError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             CommandError::Io(e) => Some(e),
-            CommandError::Timeout => None,
+            CommandError::Timeout(_) => None,
         }
     }
 }
 
+/// Parses a size such as `512`, `64KB`, `16MB` or `1GB`, where a kilobyte is
+/// 1024 bytes.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let digits = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits);
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        other => return Err(format!("unknown unit '{}', use B, KB, MB or GB", other)),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| "expected a number followed by a unit, e.g. '16MB'".to_string())?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| "size is too large".to_string())
+}
+
 pub fn parse_timeout(timeout_str: Option<&str>, default_timeout: Option<&str>) -> Option<Duration> {
     let timeout_to_parse = timeout_str.or(default_timeout)?;
 
@@ -93,11 +128,10 @@ pub fn parse_timeout(timeout_str: Option<&str>, default_timeout: Option<&str>) -
     match timeout_to_parse.parse::<humantime::Duration>() {
         Ok(duration) => Some(duration.into()),
         Err(e) => {
-            eprintln!(
-                "Warning: Invalid timeout format '{}': {}",
+            logging::warn(format!(
+                "Invalid timeout format '{}': {}. Use duration format like '5m', '30s', '1h30m'",
                 timeout_to_parse, e
-            );
-            eprintln!("Use duration format like '5m', '30s', '1h30m'");
+            ));
             None
         }
     }
@@ -107,6 +141,8 @@ pub fn expand_globs(paths: &[PathBuf]) -> Result<Vec<PathBuf>, FileError> {
     expand_globs_impl(paths, GlobExpandMode::FilesOnly)
 }
 
+/// Expands paths that may not exist yet, such as outputs, to the files and
+/// directories that do, without warning about the rest.
 pub fn expand_globs_any(paths: &[PathBuf]) -> Result<Vec<PathBuf>, FileError> {
     expand_globs_impl(paths, GlobExpandMode::AnyExisting)
 }
@@ -118,12 +154,17 @@ enum GlobExpandMode {
 }
 
 fn expand_globs_impl(paths: &[PathBuf], mode: GlobExpandMode) -> Result<Vec<PathBuf>, FileError> {
-    let mut result = Vec::new();
+    let mut result: Vec<PathBuf> = Vec::new();
     let mut seen = HashSet::new();
 
     for path in paths {
-        let path_str = path.to_string_lossy();
+        if let Some(excluded) = exclusion(path) {
+            result.retain(|p| !path_matches(&excluded, &strip_cur_dir(p)));
+            seen = result.iter().cloned().collect();
+            continue;
+        }
 
+        let path_str = path.to_string_lossy();
         if is_glob_pattern(&path_str) {
             let expanded_paths = expand_single_glob(&path_str)?;
             for expanded_path in expanded_paths {
@@ -135,18 +176,139 @@ fn expand_globs_impl(paths: &[PathBuf], mode: GlobExpandMode) -> Result<Vec<Path
                     result.push(expanded_path);
                 }
             }
-        } else {
-            add_if_exists(path, &mut result, &mut seen);
+        } else if path.is_dir() && matches!(mode, GlobExpandMode::FilesOnly) {
+            for file in files_in_dir(path) {
+                if seen.insert(file.clone()) {
+                    result.push(file);
+                }
+            }
+        } else if path.exists() {
+            if seen.insert(path.to_path_buf()) {
+                result.push(path.to_path_buf());
+            }
+        } else if let GlobExpandMode::FilesOnly = mode {
+            logging::warn(format!("Input file '{}' does not exist", path.display()));
         }
     }
 
     Ok(result)
 }
 
-fn is_glob_pattern(path: &str) -> bool {
+/// Every file below `dir`, in sorted order. Symbolic links to directories are
+/// not followed.
+pub fn files_in_dir(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let entries = match fs::read_dir(&current) {
+            Ok(entries) => entries,
+            Err(e) => {
+                logging::warn(format!(
+                    "Could not read directory '{}': {}",
+                    current.display(),
+                    e
+                ));
+                continue;
+            }
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => pending.push(path),
+                Ok(_) if path.is_file() => files.push(path),
+                _ => {}
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+pub fn is_glob_pattern(path: &str) -> bool {
     path.contains('*') || path.contains('?') || path.contains('[')
 }
 
+/// Whether `file` matches an input or output glob, is the path itself, or
+/// lies inside it when it names a directory.
+pub fn path_matches(pattern: &Path, file: &Path) -> bool {
+    let pattern = strip_cur_dir(pattern);
+    let pattern_str = pattern.to_string_lossy();
+
+    if is_glob_pattern(&pattern_str) {
+        return Pattern::new(&pattern_str)
+            .map(|pattern| pattern.matches_path(file))
+            .unwrap_or(false);
+    }
+    file.starts_with(&pattern)
+}
+
+/// The pattern of a `!pattern` entry in `inputs` or `outputs`, which removes
+/// what earlier entries matched.
+pub fn exclusion(path: &Path) -> Option<PathBuf> {
+    path.to_str()?.strip_prefix('!').map(PathBuf::from)
+}
+
+/// The file of an `@file` entry in `inputs` or `outputs`, which lists more
+/// entries, one per line.
+pub fn path_list(path: &Path) -> Option<PathBuf> {
+    path.to_str()?.strip_prefix('@').map(PathBuf::from)
+}
+
+/// Whether `file` is selected by a list of inputs or outputs: matched by an
+/// entry and not excluded by a later `!` entry, unless matched again after it.
+pub fn patterns_match(patterns: &[PathBuf], file: &Path) -> bool {
+    patterns
+        .iter()
+        .fold(false, |matched, pattern| match exclusion(pattern) {
+            Some(excluded) => matched && !path_matches(&excluded, file),
+            None => matched || path_matches(pattern, file),
+        })
+}
+
+/// Replaces a leading `~` with the home directory, as the shell would.
+pub fn expand_home(path: &Path) -> PathBuf {
+    let mut components = path.components();
+    match (components.next(), home_dir()) {
+        (Some(Component::Normal(first)), Some(home)) if first == "~" => {
+            home.join(components.as_path())
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Expands `~` in `path` and makes it relative to `dir` unless it is absolute.
+pub fn resolve_path(path: &Path, dir: &Path) -> PathBuf {
+    dir.join(expand_home(path))
+}
+
+/// The host and port of `url`, without the scheme, path or any `user:pass@`
+/// credentials, for messages that must not reveal the rest of the URL.
+pub fn url_host(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or(without_scheme);
+    authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host)
+}
+
+pub fn strip_cur_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
 fn expand_single_glob(pattern: &str) -> Result<Vec<PathBuf>, FileError> {
     let glob_paths = glob(pattern)?;
     glob_paths
@@ -154,27 +316,21 @@ fn expand_single_glob(pattern: &str) -> Result<Vec<PathBuf>, FileError> {
         .map_err(FileError::from)
 }
 
-fn add_if_exists(path: &Path, result: &mut Vec<PathBuf>, seen: &mut HashSet<PathBuf>) {
-    if path.exists() && seen.insert(path.to_path_buf()) {
-        result.push(path.to_path_buf());
-    } else if !path.exists() {
-        eprintln!("Warning: Input file '{}' does not exist", path.display());
-    }
-}
-
-pub fn hash_files(inputs: Vec<PathBuf>) -> Result<Hash, FileError> {
+/// Hashes the contents and paths of `inputs`. Paths are hashed relative to
+/// `root`, so the hash does not depend on the platform or on the directory
+/// compi runs from.
+pub fn hash_files(inputs: Vec<PathBuf>, root: &Path) -> Result<Hash, FileError> {
     let expanded_files = expand_globs(&inputs)?;
 
     if expanded_files.is_empty() {
         return Ok(blake3::hash(b""));
     }
 
+    let cwd = env::current_dir().unwrap_or_default();
+    let root = normalize_path_lexical(&cwd.join(root));
     let mut sorted_files: Vec<(String, PathBuf)> = expanded_files
         .into_iter()
-        .map(|p| {
-            let key = normalize_path_lexical(&p).to_string_lossy().to_string();
-            (key, p)
-        })
+        .map(|p| (hash_key(&cwd.join(&p), &root), p))
         .collect();
     sorted_files.sort_by(|(ak, _), (bk, _)| ak.cmp(bk));
 
@@ -190,11 +346,11 @@ pub fn hash_files(inputs: Vec<PathBuf>) -> Result<Hash, FileError> {
                 hashes.push(blake3::hash(&combined_bytes));
             }
             Err(e) => {
-                eprintln!(
-                    "Warning: Could not read file '{}': {}",
+                logging::warn(format!(
+                    "Could not read file '{}': {}",
                     file_path.display(),
                     e
-                );
+                ));
             }
         }
     }
@@ -211,151 +367,286 @@ pub fn hash_files(inputs: Vec<PathBuf>) -> Result<Hash, FileError> {
     Ok(blake3::hash(&combined_hash_data))
 }
 
+/// `path` relative to `root` when it is inside it, with `/` separators. On
+/// Windows and macOS, whose file systems are case-insensitive, it is also
+/// lowercased, so that a renamed `Foo.rs` and `foo.rs` are the same file.
+fn hash_key(path: &Path, root: &Path) -> String {
+    let path = normalize_path_lexical(path);
+    let key = path.strip_prefix(root).unwrap_or(&path).to_string_lossy();
+    let key = if MAIN_SEPARATOR == '\\' {
+        key.replace('\\', "/")
+    } else {
+        key.into_owned()
+    };
+    if cfg!(any(windows, target_os = "macos")) {
+        key.to_lowercase()
+    } else {
+        key
+    }
+}
+
+/// How long the output of a timed-out command is waited for once it stopped.
+const PARTIAL_OUTPUT_WAIT: Duration = Duration::from_secs(1);
+
+/// Runs `command` in the shell, with `stdin` as its input if given, and its
+/// stdout going to `stdout` instead of being collected if given. On timeout
+/// the command's process group is asked to exit and killed once
+/// `grace_period` has passed.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_command_with_timeout(
+    task_id: &str,
     command: &str,
     timeout: Option<Duration>,
+    grace_period: Duration,
+    stdin: Option<File>,
+    stdout: Option<File>,
     stream_output: bool,
-) -> Result<std::process::Output, CommandError> {
-    let mut cmd = if cfg!(target_os = "windows") {
-        let mut c = TokioCommand::new("cmd");
-        c.args(["/C", command]);
-        c
-    } else {
-        let mut c = TokioCommand::new("sh");
-        c.args(["-c", command]);
-        c
+    env: &[(String, String)],
+    dir: Option<&Path>,
+    wrapper: Option<&str>,
+) -> Result<CommandOutput, CommandError> {
+    let mut argv = match wrapper {
+        Some(wrapper) => shell_words::split(wrapper)
+            .map_err(|e| CommandError::Io(IoError::new(ErrorKind::InvalidInput, e)))?,
+        None => Vec::new(),
     };
+    if cfg!(target_os = "windows") {
+        argv.extend(["cmd".to_string(), "/C".to_string()]);
+    } else {
+        argv.extend(["sh".to_string(), "-c".to_string()]);
+    }
+    argv.push(command.to_string());
 
-    cmd.stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .stdin(Stdio::null());
+    let mut cmd = TokioCommand::new(&argv[0]);
+    cmd.args(&argv[1..]);
+
+    cmd.stdout(match stdout {
+        Some(file) => Stdio::from(file),
+        None => Stdio::piped(),
+    })
+    .stderr(Stdio::piped())
+    .stdin(match stdin {
+        Some(file) => Stdio::from(file),
+        None => Stdio::null(),
+    })
+    .envs(dotenv::vars().iter().map(|(key, value)| (key, value)))
+    .envs(env.iter().map(|(key, value)| (key, value)));
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    process::isolate(&mut cmd);
+    jobserver::configure(&mut cmd);
 
     let mut child = cmd.spawn().map_err(CommandError::Io)?;
+    let group = process::Group::register(&child, grace_period);
 
     let mut stdout_pipe = child.stdout.take();
     let mut stderr_pipe = child.stderr.take();
 
-    let stdout_handle = tokio::spawn(async move {
-        let mut collected: Vec<u8> = Vec::new();
-        if let Some(mut pipe) = stdout_pipe.take() {
-            let mut out = tokio::io::stdout();
-            let mut buf = [0u8; 8192];
-            loop {
-                let n = pipe.read(&mut buf).await.map_err(CommandError::Io)?;
-                if n == 0 {
-                    break;
-                }
-                collected.extend_from_slice(&buf[..n]);
-                if stream_output {
-                    out.write_all(&buf[..n]).await.map_err(CommandError::Io)?;
-                }
-            }
-            if stream_output {
-                out.flush().await.map_err(CommandError::Io)?;
-            }
-        }
-        Ok::<Vec<u8>, CommandError>(collected)
-    });
-
-    let stderr_handle = tokio::spawn(async move {
-        let mut collected: Vec<u8> = Vec::new();
-        if let Some(mut pipe) = stderr_pipe.take() {
-            let mut err = tokio::io::stderr();
-            let mut buf = [0u8; 8192];
-            loop {
-                let n = pipe.read(&mut buf).await.map_err(CommandError::Io)?;
-                if n == 0 {
-                    break;
-                }
-                collected.extend_from_slice(&buf[..n]);
-                if stream_output {
-                    err.write_all(&buf[..n]).await.map_err(CommandError::Io)?;
-                }
-            }
-            if stream_output {
-                err.flush().await.map_err(CommandError::Io)?;
-            }
-        }
-        Ok::<Vec<u8>, CommandError>(collected)
-    });
+    let stdout_handle = tokio::spawn(read_pipe(
+        stdout_pipe.take(),
+        task_id.to_string(),
+        OutputStream::Stdout,
+        stream_output,
+    ));
+    let stderr_handle = tokio::spawn(read_pipe(
+        stderr_pipe.take(),
+        task_id.to_string(),
+        OutputStream::Stderr,
+        stream_output,
+    ));
 
     let status = match timeout {
         Some(duration) => {
             tokio::select! {
                 result = child.wait() => result.map_err(CommandError::Io)?,
                 _ = tokio::time::sleep(duration) => {
-                    if let Err(kill_err) = child.kill().await {
-                        eprintln!("Warning: Failed to kill timed-out process: {}", kill_err);
+                    match &group {
+                        Some(group) => group.stop(&mut child, grace_period).await,
+                        None => {
+                            let _ = child.wait().await;
+                        }
                     }
-                    let _ = child.wait().await;
-                    return Err(CommandError::Timeout);
+                    // A process outside the group may still hold the pipes
+                    // open, so the output is only waited for briefly.
+                    let status = child.wait().await.map_err(CommandError::Io)?;
+                    let output = tokio::time::timeout(PARTIAL_OUTPUT_WAIT, async {
+                        Ok::<_, CommandError>(CommandOutput {
+                            status,
+                            stdout: collected(stdout_handle).await?,
+                            stderr: collected(stderr_handle).await?,
+                        })
+                    })
+                    .await;
+                    return Err(CommandError::Timeout(
+                        output.ok().and_then(Result::ok).map(Box::new),
+                    ));
                 }
             }
         }
         None => child.wait().await.map_err(CommandError::Io)?,
     };
 
-    let stdout = match stdout_handle.await {
-        Ok(Ok(bytes)) => bytes,
-        Ok(Err(e)) => return Err(e),
-        Err(e) => return Err(CommandError::Io(IoError::other(e))),
-    };
+    let stdout = collected(stdout_handle).await?;
+    let stderr = collected(stderr_handle).await?;
 
-    let stderr = match stderr_handle.await {
-        Ok(Ok(bytes)) => bytes,
-        Ok(Err(e)) => return Err(e),
-        Err(e) => return Err(CommandError::Io(IoError::other(e))),
-    };
-
-    Ok(Output {
+    Ok(CommandOutput {
         status,
         stdout,
         stderr,
     })
 }
 
+/// The result of a [`read_pipe`] task.
+async fn collected(
+    handle: JoinHandle<Result<Captured, CommandError>>,
+) -> Result<Captured, CommandError> {
+    match handle.await {
+        Ok(result) => result,
+        Err(e) => Err(CommandError::Io(IoError::other(e))),
+    }
+}
+
+/// Collects everything a child writes to one pipe, forwarding complete lines
+/// to the terminal as they arrive when streaming. Beyond the memory limit the
+/// output is spooled to a temporary file. Output is kept as written, secrets
+/// are only masked where it is shown.
+async fn read_pipe<R: AsyncRead + Unpin>(
+    pipe: Option<R>,
+    task_id: String,
+    stream: OutputStream,
+    stream_output: bool,
+) -> Result<Captured, CommandError> {
+    let mut collected = Captured::default();
+    let Some(mut pipe) = pipe else {
+        return Ok(collected);
+    };
+
+    let mut buf = [0u8; 8192];
+    let mut lines = mask::Lines::default();
+    loop {
+        let n = pipe.read(&mut buf).await.map_err(CommandError::Io)?;
+        if n == 0 {
+            break;
+        }
+
+        collected.push(&buf[..n]).map_err(CommandError::Io)?;
+        if let Some(lines) = lines.push(&buf[..n]) {
+            emit_lines(&task_id, stream, &lines, stream_output)?;
+        }
+    }
+
+    if let Some(rest) = lines.finish() {
+        emit_lines(&task_id, stream, &rest, stream_output)?;
+    }
+
+    Ok(collected)
+}
+
+/// Shows or records output produced outside of a child process the same way
+/// as streamed command output.
+pub fn replay_output(
+    task_id: &str,
+    output: Output,
+    stream_output: bool,
+) -> Result<CommandOutput, CommandError> {
+    for (stream, data) in [
+        (OutputStream::Stdout, &output.stdout),
+        (OutputStream::Stderr, &output.stderr),
+    ] {
+        if !data.is_empty() {
+            emit_lines(task_id, stream, data, stream_output)?;
+        }
+    }
+
+    Ok(CommandOutput {
+        status: output.status,
+        stdout: output.stdout.into(),
+        stderr: output.stderr.into(),
+    })
+}
+
+#[cfg(unix)]
+pub fn exit_status_from_code(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw((code & 0xff) << 8)
+}
+
+#[cfg(windows)]
+pub fn exit_status_from_code(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code as u32)
+}
+
+fn emit_lines(
+    task_id: &str,
+    stream: OutputStream,
+    lines: &[u8],
+    stream_output: bool,
+) -> Result<(), CommandError> {
+    if stream_output {
+        logging::task_output_chunk(task_id, stream, lines).map_err(CommandError::Io)
+    } else {
+        logging::record_task_output(task_id, stream, lines);
+        Ok(())
+    }
+}
+
 static OUTPUT_PRINT_LOCK: OnceLock<Mutex<()>> = OnceLock::new();
 
 pub fn output_print_lock() -> &'static Mutex<()> {
     OUTPUT_PRINT_LOCK.get_or_init(|| Mutex::new(()))
 }
 
-pub fn cleanup_outputs(outputs: &[PathBuf], verbose: bool) -> Result<(), FileError> {
+/// Removes the outputs that exist. A directory output is removed whole,
+/// unless a `!` entry excludes something in it, in which case only its other
+/// files are removed, along with the directories they leave empty.
+pub fn cleanup_outputs(outputs: &[PathBuf]) -> Result<(), FileError> {
     if outputs.is_empty() {
         return Ok(());
     }
 
     let expanded_outputs = expand_globs_any(outputs)?;
+    let has_exclusions = outputs.iter().any(|output| exclusion(output).is_some());
 
     for output_path in &expanded_outputs {
-        if output_path.exists() {
+        if output_path.is_dir() && has_exclusions {
+            for file in files_in_dir(output_path)
+                .into_iter()
+                .filter(|file| patterns_match(outputs, &strip_cur_dir(file)))
+            {
+                remove_output(&file, fs::remove_file(&file));
+                for dir in file.ancestors().skip(1) {
+                    if fs::remove_dir(dir).is_err() || dir == output_path {
+                        break;
+                    }
+                }
+            }
+        } else if output_path.exists() {
             let result = if output_path.is_dir() {
                 fs::remove_dir_all(output_path)
             } else {
                 fs::remove_file(output_path)
             };
-
-            match result {
-                Ok(()) => {
-                    if verbose {
-                        println!("Removed: {}", output_path.display());
-                    }
-                }
-                Err(e) => {
-                    eprintln!(
-                        "Warning: Failed to remove '{}': {}",
-                        output_path.display(),
-                        e
-                    );
-                }
-            }
+            remove_output(output_path, result);
         }
     }
 
     Ok(())
 }
 
-fn normalize_path_lexical(path: &Path) -> PathBuf {
+fn remove_output(path: &Path, result: Result<(), IoError>) {
+    match result {
+        Ok(()) => {
+            logging::debug(format!("Removed: {}", path.display()));
+        }
+        Err(e) => {
+            logging::warn(format!("Failed to remove '{}': {}", path.display(), e));
+        }
+    }
+}
+
+pub fn normalize_path_lexical(path: &Path) -> PathBuf {
     let mut out = PathBuf::new();
     let mut stack: Vec<OsString> = Vec::new();
     let mut anchored = false;
@@ -0,0 +1,92 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::error::{CompiError, Result};
+use crate::task::Task;
+use crate::util::{patterns_match, strip_cur_dir};
+
+/// Variables describing the checkout, set when a config refers to them.
+pub const BUILTIN_VARIABLES: [&str; 3] = ["GIT_SHA", "GIT_BRANCH", "GIT_DIRTY"];
+
+/// Files changed since `since`, relative to the current directory. Includes
+/// uncommitted changes and untracked files that are not ignored.
+pub fn changed_files(since: &str) -> Result<Vec<PathBuf>> {
+    let here = Path::new(".");
+    let mut files = git(here, &["diff", "--name-only", "--relative", since, "--"])?;
+    files.extend(git(here, &["ls-files", "--others", "--exclude-standard"])?);
+    files.sort();
+    files.dedup();
+    Ok(files.into_iter().map(PathBuf::from).collect())
+}
+
+/// The value of one of [`BUILTIN_VARIABLES`] for the repository containing
+/// `dir`, or `None` outside of one. `GIT_BRANCH` is empty on a detached HEAD.
+pub fn variable(name: &str, dir: &Path) -> Option<String> {
+    let git = |args: &[&str]| git(dir, args).ok();
+    match name {
+        "GIT_SHA" => git(&["rev-parse", "HEAD"])?.into_iter().next(),
+        "GIT_BRANCH" => Some(
+            git(&["branch", "--show-current"])?
+                .into_iter()
+                .next()
+                .unwrap_or_default(),
+        ),
+        "GIT_DIRTY" => Some((!git(&["status", "--porcelain"])?.is_empty()).to_string()),
+        _ => None,
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<String>> {
+    let dir = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| CompiError::Task(format!("failed to run git: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(CompiError::Task(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Tasks with an input matching one of `changed`, plus every task that depends
+/// on them.
+pub fn affected_tasks(tasks: &[Task], changed: &[PathBuf]) -> HashSet<String> {
+    let changed: Vec<PathBuf> = changed.iter().map(|p| strip_cur_dir(p)).collect();
+    let mut affected: HashSet<String> = tasks
+        .iter()
+        .filter(|task| {
+            changed
+                .iter()
+                .any(|file| patterns_match(&task.inputs, file))
+        })
+        .map(|task| task.id.clone())
+        .collect();
+
+    let mut queue: VecDeque<String> = affected.iter().cloned().collect();
+    while let Some(task_id) = queue.pop_front() {
+        for dependent in tasks.iter().filter(|t| t.dependencies.contains(&task_id)) {
+            if affected.insert(dependent.id.clone()) {
+                queue.push_back(dependent.id.clone());
+            }
+        }
+    }
+    affected
+}
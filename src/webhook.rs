@@ -0,0 +1,199 @@
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
+
+use crate::error::{CompiError, Result};
+use crate::execution::{RunSummary, TaskResult, TaskStatus};
+use crate::logging;
+use crate::util::url_host;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// Every finished run, successful or not.
+    RunCompleted,
+    /// Runs where at least one task failed.
+    RunFailed,
+    /// Each failed or timed-out task.
+    TaskFailed,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default = "default_events")]
+    pub events: Vec<WebhookEvent>,
+    /// Request body with `{{name}}` placeholders; a JSON payload is sent when omitted.
+    pub template: Option<String>,
+}
+
+fn default_events() -> Vec<WebhookEvent> {
+    vec![WebhookEvent::RunCompleted]
+}
+
+pub fn notify(webhooks: &[Webhook], target: &str, results: &[TaskResult], summary: &RunSummary) {
+    if webhooks.is_empty() {
+        return;
+    }
+
+    let failed: Vec<&TaskResult> = results
+        .iter()
+        .filter(|r| matches!(r.status, TaskStatus::Failed | TaskStatus::TimedOut))
+        .collect();
+    let run_values = run_values(target, &failed, summary);
+
+    for webhook in webhooks {
+        for event in &webhook.events {
+            let deliveries: Vec<HashMap<&str, String>> = match event {
+                WebhookEvent::RunCompleted => vec![run_values.clone()],
+                WebhookEvent::RunFailed if !failed.is_empty() => vec![run_values.clone()],
+                WebhookEvent::RunFailed => Vec::new(),
+                WebhookEvent::TaskFailed => failed
+                    .iter()
+                    .map(|result| task_values(&run_values, result))
+                    .collect(),
+            };
+
+            for mut values in deliveries {
+                values.insert("event", event_name(*event).to_string());
+                if let Err(e) = deliver(webhook, &values) {
+                    logging::warn(e);
+                }
+            }
+        }
+    }
+}
+
+fn run_values(
+    target: &str,
+    failed: &[&TaskResult],
+    summary: &RunSummary,
+) -> HashMap<&'static str, String> {
+    let status = if failed.is_empty() {
+        "success"
+    } else {
+        "failed"
+    };
+    let failed_tasks: Vec<&str> = failed.iter().map(|r| r.task_id.as_str()).collect();
+
+    HashMap::from([
+        ("target", target.to_string()),
+        ("status", status.to_string()),
+        ("executed", summary.executed.to_string()),
+        ("skipped", summary.skipped.to_string()),
+        ("failed", summary.failed.to_string()),
+        ("failed_tasks", failed_tasks.join(", ")),
+        (
+            "duration",
+            format!("{:.2}s", summary.duration_ms as f64 / 1000.0),
+        ),
+    ])
+}
+
+fn task_values(
+    run_values: &HashMap<&'static str, String>,
+    result: &TaskResult,
+) -> HashMap<&'static str, String> {
+    let mut values = run_values.clone();
+    values.insert("task", result.task_id.clone());
+    values.insert(
+        "task_status",
+        match result.status {
+            TaskStatus::TimedOut => "timed_out",
+            _ => "failed",
+        }
+        .to_string(),
+    );
+    values.insert(
+        "exit_code",
+        result
+            .exit_code
+            .map(|code| code.to_string())
+            .unwrap_or_default(),
+    );
+    values.insert(
+        "task_duration",
+        format!("{:.2}s", result.duration.as_secs_f64()),
+    );
+    values
+}
+
+fn deliver(webhook: &Webhook, values: &HashMap<&str, String>) -> Result<()> {
+    let body = match &webhook.template {
+        Some(template) => render_template(template, values),
+        None => default_payload(values).to_string(),
+    };
+
+    let response = ureq::post(&webhook.url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body);
+
+    // Webhook URLs usually embed a secret token, so only the host is reported.
+    match response {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, _)) => Err(CompiError::Http(format!(
+            "webhook to {} responded with status {}",
+            url_host(&webhook.url),
+            code
+        ))),
+        Err(ureq::Error::Transport(transport)) => Err(CompiError::Http(format!(
+            "webhook to {} failed: {}",
+            url_host(&webhook.url),
+            transport.kind()
+        ))),
+    }
+}
+
+fn default_payload(values: &HashMap<&str, String>) -> Value {
+    let mut payload = serde_json::Map::new();
+    for (key, value) in values {
+        payload.insert(key.to_string(), json!(value));
+    }
+    Value::Object(payload)
+}
+
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{(\w+)\}\}").unwrap());
+
+/// Replaces `{{name}}` placeholders in one pass, so that a value containing
+/// `{{other}}` is not substituted again, escaping values so they are safe
+/// inside JSON strings. Unknown placeholders are kept as written.
+fn render_template(template: &str, values: &HashMap<&str, String>) -> String {
+    PLACEHOLDER
+        .replace_all(template, |caps: &Captures| match values.get(&caps[1]) {
+            Some(value) => {
+                let escaped = json!(value).to_string();
+                escaped[1..escaped.len() - 1].to_string()
+            }
+            None => caps[0].to_string(),
+        })
+        .to_string()
+}
+
+fn event_name(event: WebhookEvent) -> &'static str {
+    match event {
+        WebhookEvent::RunCompleted => "run_completed",
+        WebhookEvent::RunFailed => "run_failed",
+        WebhookEvent::TaskFailed => "task_failed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_template_substitutes_values_once() {
+        let values = HashMap::from([
+            ("target", "{{status}}".to_string()),
+            ("status", "failed \"x\"".to_string()),
+        ]);
+        assert_eq!(
+            render_template("{{target}} {{status}} {{unknown}}", &values),
+            r#"{{status}} failed \"x\" {{unknown}}"#
+        );
+    }
+}
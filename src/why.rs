@@ -0,0 +1,99 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::task::{Task, sort_topologically};
+use crate::util::{exclusion, path_matches, patterns_match, strip_cur_dir};
+
+/// How the tasks relate to one file, as shown by `compi why`.
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    /// The file, relative to the current directory like task paths are.
+    pub path: PathBuf,
+    /// Tasks that declare the file as an output.
+    pub producers: Vec<Match>,
+    /// Tasks that declare the file as an input.
+    pub consumers: Vec<Match>,
+    /// Tasks that rerun after a consumer does, in execution order, each with
+    /// the dependency it reruns for.
+    pub dependents: Vec<(String, String)>,
+}
+
+/// A task and the `inputs` or `outputs` entry that selects the file.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub task: String,
+    pub entry: PathBuf,
+}
+
+pub fn explain(tasks: &[Task], path: &Path) -> Explanation {
+    let path = relative_to_cwd(path);
+    let matches = |paths: fn(&Task) -> &[PathBuf]| -> Vec<Match> {
+        tasks
+            .iter()
+            .filter_map(|task| {
+                Some(Match {
+                    task: task.id.clone(),
+                    entry: selecting_entry(paths(task), &path)?.to_path_buf(),
+                })
+            })
+            .collect()
+    };
+    let producers = matches(|task| &task.outputs);
+    let consumers = matches(|task| &task.inputs);
+
+    let mut reached: HashMap<&str, &str> = HashMap::new();
+    let mut queue: VecDeque<&str> = consumers.iter().map(|m| m.task.as_str()).collect();
+    while let Some(task_id) = queue.pop_front() {
+        for dependent in tasks
+            .iter()
+            .filter(|t| t.dependencies.iter().any(|dep| dep == task_id))
+        {
+            if consumers.iter().all(|m| m.task != dependent.id)
+                && !reached.contains_key(dependent.id.as_str())
+            {
+                reached.insert(&dependent.id, task_id);
+                queue.push_back(&dependent.id);
+            }
+        }
+    }
+
+    let dependents = sort_topologically(tasks)
+        .into_iter()
+        .filter_map(|id| {
+            let via = reached.get(id.as_str())?.to_string();
+            Some((id, via))
+        })
+        .collect();
+
+    Explanation {
+        path,
+        producers,
+        consumers,
+        dependents,
+    }
+}
+
+/// The last entry of `patterns` that matches `file`, if the entries select it.
+fn selecting_entry<'a>(patterns: &'a [PathBuf], file: &Path) -> Option<&'a Path> {
+    if !patterns_match(patterns, file) {
+        return None;
+    }
+    patterns
+        .iter()
+        .rev()
+        .find(|pattern| exclusion(pattern).is_none() && path_matches(pattern, file))
+        .map(PathBuf::as_path)
+}
+
+/// Task paths are relative to the directory compi runs in, so absolute paths
+/// inside it are made relative too.
+fn relative_to_cwd(path: &Path) -> PathBuf {
+    let path = match env::current_dir() {
+        Ok(cwd) if path.is_absolute() => path.strip_prefix(&cwd).unwrap_or(path),
+        _ => path,
+    };
+    strip_cur_dir(path)
+}